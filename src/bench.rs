@@ -0,0 +1,323 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    config::Config,
+    frame::terminal_size,
+    lut::{darken_channel, ColorLut},
+    screensaver::Screensaver,
+    terminal::TerminalScreen,
+};
+use eyre::{Result, WrapErr};
+use std::{
+    hint::black_box,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+use termwiz::{
+    caps::Capabilities,
+    color::SrgbaTuple,
+    input::InputEvent,
+    render::{terminfo::TerminfoRenderer, RenderTty},
+    surface::Change,
+    terminal::{ScreenSize, Terminal, TerminalWaker},
+};
+
+/// Size of the simulated screen when the size of the terminal can't be detected.
+const FALLBACK_SIZE: (usize, usize) = (160, 48);
+
+/// Byte budget of the renderer limiting the bytes written per frame (see --max-bytes-per-frame).
+const BENCH_BYTE_BUDGET: usize = 4096;
+
+/// Way of copying the pipes to the terminal.
+#[derive(Copy, Clone, Debug)]
+enum Renderer {
+    /// Only the cells changed since the last frame are copied (the default).
+    ChangedCells,
+    /// The whole canvas is compared with the terminal in each frame.
+    FullCompare,
+    /// Changed cells, limited to a number of bytes per frame (see --max-bytes-per-frame).
+    ByteBudget(usize),
+    /// Changed cells as ASCII without colors or text attributes (see --safe-mode).
+    Plain,
+}
+
+impl Renderer {
+    /// Retrieve the name of the renderer with the options selecting it.
+    fn name(&self) -> String {
+        match self {
+            Self::ChangedCells => "changed cells (default)".to_string(),
+            Self::FullCompare => "full compare".to_string(),
+            Self::ByteBudget(b) => format!("byte budget (--max-bytes-per-frame {b})"),
+            Self::Plain => "plain (--safe-mode)".to_string(),
+        }
+    }
+}
+
+/// All renderers, in the order of the comparison table.
+const RENDERERS: [Renderer; 4] = [
+    Renderer::ChangedCells,
+    Renderer::FullCompare,
+    Renderer::ByteBudget(BENCH_BYTE_BUDGET),
+    Renderer::Plain,
+];
+
+/// Way of computing the colors of the darkened layers (see --layer-max-drawn-pieces).
+#[derive(Copy, Clone, Debug)]
+enum DarkenMath {
+    /// Floating-point math on each channel of each cell.
+    Scalar,
+    /// Tables precomputed for all 8-bit channel values (the default).
+    Lut,
+}
+
+impl DarkenMath {
+    /// Retrieve the name of the way.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Scalar => "darken, floating-point math",
+            Self::Lut => "darken, lookup tables (default)",
+        }
+    }
+}
+
+/// All ways of darkening, in the order of the comparison table.
+const DARKEN_MATHS: [DarkenMath; 2] = [DarkenMath::Scalar, DarkenMath::Lut];
+
+/// Output of a terminal which only counts the written bytes.
+struct ByteCounter {
+    /// Size of the terminal.
+    size: (usize, usize),
+    /// Number of bytes written so far.
+    bytes: usize,
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RenderTty for ByteCounter {
+    fn get_size_in_cells(&mut self) -> termwiz::Result<(usize, usize)> {
+        Ok(self.size)
+    }
+}
+
+/// Terminal which renders the changes with the escape sequences of the real one, but only counts
+/// the bytes instead of writing them.
+struct CaptureTerminal {
+    renderer: TerminfoRenderer,
+    out: ByteCounter,
+}
+
+impl CaptureTerminal {
+    /// Create a `CaptureTerminal` of specified size rendering for the terminal with the
+    /// capabilities.
+    fn new(caps: Capabilities, size: (usize, usize)) -> Self {
+        Self {
+            renderer: TerminfoRenderer::new(caps),
+            out: ByteCounter { size, bytes: 0 },
+        }
+    }
+}
+
+impl Terminal for CaptureTerminal {
+    fn set_raw_mode(&mut self) -> termwiz::Result<()> {
+        Ok(())
+    }
+
+    fn set_cooked_mode(&mut self) -> termwiz::Result<()> {
+        Ok(())
+    }
+
+    fn enter_alternate_screen(&mut self) -> termwiz::Result<()> {
+        Ok(())
+    }
+
+    fn exit_alternate_screen(&mut self) -> termwiz::Result<()> {
+        Ok(())
+    }
+
+    fn get_screen_size(&mut self) -> termwiz::Result<ScreenSize> {
+        Ok(ScreenSize {
+            cols: self.out.size.0,
+            rows: self.out.size.1,
+            xpixel: 0,
+            ypixel: 0,
+        })
+    }
+
+    fn set_screen_size(&mut self, size: ScreenSize) -> termwiz::Result<()> {
+        self.out.size = (size.cols, size.rows);
+
+        Ok(())
+    }
+
+    fn render(&mut self, changes: &[Change]) -> termwiz::Result<()> {
+        self.renderer.render_to(changes, &mut self.out)
+    }
+
+    fn flush(&mut self) -> termwiz::Result<()> {
+        Ok(())
+    }
+
+    fn poll_input(&mut self, _wait: Option<Duration>) -> termwiz::Result<Option<InputEvent>> {
+        Ok(None)
+    }
+
+    fn waker(&self) -> TerminalWaker {
+        // Nothing waits for the input of the benchmark
+        unreachable!("the capture terminal has no input")
+    }
+}
+
+/// Result of running the benchmark with a renderer.
+struct Measurement {
+    /// Average time to copy a frame to the terminal and render it.
+    frame_time: Duration,
+    /// Average number of bytes written per frame.
+    bytes_per_frame: usize,
+}
+
+/// Run the seeded simulation for the number of steps and render each frame with the renderer.
+fn measure(
+    renderer: Renderer,
+    cfg: &Config,
+    caps: &Capabilities,
+    steps: u64,
+    size: (usize, usize),
+) -> Result<Measurement> {
+    let mut cfg = cfg.clone();
+
+    if let Renderer::Plain = renderer {
+        cfg.apply_safe_mode();
+    }
+
+    let mut app = Screensaver::headless(size, cfg)?;
+    let mut term_scr = TerminalScreen::new(CaptureTerminal::new(caps.clone(), size))?;
+
+    match renderer {
+        Renderer::ByteBudget(b) => term_scr.set_byte_budget(b),
+        Renderer::Plain => term_scr.set_plain(),
+        Renderer::ChangedCells | Renderer::FullCompare => {}
+    }
+
+    let mut elapsed = Duration::ZERO;
+
+    for _ in 0..steps {
+        app.simulate(1);
+
+        let start = Instant::now();
+
+        match renderer {
+            Renderer::FullCompare => term_scr.copy_canvas(app.canvas_mut()),
+            _ => term_scr.copy_canvas_changes(app.canvas_mut(), false),
+        }
+
+        term_scr.render()?;
+        elapsed += start.elapsed();
+    }
+
+    let frames = steps.max(1);
+    let bytes = term_scr.terminal().terminal().out.bytes;
+
+    Ok(Measurement {
+        frame_time: elapsed / frames as u32,
+        bytes_per_frame: bytes / frames as usize,
+    })
+}
+
+/// Darken the colors of a whole screen of specified size once per frame with the math and
+/// retrieve the average time needed for a frame.
+fn measure_darken(math: DarkenMath, amount: f32, frames: u64, size: (usize, usize)) -> Duration {
+    let min = SrgbaTuple(0.0, 0.0, 0.0, 1.0);
+    // Colors spread over the whole range of each channel
+    let colors: Vec<SrgbaTuple> = (0..size.0 * size.1)
+        .map(|i| {
+            let channel = |k: usize| (i * k % 256) as f32 / 255.0;
+
+            SrgbaTuple(channel(37), channel(91), channel(173), 1.0)
+        })
+        .collect();
+
+    let start = Instant::now();
+
+    for _ in 0..frames {
+        match math {
+            DarkenMath::Scalar => {
+                for c in &colors {
+                    black_box(SrgbaTuple(
+                        darken_channel(c.0, amount, min.0),
+                        darken_channel(c.1, amount, min.1),
+                        darken_channel(c.2, amount, min.2),
+                        c.3,
+                    ));
+                }
+            }
+            DarkenMath::Lut => {
+                // The tables are built for each darkening, as the darken pass does
+                let lut = ColorLut::darken(amount, min);
+
+                for c in &colors {
+                    black_box(lut.apply(*c));
+                }
+            }
+        }
+    }
+
+    start.elapsed() / frames.max(1) as u32
+}
+
+/// Run the same seeded simulation with each renderer and print a table comparing the time and
+/// bytes needed to draw a frame, followed by a table comparing the ways of darkening the layers.
+/// Nothing is drawn on the terminal.
+pub fn run(mut cfg: Config, steps: u64, size: Option<(usize, usize)>) -> Result<()> {
+    let size = size.unwrap_or_else(|| terminal_size().unwrap_or(FALLBACK_SIZE));
+    let caps = Capabilities::new_from_env().wrap_err("cannot read terminal capabilities")?;
+
+    // The colors are drawn as they would be on the terminal
+    for w in cfg.adapt_to_colors(caps.color_level()) {
+        eprintln!("warning: {w}");
+    }
+
+    // Each renderer draws the same pipes
+    let seed = cfg.seed.unwrap_or(0);
+    cfg.seed = Some(seed);
+
+    println!("{steps} frames of {}x{}, seed {seed}", size.0, size.1);
+    println!();
+    println!(
+        "{:<42} {:>12} {:>12}",
+        "renderer", "frame time", "bytes/frame"
+    );
+
+    for r in RENDERERS {
+        let m = measure(r, &cfg, &caps, steps, size)?;
+
+        println!(
+            "{:<42} {:>9} µs {:>12}",
+            r.name(),
+            m.frame_time.as_micros(),
+            m.bytes_per_frame
+        );
+    }
+
+    println!();
+    println!("{:<42} {:>12}", "color math", "frame time");
+
+    for m in DARKEN_MATHS {
+        let frame_time = measure_darken(m, cfg.darken_factor, steps, size);
+
+        println!("{:<42} {:>9} µs", m.name(), frame_time.as_micros());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::paths::config_dir;
+use eyre::{bail, eyre, Result, WrapErr};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Seed and theme of a run, saved to replay it later (see --from-bookmark).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub seed: u64,
+    pub theme: Option<String>,
+}
+
+impl Bookmark {
+    /// Parse a line of the bookmarks file: the seed, optionally followed by the theme name.
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let seed = words.next()?.parse().ok()?;
+        let theme = words.next().map(str::to_string);
+
+        Some(Self { seed, theme })
+    }
+
+    /// Append the bookmark to the bookmarks file.
+    pub fn save(&self) -> Result<()> {
+        let path = bookmarks_file()?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).wrap_err_with(|| format!("cannot create {}", dir.display()))?;
+        }
+
+        let mut line = self.seed.to_string();
+
+        if let Some(t) = &self.theme {
+            line = format!("{line} {t}");
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| writeln!(f, "{line}"))
+            .wrap_err_with(|| format!("cannot write {}", path.display()))
+    }
+
+    /// Load the bookmark with the number (starting from 1).
+    pub fn load(n: usize) -> Result<Self> {
+        let path = bookmarks_file()?;
+        let bookmarks = load_all(&path);
+
+        if bookmarks.is_empty() {
+            bail!("no bookmarks in {} (press `b` to add one)", path.display());
+        }
+
+        n.checked_sub(1)
+            .and_then(|i| bookmarks.get(i))
+            .cloned()
+            .ok_or_else(|| eyre!("no bookmark {n} (there are {})", bookmarks.len()))
+    }
+}
+
+/// Read all bookmarks from the file. Malformed lines are skipped.
+fn load_all(path: &Path) -> Vec<Bookmark> {
+    fs::read_to_string(path)
+        .map(|s| s.lines().filter_map(Bookmark::parse).collect())
+        .unwrap_or_default()
+}
+
+/// Retrieve the path to the bookmarks file.
+fn bookmarks_file() -> Result<PathBuf> {
+    config_dir()
+        .map(|d| d.join("bookmarks"))
+        .ok_or_else(|| eyre!("cannot find the config directory (is $HOME set?)"))
+}
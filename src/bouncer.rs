@@ -0,0 +1,101 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{canvas::Canvas, color::ColorPool, plane_2d::Point};
+use rand::{rngs::StdRng, Rng};
+use termwiz::cell::unicode_column_width;
+
+/// A sprite bouncing around the screen on top of the pipes, like the good old DVD logo.
+pub struct Bouncer {
+    /// Layer with the sprite.
+    canv: Canvas,
+    /// Text of the sprite.
+    text: String,
+    /// Colors used for the sprite.
+    pool: ColorPool,
+    /// Velocity of the sprite in cells per frame.
+    vel: (isize, isize),
+    /// Random number generator picking the position and the colors.
+    rng: StdRng,
+}
+
+impl Bouncer {
+    /// Create a `Bouncer` at a random position on the screen of specified size.
+    pub fn new(text: &str, pool: ColorPool, scr_size: (usize, usize), mut rng: StdRng) -> Self {
+        let width = unicode_column_width(text, None).max(1);
+
+        let mut s = Self {
+            canv: Canvas::new(Point { x: 0, y: 0 }, (width, 1)),
+            text: text.to_string(),
+            pool,
+            vel: (
+                if rng.gen() { 1 } else { -1 },
+                if rng.gen() { 1 } else { -1 },
+            ),
+            rng,
+        };
+
+        let max = s.max_pos(scr_size);
+
+        s.canv.pos = Point {
+            x: s.rng.gen_range(0..=max.x),
+            y: s.rng.gen_range(0..=max.y),
+        };
+        s.paint();
+
+        s
+    }
+
+    /// Move the sprite one step further, bouncing off the screen edges. The sprite changes its
+    /// color on each bounce.
+    pub fn update(&mut self, scr_size: (usize, usize)) {
+        let max = self.max_pos(scr_size);
+        let pos = &mut self.canv.pos;
+        let mut bounced = false;
+
+        pos.x += self.vel.0;
+        pos.y += self.vel.1;
+
+        if pos.x <= 0 || pos.x >= max.x {
+            self.vel.0 = -self.vel.0;
+            bounced = true;
+        }
+
+        if pos.y <= 0 || pos.y >= max.y {
+            self.vel.1 = -self.vel.1;
+            bounced = true;
+        }
+
+        pos.x = pos.x.clamp(0, max.x);
+        pos.y = pos.y.clamp(0, max.y);
+
+        if bounced {
+            self.paint();
+        }
+    }
+
+    /// Retrieve a reference to the sprite layer.
+    pub fn canvas(&self) -> &Canvas {
+        &self.canv
+    }
+
+    /// Draw the sprite text in a new random color.
+    fn paint(&mut self) {
+        self.canv.move_to(Point { x: 0, y: 0 });
+
+        if let Some(color) = self.pool.gen(&mut self.rng) {
+            self.canv.set_fg_color(color);
+        }
+
+        self.canv.put_str(&self.text);
+    }
+
+    /// The farthest position where the whole sprite still fits on the screen.
+    fn max_pos(&self, scr_size: (usize, usize)) -> Point {
+        Point {
+            x: scr_size.0.saturating_sub(self.canv.size().0) as isize,
+            y: scr_size.1.saturating_sub(self.canv.size().1) as isize,
+        }
+    }
+}
@@ -2,13 +2,42 @@
 //
 // This file is licensed under the MIT License (see LICENSE.md).
 
-use crate::plane_2d::Point;
+use crate::{
+    color::{blend, to_srgba},
+    plane_2d::Point,
+    postfx::{Cells, Pass},
+};
+use rand::Rng;
+use std::mem;
 use termwiz::{
-    cell::AttributeChange,
+    cell::{unicode_column_width, AttributeChange, Blink, Cell, CellAttributes, Intensity},
     color::{ColorAttribute, SrgbaTuple},
-    surface::{Change, Position, Surface},
+    surface::{Change, Position, SequenceNo, Surface},
 };
 
+/// Number of changes after which the change log of the cell buffer is dropped.
+const MAX_LOGGED_CHANGES: usize = 4096;
+
+/// Text attributes of cells.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TextStyle {
+    /// Bold text.
+    pub bold: bool,
+    /// Italic text.
+    pub italic: bool,
+    /// Blinking text.
+    pub blink: bool,
+}
+
+/// Cells of a canvas changed since it was last copied to the terminal.
+#[derive(Clone, Debug)]
+enum Damage {
+    /// Only these cells have changed.
+    Cells(Vec<Point>),
+    /// The whole canvas has changed.
+    All,
+}
+
 /// Drawing area of the terminal.
 pub struct Canvas {
     /// Cell buffer.
@@ -17,6 +46,15 @@ pub struct Canvas {
     size: (usize, usize),
     /// Position of the canvas.
     pub pos: Point,
+    /// Position of the cursor.
+    cursor: Point,
+    /// Cells changed since the canvas was last copied to the terminal.
+    damage: Damage,
+    /// Sequence number of the oldest change in the change log of the cell buffer.
+    log_start: SequenceNo,
+    /// Cells drawn since the last snapshot was taken, `None` if there is no snapshot or it's
+    /// outdated (e.g. the canvas has been cleared since).
+    drawn_since_snapshot: Option<Vec<Point>>,
 }
 
 impl Canvas {
@@ -24,105 +62,397 @@ impl Canvas {
     pub fn new(pos: Point, size: (usize, usize)) -> Self {
         let surface = Surface::new(size.0, size.1);
 
-        Self { surface, size, pos }
+        Self {
+            surface,
+            size,
+            pos,
+            cursor: Point { x: 0, y: 0 },
+            damage: Damage::All,
+            log_start: 0,
+            drawn_since_snapshot: None,
+        }
     }
 
     /// Resize canvas to specified size.
     pub fn resize(&mut self, size: (usize, usize)) {
         self.size = size;
         self.surface.resize(size.0, size.1);
+        self.drawn_since_snapshot = None;
+        self.invalidate();
     }
 
     /// Make the canvas blank.
     pub fn clear(&mut self) {
-        self.surface
-            .add_change(Change::ClearScreen(ColorAttribute::Default));
+        self.fill(ColorAttribute::Default);
     }
 
     /// Fill the canvas with specified color.
     pub fn fill(&mut self, c: ColorAttribute) {
-        self.surface.add_change(Change::ClearScreen(c));
+        self.add_change(Change::ClearScreen(c));
+        self.cursor = Point { x: 0, y: 0 };
+        self.drawn_since_snapshot = None;
+        self.invalidate();
     }
 
     /// Move the cursor to the 2D point.
     pub fn move_to(&mut self, p: Point) {
-        self.surface.add_change(Change::CursorPosition {
+        self.add_change(Change::CursorPosition {
             x: Position::Absolute(p.x as usize),
             y: Position::Absolute(p.y as usize),
         });
+        self.cursor = p;
+    }
+
+    /// Apply the change to the cell buffer.
+    fn add_change(&mut self, change: Change) {
+        self.surface.add_change(change);
+        self.compact_log();
+    }
+
+    /// Drop the changes recorded by the cell buffer once there are too many of them. Only the
+    /// cells are ever read, but the buffer keeps a log of all changes, which would otherwise grow
+    /// for as long as the program runs.
+    fn compact_log(&mut self) {
+        let seqno = self.surface.current_seqno();
+
+        if seqno - self.log_start >= MAX_LOGGED_CHANGES {
+            self.surface.flush_changes_older_than(seqno);
+            self.log_start = seqno;
+        }
+    }
+
+    /// Mark the whole canvas as changed, e.g. after the terminal has been cleared.
+    pub fn invalidate(&mut self) {
+        self.damage = Damage::All;
+    }
+
+    /// Mark the cells as changed again, so they are retrieved by the next call of
+    /// [`Self::take_changed_cells`].
+    pub fn defer_changes(&mut self, points: impl IntoIterator<Item = Point>) {
+        if let Damage::Cells(p) = &mut self.damage {
+            p.extend(points);
+        }
+    }
+
+    /// Retrieve the cells changed since the last call (with their positions), so only they need to
+    /// be copied to the terminal. Returns `None` if the whole canvas has changed.
+    pub fn take_changed_cells(&mut self) -> Option<Vec<(Point, Cell)>> {
+        let Damage::Cells(mut points) = mem::replace(&mut self.damage, Damage::Cells(vec![]))
+        else {
+            return None;
+        };
+
+        points.sort_by_key(|p| (p.y, p.x));
+        points.dedup();
+
+        let lines = self.surface.screen_cells();
+        let mut cells = vec![];
+
+        for p in points {
+            let (Ok(x), Ok(y)) = (usize::try_from(p.x), usize::try_from(p.y)) else {
+                continue;
+            };
+            let Some(line) = lines.get(y) else {
+                continue;
+            };
+
+            // Skip the right half of wide glyphs
+            if x > 0 && line.get(x - 1).is_some_and(|c| c.width() > 1) {
+                continue;
+            }
+
+            if let Some(cell) = line.get(x) {
+                cells.push((p, cell.clone()));
+            }
+        }
+
+        Some(cells)
     }
 
     /// Set the foreground color of new cells.
     pub fn set_fg_color(&mut self, c: ColorAttribute) {
-        self.surface
-            .add_change(Change::Attribute(AttributeChange::Foreground(c)));
+        self.add_change(Change::Attribute(AttributeChange::Foreground(c)));
     }
 
     /// Set the background color of new cells.
     pub fn set_bg_color(&mut self, c: ColorAttribute) {
-        self.surface
-            .add_change(Change::Attribute(AttributeChange::Background(c)));
+        self.add_change(Change::Attribute(AttributeChange::Background(c)));
+    }
+
+    /// Set the text attributes of new cells.
+    pub fn set_style(&mut self, style: TextStyle) {
+        let intensity = if style.bold {
+            Intensity::Bold
+        } else {
+            Intensity::Normal
+        };
+        let blink = if style.blink {
+            Blink::Slow
+        } else {
+            Blink::None
+        };
+
+        for change in [
+            AttributeChange::Intensity(intensity),
+            AttributeChange::Italic(style.italic),
+            AttributeChange::Blink(blink),
+        ] {
+            self.add_change(Change::Attribute(change));
+        }
     }
 
     /// Print string at the current position of the cursor.
     pub fn put_str(&mut self, s: impl AsRef<str>) {
-        self.surface
-            .add_change(Change::Text(String::from(s.as_ref())));
+        let width = unicode_column_width(s.as_ref(), None);
+
+        let cursor = self.cursor;
+        let drawn = (0..width as isize).map(|dx| Point {
+            x: cursor.x + dx,
+            y: cursor.y,
+        });
+
+        self.add_change(Change::Text(String::from(s.as_ref())));
+
+        if let Some(cells) = &mut self.drawn_since_snapshot {
+            cells.extend(drawn.clone());
+        }
+
+        if let Damage::Cells(cells) = &mut self.damage {
+            cells.extend(drawn);
+
+            // Copying the whole canvas is cheaper than copying this many cells
+            if cells.len() > self.size.0 * self.size.1 / 2 {
+                self.damage = Damage::All;
+            }
+        }
+
+        self.cursor.x += width as isize;
+    }
+
+    /// Retrieve a copy of the cell at the point.
+    pub fn cell(&mut self, p: Point) -> Option<Cell> {
+        let lines = self.surface.screen_cells();
+        let line = lines.get(usize::try_from(p.y).ok()?)?;
+
+        line.get(usize::try_from(p.x).ok()?).cloned()
+    }
+
+    /// Put the cell (with all its attributes) at the point.
+    pub fn put_cell(&mut self, p: Point, cell: &Cell) {
+        self.move_to(p);
+        self.add_change(Change::AllAttributes(cell.attrs().clone()));
+        self.put_str(cell.str());
+    }
+
+    /// Retrieve the contents of the canvas as lines of text with ANSI escape sequences for colors
+    /// and text attributes.
+    pub fn ansi_text(&mut self) -> String {
+        let mut out = String::new();
+
+        for line in self.surface.screen_cells() {
+            let mut attrs = None;
+            let mut skip = 0;
+
+            for cell in line.iter() {
+                // Wide glyphs cover the following cells
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+
+                skip = cell.width().saturating_sub(1);
+
+                let a = cell.attrs();
+
+                if attrs != Some(a) {
+                    let mut sgr = vec!["0".to_string()];
+
+                    if a.intensity() == Intensity::Bold {
+                        sgr.push("1".to_string());
+                    }
+
+                    if a.italic() {
+                        sgr.push("3".to_string());
+                    }
+
+                    if a.blink() != Blink::None {
+                        sgr.push("5".to_string());
+                    }
+
+                    sgr.extend(sgr_color(a.foreground(), 30));
+                    sgr.extend(sgr_color(a.background(), 40));
+
+                    out.push_str(&format!("\x1b[{}m", sgr.join(";")));
+                    attrs = Some(a);
+                }
+
+                out.push_str(cell.str());
+            }
+
+            out.push_str("\x1b[0m\n");
+        }
+
+        out
     }
 
     /// Makes all characters darker upto the minimal color. If the minimal color is lighter than
     /// character's color, the character will be lighten instead.
     pub fn darken(&mut self, amount: f32, min: SrgbaTuple) {
+        let mut cells = self.cells();
+
+        Pass::Darken { amount, min }.apply(&mut cells);
+        self.drawn_since_snapshot = None;
+        self.write_cells(cells, &[]);
+    }
+
+    /// Erase each character with the probability, leaving a blank cell with the background color.
+    pub fn dissolve(&mut self, prob: f64, bg: ColorAttribute, rng: &mut impl Rng) {
         let mut changes: Vec<Change> = vec![];
+        let mut attrs = CellAttributes::default();
+        attrs.set_background(bg);
 
         for (i, l) in self.surface.screen_cells().iter().enumerate() {
             for (j, cell) in l.iter().enumerate() {
-                if cell.str().trim_ascii().is_empty() {
+                if cell.str().trim_ascii().is_empty() || !rng.gen_bool(prob) {
                     continue;
                 }
 
-                let attrs = cell.attrs();
-                let mut fg = attrs.foreground();
+                changes.push(Change::CursorPosition {
+                    x: Position::Absolute(j),
+                    y: Position::Absolute(i),
+                });
+                changes.push(Change::AllAttributes(attrs.clone()));
+                changes.push(Change::Text(" ".to_string()));
+            }
+        }
 
-                let approach = |x: f32, a: f32, amount: f32| -> f32 {
-                    let mut x = x;
+        self.surface.add_changes(changes);
+        self.compact_log();
+        self.drawn_since_snapshot = None;
+        self.invalidate();
+    }
 
-                    if x > a {
-                        x *= amount;
-                        x = x.clamp(a, 1.0);
-                    } else if x < a {
-                        x *= 1.0 + amount;
-                        x = x.clamp(0.0, a);
-                    }
+    /// Retrieve a copy of all cells.
+    pub fn cells(&mut self) -> Cells {
+        self.surface
+            .screen_cells()
+            .iter()
+            .map(|l| l.to_vec())
+            .collect()
+    }
+
+    /// Put the cells which differ from the current ones, except those at the skipped points
+    /// (sorted by row, then column).
+    fn write_cells(&mut self, cells: Cells, skip: &[Point]) {
+        let current = self.cells();
+        let mut changed = vec![];
+
+        for (y, (line, cur_line)) in cells.iter().zip(&current).enumerate() {
+            for (x, (cell, cur)) in line.iter().zip(cur_line).enumerate() {
+                // The right half of wide glyphs is put with the left one
+                if cell == cur || (x > 0 && line[x - 1].width() > 1) {
+                    continue;
+                }
 
-                    x
+                let p = Point {
+                    x: x as isize,
+                    y: y as isize,
                 };
 
-                fg = match fg {
-                    ColorAttribute::TrueColorWithDefaultFallback(mut cell_color) => {
-                        cell_color.0 = approach(cell_color.0, min.0, amount);
-                        cell_color.1 = approach(cell_color.1, min.1, amount);
-                        cell_color.2 = approach(cell_color.2, min.2, amount);
+                if skip
+                    .binary_search_by_key(&(p.y, p.x), |s| (s.y, s.x))
+                    .is_err()
+                {
+                    changed.push((p, cell));
+                }
+            }
+        }
 
-                        ColorAttribute::TrueColorWithDefaultFallback(cell_color)
-                    }
-                    _ => fg,
+        for (p, cell) in changed {
+            self.put_cell(p, cell);
+        }
+    }
+
+    /// Retrieve a copy of all cells, to be processed elsewhere and merged back later (see
+    /// `merge_snapshot`). The cells drawn from now on are tracked.
+    pub fn snapshot(&mut self) -> Cells {
+        self.drawn_since_snapshot = Some(vec![]);
+
+        self.cells()
+    }
+
+    /// Merge the processed snapshot back, except for the cells drawn since it was taken. The
+    /// snapshot is discarded if the canvas has been cleared, resized or processed as a whole in
+    /// the meantime.
+    pub fn merge_snapshot(&mut self, cells: Cells) {
+        let Some(mut drawn) = self.drawn_since_snapshot.take() else {
+            return;
+        };
+
+        drawn.sort_by_key(|p| (p.y, p.x));
+        drawn.dedup();
+
+        self.write_cells(cells, &drawn);
+    }
+
+    /// Composite the canvas over the canvas beneath with the opacity (0.0 - 1.0) and retrieve the
+    /// result. The background of the canvas is blended with the colors beneath; where the canvas
+    /// is blank, the characters beneath show through, tinted by its background. Colors which
+    /// aren't set are taken to be `default`.
+    pub fn composite_over(
+        &self,
+        beneath: &Canvas,
+        opacity: f32,
+        default: (SrgbaTuple, SrgbaTuple),
+    ) -> Canvas {
+        let (default_fg, default_bg) = default;
+        let rgb = |c: ColorAttribute, default: SrgbaTuple| {
+            let SrgbaTuple(r, g, b, _) = to_srgba(c).unwrap_or(default);
+
+            SrgbaTuple(r, g, b, opacity)
+        };
+
+        let mut out = Canvas::new(self.pos, self.size);
+        let lines = beneath.surface.screen_lines();
+
+        for (y, line) in self.surface.screen_lines().iter().enumerate() {
+            for cell in line.visible_cells() {
+                let p = Point {
+                    x: cell.cell_index() as isize,
+                    y: y as isize,
                 };
+                let under = usize::try_from(self.pos.y + p.y - beneath.pos.y)
+                    .ok()
+                    .zip(usize::try_from(self.pos.x + p.x - beneath.pos.x).ok())
+                    .and_then(|(y, x)| lines.get(y)?.get_cell(x).map(|c| c.as_cell()))
+                    .unwrap_or_default();
 
-                // In order to apply the foreground change, we need so print something.
-                let text = cell.str().to_string();
+                let top_bg = rgb(cell.attrs().background(), default_bg);
+                let under_bg = to_srgba(under.attrs().background()).unwrap_or(default_bg);
 
-                changes.push(Change::CursorPosition {
-                    x: Position::Absolute(j),
-                    y: Position::Absolute(i),
-                });
+                let mut c = if cell.str().trim().is_empty() && !under.str().trim().is_empty() {
+                    let mut c = under.clone();
+                    let fg = to_srgba(under.attrs().foreground()).unwrap_or(default_fg);
 
-                changes.push(Change::Attribute(AttributeChange::Foreground(fg)));
-                changes.push(Change::Text(text));
+                    c.attrs_mut()
+                        .set_foreground(ColorAttribute::TrueColorWithDefaultFallback(blend(
+                            top_bg, fg,
+                        )));
+
+                    c
+                } else {
+                    cell.as_cell()
+                };
+
+                c.attrs_mut()
+                    .set_background(ColorAttribute::TrueColorWithDefaultFallback(blend(
+                        top_bg, under_bg,
+                    )));
+                out.put_cell(p, &c);
             }
         }
 
-        self.surface.add_changes(changes);
+        out
     }
 
     /// Retrieve the size of the area.
@@ -135,3 +465,20 @@ impl Canvas {
         &self.surface
     }
 }
+
+/// Retrieve the SGR parameter setting the color; `base` is 30 for the foreground and 40 for the
+/// background.
+fn sgr_color(c: ColorAttribute, base: u8) -> Option<String> {
+    match c {
+        ColorAttribute::Default => None,
+        ColorAttribute::PaletteIndex(i) if i < 8 => Some((base + i).to_string()),
+        ColorAttribute::PaletteIndex(i) if i < 16 => Some((base + 60 + i - 8).to_string()),
+        ColorAttribute::PaletteIndex(i) => Some(format!("{};5;{i}", base + 8)),
+        ColorAttribute::TrueColorWithPaletteFallback(c, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(c) => {
+            let (r, g, b, _) = c.to_srgb_u8();
+
+            Some(format!("{};2;{r};{g};{b}", base + 8))
+        }
+    }
+}
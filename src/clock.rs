@@ -0,0 +1,181 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Minutes in a day.
+const DAY_MINUTES: i64 = 24 * 60;
+
+/// Retrieve the current local time in minutes since midnight.
+#[cfg(unix)]
+pub fn local_minutes() -> Option<u32> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() as libc::time_t;
+
+    // SAFETY: `libc::tm` is a plain C struct, for which all-zero bytes is a valid value.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    // SAFETY: both pointers are valid for the duration of the call.
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return None;
+    }
+
+    Some(tm.tm_hour as u32 * 60 + tm.tm_min as u32)
+}
+
+/// Retrieve the current UTC time in minutes since midnight (local time zones aren't supported on
+/// this platform).
+#[cfg(not(unix))]
+pub fn local_minutes() -> Option<u32> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+    Some((secs / 60 % DAY_MINUTES as u64) as u32)
+}
+
+/// Parse a range of hours like `21-7`.
+pub fn parse_hour_range(s: &str) -> Result<(u32, u32), String> {
+    let err = || format!("invalid hour range `{s}` (expected e.g. 21-7)");
+    let (start, end) = s.split_once('-').ok_or_else(err)?;
+
+    let parse_hour = |h: &str| {
+        h.trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|h| *h < 24)
+            .ok_or_else(err)
+    };
+
+    Ok((parse_hour(start)?, parse_hour(end)?))
+}
+
+/// Themes switched at local times (see --schedule).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    /// Times (in minutes since midnight) and names of the themes, ordered by the time.
+    entries: Vec<(u32, String)>,
+}
+
+impl Schedule {
+    /// Retrieve the name of the theme in effect at the time (in minutes since midnight). Before
+    /// the first entry, the last one from the previous day is in effect.
+    pub fn at(&self, now: u32) -> &str {
+        let entry = self
+            .entries
+            .iter()
+            .rev()
+            .find(|(t, _)| *t <= now)
+            .or(self.entries.last());
+
+        entry.map_or("", |(_, name)| name)
+    }
+
+    /// Retrieve the names of all scheduled themes.
+    pub fn themes(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(_, name)| name.as_str())
+    }
+}
+
+/// Parse a schedule like `08:00=paper,20:00=midnight`.
+pub fn parse_schedule(s: &str) -> Result<Schedule, String> {
+    let err = || format!("invalid schedule `{s}` (expected e.g. 08:00=paper,20:00=midnight)");
+    let mut entries = vec![];
+
+    for entry in s.split(',') {
+        let (time, name) = entry.split_once('=').ok_or_else(err)?;
+        let (h, m) = time.trim().split_once(':').ok_or_else(err)?;
+        let h: u32 = h.parse().ok().filter(|h| *h < 24).ok_or_else(err)?;
+        let m: u32 = m.parse().ok().filter(|m| *m < 60).ok_or_else(err)?;
+        let name = name.trim();
+
+        if name.is_empty() {
+            return Err(err());
+        }
+
+        entries.push((h * 60 + m, name.to_string()));
+    }
+
+    entries.sort_by_key(|(t, _)| *t);
+
+    Ok(Schedule { entries })
+}
+
+/// Calculate how deep into the night the time is (0.0 - 1.0). The value gradually rises during the
+/// first hour of the night and falls during the last one.
+pub fn night_depth(now: u32, (start, end): (u32, u32)) -> f32 {
+    let since_start = (now as i64 - start as i64 * 60).rem_euclid(DAY_MINUTES);
+    let night_len = ((end as i64 - start as i64) * 60).rem_euclid(DAY_MINUTES);
+
+    if since_start >= night_len {
+        return 0.0;
+    }
+
+    let edge = since_start.min(night_len - since_start);
+
+    (edge as f32 / 60.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hour_range() {
+        assert_eq!(parse_hour_range("21-7"), Ok((21, 7)));
+        assert_eq!(parse_hour_range(" 0 - 23 "), Ok((0, 23)));
+    }
+
+    #[test]
+    fn malformed_hour_range() {
+        for s in [
+            "", "-", "21", "21-", "-7", "24-7", "21-24", "a-b", "21-7-8", "-1-7",
+        ] {
+            assert!(parse_hour_range(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn night_depth_across_midnight() {
+        let night = (21, 7);
+
+        assert_eq!(night_depth(20 * 60, night), 0.0);
+        assert_eq!(night_depth(21 * 60 + 30, night), 0.5);
+        assert_eq!(night_depth(2 * 60, night), 1.0);
+        assert_eq!(night_depth(6 * 60 + 45, night), 0.25);
+        assert_eq!(night_depth(7 * 60, night), 0.0);
+        assert_eq!(night_depth(12 * 60, night), 0.0);
+    }
+
+    #[test]
+    fn empty_night() {
+        assert_eq!(night_depth(3 * 60, (3, 3)), 0.0);
+    }
+
+    #[test]
+    fn schedule() {
+        let s = parse_schedule("20:00=midnight, 08:30 = paper").unwrap();
+
+        assert_eq!(s.themes().collect::<Vec<_>>(), ["paper", "midnight"]);
+        assert_eq!(s.at(8 * 60 + 30), "paper");
+        assert_eq!(s.at(19 * 60 + 59), "paper");
+        assert_eq!(s.at(20 * 60), "midnight");
+        // Before the first entry, the last one from the previous day is in effect
+        assert_eq!(s.at(0), "midnight");
+    }
+
+    #[test]
+    fn malformed_schedule() {
+        for s in [
+            "",
+            "08:00",
+            "08:00=",
+            "8=paper",
+            "24:00=paper",
+            "08:60=paper",
+            "-1:00=paper",
+            "08:00=paper,",
+            "08:00=paper,,20:00=midnight",
+        ] {
+            assert!(parse_schedule(s).is_err(), "{s:?}");
+        }
+    }
+}
@@ -3,10 +3,14 @@
 // This file is licensed under the MIT License (see LICENSE.md).
 
 use clap::ValueEnum;
+use hex_color::HexColor;
+use termwiz::color::{ColorAttribute, SrgbaTuple};
 
 use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
+    distributions::{Distribution, Standard, WeightedIndex},
+    rngs::StdRng,
+    seq::SliceRandom,
+    Rng, SeedableRng,
 };
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, PartialOrd, Ord)]
@@ -32,3 +36,526 @@ pub enum ColorPalette {
     BaseColors,
     Rgb,
 }
+
+/// How many colors to sample at most when looking for a distinct one.
+const DISTINCT_ATTEMPTS: u32 = 32;
+
+/// Approximate values of the base colors (the xterm defaults), since the real ones are defined by
+/// the terminal.
+const BASE_COLORS_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Custom set of colors with weights: the higher the weight of a color, the more often it's
+/// picked (see --colors).
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomColors {
+    colors: Vec<SrgbaTuple>,
+    weights: WeightedIndex<u32>,
+}
+
+impl CustomColors {
+    /// Pick a random color according to the weights.
+    fn sample(&self, rng: &mut impl Rng) -> SrgbaTuple {
+        self.colors[self.weights.sample(rng)]
+    }
+}
+
+/// Parse a color like `#ff8800`.
+pub fn parse_color(s: &str) -> Result<SrgbaTuple, String> {
+    let hc =
+        HexColor::parse_rgb(s).map_err(|_| format!("`{s}` is not a color (expected #rrggbb)"))?;
+
+    Ok(SrgbaTuple(
+        hc.r as f32 / 255.0,
+        hc.g as f32 / 255.0,
+        hc.b as f32 / 255.0,
+        1.0,
+    ))
+}
+
+/// Parse a list of colors like `#ff0000:5,#00ff00:1`. A color without a weight has the weight 1.
+pub fn parse_colors(s: &str) -> Result<CustomColors, String> {
+    let mut colors = vec![];
+    let mut weights = vec![];
+
+    for entry in s.split(',') {
+        let (color, weight) = entry.trim().split_once(':').unwrap_or((entry.trim(), "1"));
+        let color = parse_color(color)?;
+        let weight = weight
+            .parse::<u32>()
+            .map_err(|_| format!("invalid weight `{weight}` of {entry} (expected e.g. 5)"))?;
+
+        colors.push(color);
+        weights.push(weight);
+    }
+
+    let weights = WeightedIndex::new(weights)
+        .map_err(|_| "at least one weight must be above 0".to_string())?;
+
+    Ok(CustomColors { colors, weights })
+}
+
+/// Source of random pipe colors.
+#[derive(Clone, Debug, Default)]
+pub struct ColorPool {
+    /// Palette to pick colors from.
+    pub palette: ColorPalette,
+    /// Custom colors picked instead of the palette (unless it's none).
+    pub custom: Option<CustomColors>,
+    /// Prefer darker colors, which are better visible on light backgrounds.
+    pub dark: bool,
+    /// How much to warm up the colors by reducing blue (0.0 - 1.0).
+    pub warmth: f32,
+    /// Derive each color from the previous one instead of picking a fresh one: the maximal change
+    /// of an RGB channel, or the probability of switching to a neighbouring base color.
+    pub walk: Option<f32>,
+    /// Previously picked color.
+    pub last: Option<ColorAttribute>,
+    /// Minimal and maximal luminance of RGB colors.
+    pub luma: Option<(f32, f32)>,
+    /// Minimal distance (CIE76 ΔE) of a new color from the previous color and the background.
+    pub distinct: Option<f32>,
+    /// Background color, which new colors must differ from.
+    pub bg: Option<SrgbaTuple>,
+}
+
+impl ColorPool {
+    /// Pick random color from the pool.
+    pub fn gen(&mut self, rng: &mut impl Rng) -> Option<ColorAttribute> {
+        let mut color = self.pick(rng);
+
+        // Resample colors too close to the neighbours, but settle for the most distinct one
+        if let Some(min) = self.distinct {
+            let mut best = (color, self.neighbour_distance(color));
+
+            for _ in 1..DISTINCT_ATTEMPTS {
+                if best.1 >= min {
+                    break;
+                }
+
+                let c = self.pick(rng);
+                let d = self.neighbour_distance(c);
+
+                if d > best.1 {
+                    best = (c, d);
+                }
+            }
+
+            color = best.0;
+        }
+
+        self.last = color;
+
+        color
+    }
+
+    /// Pick a color determined by the key (e.g. a hash of a name), so the same key always gets the
+    /// same color.
+    pub fn keyed(&self, key: u64) -> Option<ColorAttribute> {
+        self.clamp_luma(self.sample(&mut StdRng::seed_from_u64(key)))
+    }
+
+    /// Pick a color according to the settings, without checking its distinctness.
+    fn pick(&self, rng: &mut impl Rng) -> Option<ColorAttribute> {
+        let color = match (self.walk, self.last) {
+            (Some(step), Some(last)) => self.walk_from(last, step, rng),
+            _ => self.sample(rng),
+        };
+
+        self.clamp_luma(color)
+    }
+
+    /// Keep the luminance of an RGB color in the range (see --min-luma and --max-luma).
+    fn clamp_luma(&self, color: Option<ColorAttribute>) -> Option<ColorAttribute> {
+        match (color, self.luma) {
+            (Some(ColorAttribute::TrueColorWithDefaultFallback(c)), Some((min, max))) => Some(
+                ColorAttribute::TrueColorWithDefaultFallback(clamp_luminance(c, min, max)),
+            ),
+            _ => color,
+        }
+    }
+
+    /// Retrieve the distance of the color from the previous color and the background.
+    fn neighbour_distance(&self, color: Option<ColorAttribute>) -> f32 {
+        let Some(c) = color.and_then(to_srgba) else {
+            return f32::INFINITY;
+        };
+
+        self.last
+            .and_then(to_srgba)
+            .into_iter()
+            .chain(self.bg)
+            .map(|n| delta_e(c, n))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Retrieve the maximal values of the RGB channels.
+    fn rgb_ceiling(&self) -> (f32, f32, f32) {
+        let max = if self.dark { 0.6 } else { 1.0 };
+
+        (
+            max,
+            max * (1.0 - 0.2 * self.warmth),
+            max * (1.0 - 0.7 * self.warmth),
+        )
+    }
+
+    /// Retrieve the base colors which can be picked.
+    fn base_indices(&self) -> &'static [u8] {
+        let warm = self.warmth >= 0.5;
+
+        match (self.dark, warm) {
+            // Skip the bright colors and white, they are barely visible on light backgrounds
+            (true, false) => &[0, 1, 2, 3, 4, 5, 6],
+            (true, true) => &[0, 1, 2, 3, 5],
+            // Skip blue and cyan
+            (false, true) => &[0, 1, 2, 3, 5, 7, 8, 9, 10, 11, 13, 15],
+            (false, false) => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        }
+    }
+
+    /// Pick a fresh random color.
+    fn sample(&self, rng: &mut impl Rng) -> Option<ColorAttribute> {
+        match self.palette {
+            ColorPalette::None => None,
+            _ if self.custom.is_some() => self
+                .custom
+                .as_ref()
+                .map(|c| ColorAttribute::TrueColorWithDefaultFallback(c.sample(rng))),
+            ColorPalette::BaseColors => self
+                .base_indices()
+                .choose(rng)
+                .map(|i| ColorAttribute::PaletteIndex(*i)),
+            ColorPalette::Rgb => {
+                let max = self.rgb_ceiling();
+
+                Some(ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                    rng.gen::<f32>() * max.0,
+                    rng.gen::<f32>() * max.1,
+                    rng.gen::<f32>() * max.2,
+                    1.0,
+                )))
+            }
+        }
+    }
+
+    /// Pick a color close to the previous one.
+    fn walk_from(
+        &self,
+        last: ColorAttribute,
+        step: f32,
+        rng: &mut impl Rng,
+    ) -> Option<ColorAttribute> {
+        match (self.palette, last) {
+            (ColorPalette::Rgb, ColorAttribute::TrueColorWithDefaultFallback(c)) => {
+                let max = self.rgb_ceiling();
+                let mut walk = |x: f32, max: f32| (x + rng.gen_range(-step..=step)).clamp(0.0, max);
+
+                Some(ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                    walk(c.0, max.0),
+                    walk(c.1, max.1),
+                    walk(c.2, max.2),
+                    1.0,
+                )))
+            }
+            (ColorPalette::BaseColors, ColorAttribute::PaletteIndex(i)) => {
+                if !rng.gen_bool(step.clamp(0.0, 1.0) as f64) {
+                    return Some(last);
+                }
+
+                // The bright/dim variant or the neighbouring hue
+                let next = match rng.gen_range(0..3) {
+                    0 => shift_base_color(i, i < 8),
+                    1 if i % 8 > 1 && i % 8 < 7 => i - 1,
+                    2 if i % 8 > 0 && i % 8 < 6 => i + 1,
+                    _ => i,
+                };
+
+                if self.base_indices().contains(&next) {
+                    Some(ColorAttribute::PaletteIndex(next))
+                } else {
+                    Some(last)
+                }
+            }
+            _ => self.sample(rng),
+        }
+    }
+}
+
+/// Retrieve the complementary color: the inverted RGB color, or the opposite base color (red and
+/// cyan, green and magenta, yellow and blue, black and white) of the same brightness.
+pub fn complementary(c: ColorAttribute) -> ColorAttribute {
+    match c {
+        ColorAttribute::TrueColorWithDefaultFallback(c) => {
+            ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                1.0 - c.0,
+                1.0 - c.1,
+                1.0 - c.2,
+                c.3,
+            ))
+        }
+        ColorAttribute::PaletteIndex(i) if i < 16 => {
+            ColorAttribute::PaletteIndex(i / 8 * 8 + 7 - i % 8)
+        }
+        _ => c,
+    }
+}
+
+/// Composite the color over another one by its alpha channel. The result is opaque.
+pub fn blend(top: SrgbaTuple, bottom: SrgbaTuple) -> SrgbaTuple {
+    let a = top.3.clamp(0.0, 1.0);
+    let mix = |t: f32, b: f32| t * a + b * (1.0 - a);
+
+    SrgbaTuple(
+        mix(top.0, bottom.0),
+        mix(top.1, bottom.1),
+        mix(top.2, bottom.2),
+        1.0,
+    )
+}
+
+/// Relative luminance of a color (0.0 - 1.0).
+pub fn luminance(c: SrgbaTuple) -> f32 {
+    0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2
+}
+
+/// Retrieve the color of a heatmap for the heat (0.0 - 1.0): from blue through cyan, green and
+/// yellow to red.
+pub fn heat_color(heat: f32) -> SrgbaTuple {
+    // Hue in sixths of the color wheel, 4 is blue and 0 is red
+    let h = (1.0 - heat.clamp(0.0, 1.0)) * 4.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        _ => (0.0, x, 1.0),
+    };
+
+    SrgbaTuple(r, g, b, 1.0)
+}
+
+/// Convert a color to RGB. Base colors are approximated by the xterm defaults.
+pub fn to_srgba(c: ColorAttribute) -> Option<SrgbaTuple> {
+    match c {
+        ColorAttribute::TrueColorWithDefaultFallback(c)
+        | ColorAttribute::TrueColorWithPaletteFallback(c, _) => Some(c),
+        ColorAttribute::PaletteIndex(i) => BASE_COLORS_RGB.get(i as usize).map(|(r, g, b)| {
+            SrgbaTuple(*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0, 1.0)
+        }),
+        ColorAttribute::Default => None,
+    }
+}
+
+/// Convert an sRGB channel value (0.0 - 1.0) to linear light.
+fn to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert an sRGB color to the CIE L*a*b* color space (D65 white point).
+fn to_lab(c: SrgbaTuple) -> (f32, f32, f32) {
+    let (r, g, b) = (to_linear(c.0), to_linear(c.1), to_linear(c.2));
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// Perceptual distance of two colors (CIE76 ΔE). Colors with ΔE around 2 are barely
+/// distinguishable.
+pub fn delta_e(a: SrgbaTuple, b: SrgbaTuple) -> f32 {
+    let (a, b) = (to_lab(a), to_lab(b));
+
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+/// Contrast ratio of two colors as defined by WCAG (1.0 - 21.0). Text should have at least 4.5
+/// with its background.
+pub fn contrast_ratio(a: SrgbaTuple, b: SrgbaTuple) -> f32 {
+    let lum =
+        |c: SrgbaTuple| 0.2126 * to_linear(c.0) + 0.7152 * to_linear(c.1) + 0.0722 * to_linear(c.2);
+    let (a, b) = (lum(a), lum(b));
+
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Mix the color with black or white, whichever contrasts more with the background, until it has
+/// at least the contrast ratio with the background (or can't get any further).
+pub fn ensure_contrast(c: SrgbaTuple, bg: SrgbaTuple, ratio: f32) -> SrgbaTuple {
+    let target = if luminance(bg) > 0.5 { 0.0 } else { 1.0 };
+    let mut c = c;
+
+    for _ in 0..20 {
+        if contrast_ratio(c, bg) >= ratio {
+            break;
+        }
+
+        c = blend(SrgbaTuple(target, target, target, 0.2), c);
+    }
+
+    c
+}
+
+/// Bring the luminance of a color into the range: too dark colors are mixed with white, too light
+/// colors are darkened.
+pub fn clamp_luminance(c: SrgbaTuple, min: f32, max: f32) -> SrgbaTuple {
+    let l = luminance(c);
+
+    if l < min {
+        // Luminance is linear, so mixing with white by `t` raises it to l + t * (1 - l)
+        let t = (min - l) / (1.0 - l);
+        let mix = |x: f32| (x + (1.0 - x) * t).clamp(0.0, 1.0);
+
+        SrgbaTuple(mix(c.0), mix(c.1), mix(c.2), c.3)
+    } else if l > max {
+        let k = max / l;
+
+        SrgbaTuple(c.0 * k, c.1 * k, c.2 * k, c.3)
+    } else {
+        c
+    }
+}
+
+/// Shift all channels of an RGB color by `step` (used by the gradient mode). Other colors are
+/// returned unchanged.
+pub fn shift_color(c: ColorAttribute, step: f32) -> ColorAttribute {
+    match c {
+        ColorAttribute::TrueColorWithDefaultFallback(srgba) => {
+            ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                (srgba.0 + step).clamp(0.0, 1.0),
+                (srgba.1 + step).clamp(0.0, 1.0),
+                (srgba.2 + step).clamp(0.0, 1.0),
+                1.0,
+            ))
+        }
+        c => c,
+    }
+}
+
+/// Switch a base color to its brighter (`up`) or dimmer variant. Colors are switched between their
+/// normal (1 - 6) and bright (9 - 14) variants, grays step through black, bright black, white and
+/// bright white. The brightest and the dimmest variants are left unchanged.
+pub fn shift_base_color(i: u8, up: bool) -> u8 {
+    const GRAYS: [u8; 4] = [0, 8, 7, 15];
+
+    if let Some(pos) = GRAYS.iter().position(|g| *g == i) {
+        let pos = if up {
+            (pos + 1).min(GRAYS.len() - 1)
+        } else {
+            pos.saturating_sub(1)
+        };
+
+        return GRAYS[pos];
+    }
+
+    match (i, up) {
+        (1..=6, true) => i + 8,
+        (9..=14, false) => i - 8,
+        _ => i,
+    }
+}
+
+/// Scale the brightness of a color by `factor` (0.0 - 1.0, or above 1.0 to brighten it).
+///
+/// RGB colors are scaled per channel. Base colors can't be scaled, so the bright variants
+/// (indices 8 - 15) are swapped with their normal counterparts when the factor drops below a half,
+/// and the other way around when it's above 1.
+pub fn scale_brightness(c: ColorAttribute, factor: f32) -> ColorAttribute {
+    match c {
+        ColorAttribute::TrueColorWithDefaultFallback(srgba) => {
+            ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                (srgba.0 * factor).clamp(0.0, 1.0),
+                (srgba.1 * factor).clamp(0.0, 1.0),
+                (srgba.2 * factor).clamp(0.0, 1.0),
+                srgba.3,
+            ))
+        }
+        ColorAttribute::PaletteIndex(i) if i >= 8 && factor < 0.5 => {
+            ColorAttribute::PaletteIndex(i - 8)
+        }
+        ColorAttribute::PaletteIndex(i) if i < 8 && factor > 1.0 => {
+            ColorAttribute::PaletteIndex(i + 8)
+        }
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: SrgbaTuple = SrgbaTuple(1.0, 0.0, 0.0, 1.0);
+    const GREEN: SrgbaTuple = SrgbaTuple(0.0, 1.0, 0.0, 1.0);
+
+    #[test]
+    fn colors() {
+        let c = parse_colors("#ff0000:5, #00ff00").unwrap();
+
+        assert_eq!(c.colors, [RED, GREEN]);
+        assert_eq!(c.weights, WeightedIndex::new([5, 1]).unwrap());
+    }
+
+    #[test]
+    fn zero_weight_never_picked() {
+        let c = parse_colors("#ff0000:0,#00ff00:1").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        assert!((0..100).all(|_| c.sample(&mut rng) == GREEN));
+    }
+
+    #[test]
+    fn malformed_colors() {
+        for s in [
+            "",
+            ",",
+            "red",
+            "#ff0000,",
+            "#ff0000:",
+            "#ff0000:-1",
+            "#ff0000:1.5",
+            "#ff0000:1:2",
+        ] {
+            assert!(parse_colors(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn all_zero_weights() {
+        assert_eq!(
+            parse_colors("#ff0000:0,#00ff00:0"),
+            Err("at least one weight must be above 0".to_string())
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    color::ColorPalette,
+    config::{env_var, Config, GlyphAttr, PieceBudget},
+};
+use rand::{seq::SliceRandom, Rng};
+use std::{env, ffi::OsString};
+use termwiz::color::ColorAttribute;
+
+/// Value of --compat selecting pipes.sh.
+const PIPES_SH: &str = "pipes.sh";
+
+/// Piece sets of pipes.sh (-t), indexed by `[DIRECTION OF THE PREVIOUS PIECE * 4 + CURRENT
+/// DIRECTION]` where the directions are up, right, down and left.
+const PIPES_SH_SETS: [&str; 10] = [
+    "┃┏ ┓┛━┓  ┗┃┛┗ ┏━",
+    "│╭ ╮╯─╮  ╰│╯╰ ╭─",
+    "│┌ ┐┘─┐  └│┘└ ┌─",
+    "║╔ ╗╝═╗  ╚║╝╚ ╔═",
+    "|+ ++-+  +|++ +-",
+    "|/ \\/-\\  \\|/\\ /-",
+    ".. ....  .... ..",
+    ".o oo.o  o.oo o.",
+    "-\\ /\\|/  /-\\/ \\|",
+    "╿┍ ┑┚╼┒  ┕╽┙┖ ┎╾",
+];
+
+/// Indices of the pieces of a pipes.sh set in the canonical order (`│─┌┐└┘`).
+const CANONICAL_ORDER: [usize; 6] = [0, 5, 1, 3, 9, 11];
+
+/// Number of pieces in a pipes.sh set.
+const PIPES_SH_SET_LEN: usize = 16;
+
+/// Frame rate of pipes.sh.
+const PIPES_SH_FPS: i64 = 75;
+
+/// Straightness of pipes.sh (-s): each step turns with probability `2 / straightness`.
+const PIPES_SH_STRAIGHTNESS: u32 = 13;
+
+/// Number of drawn pieces after which pipes.sh clears the screen (-r).
+const PIPES_SH_RESET_LIMIT: u64 = 2000;
+
+/// Behavior of pipes.sh which has no rxpipes option.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipesSh {
+    /// Start the pipes at random points instead of the center of the screen (-R).
+    pub random_start: bool,
+    /// Don't draw the pieces bold (-B).
+    pub no_bold: bool,
+    /// Keep the color of the pipe when it crosses an edge (-K).
+    pub keep_color: bool,
+    /// Base colors the pipes are colored with (-c). All colors of the palette if empty.
+    pub colors: Vec<u8>,
+}
+
+impl PipesSh {
+    /// Apply the defaults of pipes.sh to the configuration: 75 FPS, turning probability 2/13, a
+    /// single endless pipe, clearing after 2000 pieces and bold pieces. Options set explicitly by
+    /// the user take precedence.
+    pub fn apply(mut self, cfg: &mut Config) {
+        if !cfg.is_explicit("fps") {
+            cfg.fps = PIPES_SH_FPS;
+        }
+
+        if !cfg.is_explicit("turning_prob") {
+            cfg.turning_prob = 2.0 / PIPES_SH_STRAIGHTNESS as f64;
+        }
+
+        if !cfg.is_explicit("max_drawn_pieces") {
+            cfg.max_drawn_pieces = PieceBudget::Pieces(PIPES_SH_RESET_LIMIT);
+        }
+
+        // The pipe of pipes.sh never ends, it only changes color at the edges
+        if !cfg.is_explicit("min_pipe_length") && !cfg.is_explicit("max_pipe_length") {
+            cfg.min_pipe_length = u64::MAX;
+            cfg.max_pipe_length = u64::MAX;
+        }
+
+        if !self.no_bold && !cfg.is_explicit("attr") {
+            cfg.attr = vec![GlyphAttr::Bold];
+        }
+
+        if cfg.palette == ColorPalette::None {
+            self.colors.clear();
+        }
+
+        cfg.pipes_sh = Some(self);
+    }
+
+    /// Pick one of the colors given by -c, if there are any.
+    pub fn fixed_color(&self, rng: &mut impl Rng) -> Option<ColorAttribute> {
+        self.colors
+            .choose(rng)
+            .map(|c| ColorAttribute::PaletteIndex(*c))
+    }
+}
+
+/// Check whether the command line or the environment selects pipes.sh compatibility.
+fn wants_pipes_sh(args: &[OsString]) -> bool {
+    let in_args = args
+        .windows(2)
+        .any(|w| w[0] == "--compat" && w[1] == PIPES_SH)
+        || args
+            .iter()
+            .any(|a| *a == format!("--compat={PIPES_SH}").as_str());
+
+    in_args || env::var(env_var("compat")).is_ok_and(|v| v == PIPES_SH)
+}
+
+/// Convert a pipes.sh piece set (16 pieces) to the canonical order of rxpipes.
+fn convert_pieces(set: &str) -> Option<String> {
+    let pieces: Vec<char> = set.chars().collect();
+
+    if pieces.len() != PIPES_SH_SET_LEN {
+        return None;
+    }
+
+    Some(CANONICAL_ORDER.iter().map(|&i| pieces[i]).collect())
+}
+
+/// Translate the flags of pipes.sh (`-p`, `-t`, `-c`, `-f`, `-s`, `-r`, `-R`, `-B`, `-C`, `-K`,
+/// `-h` and `-v`) to rxpipes options if pipes.sh compatibility is selected (--compat pipes.sh).
+/// The behavior without an rxpipes option is returned separately and applied after parsing (see
+/// [`PipesSh::apply`]). Other arguments are passed through unchanged.
+pub fn translate_args(args: Vec<OsString>) -> Result<(Vec<OsString>, Option<PipesSh>), String> {
+    if !wants_pipes_sh(&args) {
+        return Ok((args, None));
+    }
+
+    let mut quirks = PipesSh::default();
+    let mut piece_set_given = false;
+    let mut out = vec![];
+    let mut args = args.into_iter();
+
+    out.extend(args.next());
+
+    while let Some(arg) = args.next() {
+        let flags = match arg.to_str() {
+            Some("--") => {
+                out.push(arg);
+                out.extend(args.by_ref());
+                break;
+            }
+            Some(s)
+                if s.starts_with('-')
+                    && !s.starts_with("--")
+                    && s[1..].starts_with(|c| "ptcfsrRBCKhv".contains(c)) =>
+            {
+                s[1..].to_string()
+            }
+            _ => {
+                out.push(arg);
+                continue;
+            }
+        };
+
+        for (i, flag) in flags.char_indices() {
+            let mut value = || {
+                let rest = &flags[i + flag.len_utf8()..];
+
+                if rest.is_empty() {
+                    args.next()
+                        .and_then(|v| v.into_string().ok())
+                        .ok_or_else(|| format!("pipes.sh flag -{flag} needs a value"))
+                } else {
+                    Ok(rest.to_string())
+                }
+            };
+            let number = |v: String| {
+                v.parse::<u64>()
+                    .map_err(|_| format!("invalid value `{v}` for pipes.sh flag -{flag}"))
+            };
+
+            match flag {
+                'p' | 't' | 'c' | 'f' | 's' | 'r' => {
+                    let v = value()?;
+
+                    match flag {
+                        // rxpipes draws one pipe at a time
+                        'p' => {
+                            number(v)?;
+                        }
+                        't' => {
+                            let set = match v.strip_prefix('c') {
+                                Some(custom) => convert_pieces(custom),
+                                None => PIPES_SH_SETS
+                                    .get(number(v.clone())? as usize)
+                                    .and_then(|s| convert_pieces(s)),
+                            }
+                            .ok_or_else(|| {
+                                format!("invalid pipes.sh piece set `{v}` (expected 0 - 9 or c followed by 16 pieces)")
+                            })?;
+
+                            // pipes.sh picks a random one of several sets, use the first one
+                            if !piece_set_given {
+                                piece_set_given = true;
+                                out.extend(["--custom-piece-set".into(), set.into()]);
+                            }
+                        }
+                        'c' => {
+                            let c = u8::try_from(number(v.clone())?)
+                                .map_err(|_| format!("invalid pipes.sh color `{v}`"))?;
+
+                            quirks.colors.push(c);
+                        }
+                        'f' => out.extend(["--fps".into(), number(v)?.to_string().into()]),
+                        's' => {
+                            let straightness = number(v.clone())?;
+
+                            if straightness < 2 {
+                                return Err(format!(
+                                    "invalid pipes.sh straightness `{v}` (expected at least 2)"
+                                ));
+                            }
+
+                            out.extend([
+                                "--turning-prob".into(),
+                                (2.0 / straightness as f64).to_string().into(),
+                            ]);
+                        }
+                        _ => {
+                            out.extend(["--max-drawn-pieces".into(), number(v)?.to_string().into()])
+                        }
+                    }
+
+                    // The rest of the argument was the value
+                    break;
+                }
+                'R' => quirks.random_start = true,
+                'B' => quirks.no_bold = true,
+                'C' => out.extend(["--palette".into(), "none".into()]),
+                'K' => quirks.keep_color = true,
+                'h' => out.push("--help".into()),
+                'v' => out.push("--version".into()),
+                _ => return Err(format!("unknown pipes.sh flag -{flag}")),
+            }
+        }
+    }
+
+    Ok((out, Some(quirks)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Translate the arguments (without the program name) and retrieve them as strings.
+    fn translate(args: &[&str]) -> Result<(Vec<String>, Option<PipesSh>), String> {
+        let args = ["rxpipes"].iter().chain(args).map(OsString::from).collect();
+
+        translate_args(args).map(|(out, quirks)| {
+            let out = out
+                .into_iter()
+                .skip(1)
+                .map(|a| a.into_string().unwrap())
+                .collect();
+
+            (out, quirks)
+        })
+    }
+
+    #[test]
+    fn passes_through_without_compat() {
+        let (out, quirks) = translate(&["-p", "3", "-f", "60"]).unwrap();
+
+        assert_eq!(out, ["-p", "3", "-f", "60"]);
+        assert_eq!(quirks, None);
+    }
+
+    #[test]
+    fn translates_flags_with_values() {
+        let (out, quirks) = translate(&[
+            "--compat", "pipes.sh", "-p", "3", "-f60", "-s", "4", "-r", "500",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            out,
+            [
+                "--compat",
+                "pipes.sh",
+                "--fps",
+                "60",
+                "--turning-prob",
+                "0.5",
+                "--max-drawn-pieces",
+                "500",
+            ]
+        );
+        assert_eq!(quirks, Some(PipesSh::default()));
+    }
+
+    #[test]
+    fn translates_combined_flags() {
+        let (out, quirks) = translate(&["--compat=pipes.sh", "-RBKc2", "-c", "4"]).unwrap();
+        let quirks = quirks.unwrap();
+
+        assert_eq!(out, ["--compat=pipes.sh"]);
+        assert!(quirks.random_start && quirks.no_bold && quirks.keep_color);
+        assert_eq!(quirks.colors, [2, 4]);
+    }
+
+    #[test]
+    fn translates_piece_sets() {
+        let (out, _) = translate(&["--compat", "pipes.sh", "-t", "0", "-t", "1"]).unwrap();
+
+        // Only the first piece set is used
+        assert_eq!(
+            out,
+            ["--compat", "pipes.sh", "--custom-piece-set", "┃━┏┓┗┛"]
+        );
+
+        let (out, _) = translate(&["--compat", "pipes.sh", "-tc|+ ++-+  +|++ +-"]).unwrap();
+
+        assert_eq!(out[3], "|-++++");
+    }
+
+    #[test]
+    fn keeps_arguments_after_double_dash() {
+        let (out, _) = translate(&["--compat", "pipes.sh", "--", "-f", "60"]).unwrap();
+
+        assert_eq!(out, ["--compat", "pipes.sh", "--", "-f", "60"]);
+    }
+
+    #[test]
+    fn rejects_invalid_flags() {
+        for flags in [
+            &["-Rx"][..],
+            &["-f", "fast"],
+            &["-f"],
+            &["-s", "1"],
+            &["-t", "10"],
+            &["-tcshort"],
+            &["-c", "256"],
+        ] {
+            let args: Vec<&str> = ["--compat", "pipes.sh"]
+                .iter()
+                .chain(flags)
+                .copied()
+                .collect();
+
+            assert!(translate(&args).is_err(), "{flags:?}");
+        }
+    }
+
+    #[test]
+    fn passes_through_other_flags() {
+        let (out, _) = translate(&["--compat", "pipes.sh", "-x", "-a"]).unwrap();
+
+        assert_eq!(out, ["--compat", "pipes.sh", "-x", "-a"]);
+    }
+}
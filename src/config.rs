@@ -2,11 +2,320 @@
 //
 // This file is licensed under the MIT License (see LICENSE.md).
 
-use crate::color::ColorPalette;
-use clap::Parser;
+use crate::{
+    clock::{parse_hour_range, parse_schedule, Schedule},
+    color::{parse_color, parse_colors, ColorPalette, CustomColors},
+    compat::PipesSh,
+    curve::CurveKind,
+    piece_set::{PieceSetMeta, PIECE_COUNT},
+    plane_2d::Point,
+    react::Metric,
+};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, Parser, Subcommand, ValueEnum};
+use eyre::{bail, Result};
+use hex_color::HexColor;
+use std::{
+    io::{self, IsTerminal},
+    mem,
+    path::PathBuf,
+    time::Duration,
+};
+use termwiz::{caps::ColorLevel, color::SrgbaTuple};
+
+/// Prefix of the environment variables setting the options.
+const ENV_PREFIX: &str = "RXPIPES_";
+
+/// Retrieve the name of the environment variable setting the option with the long name, e.g.
+/// `RXPIPES_MIN_PIPE_LENGTH` for `--min-pipe-length`.
+pub fn env_var(long: &str) -> String {
+    format!(
+        "{ENV_PREFIX}{}",
+        long.trim_start_matches('-')
+            .replace('-', "_")
+            .to_uppercase()
+    )
+}
+
+/// What the screensaver draws.
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum Mode {
+    /// Pipes randomly wandering around the screen.
+    #[default]
+    Pipes,
+    /// A randomly generated maze carved with pipe pieces.
+    Maze,
+    /// A pipe tracing a famous curve (see --curve).
+    Curve,
+    /// Pipes which crumble away like cells of the Game of Life before the screen is cleared.
+    LifeDecay,
+    /// Pipes under the current weather (see --weather-cmd): rain or snow falls over them and their
+    /// colors follow the conditions.
+    Weather,
+    /// A burst of pipes for each new commit in a git repository (see --repo), colored by the
+    /// author.
+    GitActivity,
+}
+
+/// Where the twin of each pipe is drawn (see --mirror-pair).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum MirrorPair {
+    /// Reflected through the center of the screen.
+    Point,
+    /// Shifted by half of the screen in both directions.
+    Offset,
+}
+
+/// Text attribute of the pipe pieces.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum GlyphAttr {
+    Bold,
+    Italic,
+    Blink,
+    /// Give each pipe a random combination of the other listed attributes (bold and italic if
+    /// none are listed).
+    Random,
+}
+
+/// What happens when the screen is full (see --max-drawn-pieces).
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum OnFull {
+    /// Clear the screen.
+    #[default]
+    Clear,
+    /// Pause the animation, keeping the pipes on the screen. When resumed, it pauses again after
+    /// drawing as many pieces more.
+    Stop,
+    /// Darken the pipes and draw new ones on top of them, like a new layer in depth mode.
+    Darken,
+}
+
+/// How the stats widget labels its values (see --stats-style).
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum StatsStyle {
+    /// Abbreviated labels separated by commas, e.g. "pcs. drawn: 1234, pps. drawn: 12".
+    #[default]
+    Compact,
+    /// Full labels separated by bullets, e.g. "pieces drawn: 1234 • pipes: 12".
+    Verbose,
+}
+
+/// Aspect of the style randomized by --surprise-me.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum Surprise {
+    /// A built-in theme (unless --theme or --schedule is given).
+    Theme,
+    /// Base colors or RGB.
+    Palette,
+    /// One of the default piece sets.
+    PieceSet,
+    /// A mirrored twin of each pipe, or none (see --mirror-pair).
+    Symmetry,
+    /// Gradient and z-depth.
+    Effects,
+}
+
+/// How the screen is cleared when it's full (see --clear-animation).
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum ClearAnimation {
+    /// Blank the screen at once.
+    #[default]
+    None,
+    /// Darken the pipes and let them dissolve.
+    Fade,
+    /// Retract the pipes in the reverse drawing order.
+    Rewind,
+}
+
+/// Where the pause badge is shown (see --pause-badge).
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum BadgePosition {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    /// Don't show the badge.
+    None,
+}
+
+/// Simulation event which runs --sound-cmd.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum SoundEvent {
+    /// The screen has been cleared.
+    Clear,
+    /// A new layer has been started (depth mode).
+    Layer,
+    /// A pipe has run into another one (see --end-on-collision).
+    Collision,
+}
+
+/// Program whose defaults and flags are mimicked.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum Compat {
+    /// pipes.sh (https://github.com/pipeseroni/pipes.sh).
+    #[value(name = "pipes.sh")]
+    PipesSh,
+}
+
+/// Additional tools.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Design a custom piece set interactively and write it to a file usable with
+    /// --piece-set-file.
+    Design {
+        /// File to write the piece set to.
+        #[arg(default_value = "pieces.txt")]
+        output: PathBuf,
+    },
+    /// Run the simulation without animating it and print only the final frame (e.g. for prompts
+    /// and MOTDs). The terminal isn't switched to raw mode or the alternate screen.
+    Frame {
+        /// Number of simulation steps.
+        #[arg(long, default_value_t = 1000)]
+        steps: u64,
+        /// Size of the frame (COLSxROWS). Defaults to the size of the terminal.
+        #[arg(long, value_parser = parse_size)]
+        size: Option<(usize, usize)>,
+    },
+    /// Run several seeded simulations side by side and pick one (key 1-9) to continue
+    /// full-screen with its seed.
+    Gallery {
+        /// Number of simulations (rounded up to fill the grid).
+        #[arg(long, default_value_t = 4, value_parser = 1..=9)]
+        seeds: i64,
+        /// Number of simulation steps after which the simulations stop.
+        #[arg(long, default_value_t = 300)]
+        steps: u64,
+    },
+    /// Run the same seeded simulation with each way of rendering it and print a table comparing
+    /// the time and bytes needed to draw a frame, and the ways of darkening the layers. Nothing is
+    /// drawn on the terminal.
+    Bench {
+        /// Number of simulation steps (frames).
+        #[arg(long, default_value_t = 1000)]
+        steps: u64,
+        /// Size of the simulated screen (COLSxROWS). Defaults to the size of the terminal.
+        #[arg(long, value_parser = parse_size)]
+        size: Option<(usize, usize)>,
+    },
+    /// Browse the built-in themes with a live preview and write them to the config directory.
+    Themes {
+        /// Only print the names of the available themes.
+        #[arg(long)]
+        list: bool,
+    },
+}
+
+/// Region of the terminal screen.
+#[derive(Copy, Clone, Debug)]
+pub struct Geometry {
+    /// Size of the region.
+    pub size: (usize, usize),
+    /// Position of the top left corner.
+    pub pos: Point,
+}
+
+/// Parse a geometry like `80x24+10+5` (`COLSxROWS+X+Y`). The position may be omitted.
+pub fn parse_geometry(s: &str) -> Result<Geometry, String> {
+    let err = || format!("invalid geometry `{s}` (expected COLSxROWS+X+Y)");
+    let mut parts = s.split('+');
+
+    let (cols, rows) = parts
+        .next()
+        .and_then(|size| size.split_once('x'))
+        .ok_or_else(err)?;
+    let num = |s: &str| s.parse::<usize>().map_err(|_| err());
+
+    let size = (num(cols)?, num(rows)?);
+    let pos = Point {
+        x: parts.next().map_or(Ok(0), num)? as isize,
+        y: parts.next().map_or(Ok(0), num)? as isize,
+    };
+
+    if size.0 == 0 || size.1 == 0 || parts.next().is_some() {
+        return Err(err());
+    }
+
+    Ok(Geometry { size, pos })
+}
+
+/// Parse a size like `80x24`.
+pub fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    match parse_geometry(s)? {
+        Geometry {
+            size,
+            pos: Point { x: 0, y: 0 },
+        } if !s.contains('+') => Ok(size),
+        _ => Err(format!("invalid size `{s}` (expected COLSxROWS)")),
+    }
+}
+
+/// Number of pipe pieces, either fixed or proportional to the screen area.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PieceBudget {
+    /// Fixed number of pieces.
+    Pieces(u64),
+    /// Multiple of the number of cells of the screen (e.g. `5x`).
+    PerArea(f64),
+}
+
+impl PieceBudget {
+    /// Retrieve the number of pieces on a screen of specified size.
+    pub fn pieces(self, scr_size: (usize, usize)) -> u64 {
+        match self {
+            Self::Pieces(n) => n,
+            Self::PerArea(k) => (k * (scr_size.0 * scr_size.1) as f64).round() as u64,
+        }
+    }
+
+    /// Check whether the budget is 0 on any screen.
+    pub fn is_zero(self) -> bool {
+        matches!(self, Self::Pieces(0)) || self == Self::PerArea(0.0)
+    }
+}
+
+/// Parse a number of pieces like `10000` or a multiple of the screen area like `5x` or `0.5x`.
+pub fn parse_piece_budget(s: &str) -> Result<PieceBudget, String> {
+    let err = || format!("invalid number of pieces `{s}` (expected e.g. 10000 or 5x)");
+
+    match s.strip_suffix('x') {
+        Some(k) => k
+            .parse::<f64>()
+            .ok()
+            .filter(|k| k.is_finite() && *k >= 0.0)
+            .map(PieceBudget::PerArea)
+            .ok_or_else(err),
+        None => s.parse().map(PieceBudget::Pieces).map_err(|_| err()),
+    }
+}
+
+/// Parse a duration like `90`, `90s`, `5m` or `1h` (a bare number means seconds).
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+
+    let n: u64 = num.parse().map_err(|_| format!("invalid duration `{s}`"))?;
+
+    let secs = match unit {
+        "s" => Some(n),
+        "m" => n.checked_mul(60),
+        "h" => n.checked_mul(60 * 60),
+        _ => {
+            return Err(format!(
+                "invalid duration unit `{unit}` (expected s, m or h)"
+            ))
+        }
+    };
+
+    secs.map(Duration::from_secs)
+        .ok_or_else(|| format!("duration `{s}` is out of range"))
+}
 
 /// Screensaver settings and CLI parser.
-#[derive(Debug, Parser)]
+#[derive(Clone, Debug, Parser)]
 #[command(
     about = "2D version of the ancient pipes screensaver for terminals.",
     author = "inunix3",
@@ -14,53 +323,361 @@ use clap::Parser;
     long_about = None,
 )]
 pub struct Config {
+    /// What to draw.
+    #[arg(long, default_value_t, value_enum)]
+    pub mode: Mode,
+    /// Curve mode: which curve to trace.
+    #[arg(long, default_value_t, value_enum)]
+    pub curve: CurveKind,
+    /// Weather mode: command (run via `sh -c`) printing the current weather, either a description
+    /// of the conditions (e.g. `Light rain`) or a JSON object with a `condition` field holding it.
+    #[arg(
+        long,
+        value_name = "CMD",
+        conflicts_with = "weather_file",
+        verbatim_doc_comment
+    )]
+    pub weather_cmd: Option<String>,
+    /// Weather mode: file holding the current weather, in the same format as the output of
+    /// --weather-cmd.
+    #[arg(long, value_name = "FILE", verbatim_doc_comment)]
+    pub weather_file: Option<PathBuf>,
+    /// Weather mode: how often the weather is read again (e.g. 10m).
+    #[arg(long, value_parser = parse_duration, default_value = "10m")]
+    pub weather_interval: Duration,
+    /// Git-activity mode: path to the git repository to watch for new commits.
+    #[arg(long, value_name = "PATH")]
+    pub repo: Option<PathBuf>,
+    /// Follow this file (e.g. a log) like `tail -F` and spawn a pipe for each new line, colored by
+    /// the line, instead of spawning pipes on their own.
+    #[arg(long, value_name = "FILE", verbatim_doc_comment)]
+    pub spawn_on_line: Option<PathBuf>,
+    /// Read the bytes piped to rxpipes (e.g. `producer | rxpipes --stdin-mode`) and drive the
+    /// turns and colors by them instead of the seed, one byte per step, so the same input always
+    /// draws the same picture. The pipes stop when the input runs dry. The keys are read from the
+    /// terminal (/dev/tty).
+    #[arg(long, conflicts_with = "sync_connect", verbatim_doc_comment)]
+    pub stdin_mode: bool,
     /// Frames per second.
     #[arg(short, long, value_parser = 1.., default_value_t = 24)]
     pub fps: i64,
-    /// Maximum drawn pieces of pipes on the screen.
-    /// When this maximum is reached, the screen will be cleared.
+    /// Reduce motion for users sensitive to it: cap the FPS at 10, don't blink or flash and fade
+    /// the pipes out gently instead of clearing the screen abruptly.
+    #[arg(long, verbatim_doc_comment)]
+    pub reduced_motion: bool,
+    /// Guaranteed-to-work mode for serial consoles, very old terminals and CI logs: ASCII pieces,
+    /// no colors or text attributes, no alternate screen and no mouse reporting.
+    #[arg(long, verbatim_doc_comment)]
+    pub safe_mode: bool,
+    /// Use only the glyphs and colors the Linux virtual console can show: thin or double pipes
+    /// instead of bold and rounded ones, and the base colors instead of RGB. Enabled automatically
+    /// when TERM is `linux`. Options set explicitly are kept.
+    #[arg(long, verbatim_doc_comment)]
+    pub console: bool,
+    /// Lower the FPS automatically when the terminal can't keep up with rendering (e.g. over slow
+    /// SSH), and raise it back to --fps when it can again.
+    #[arg(long, verbatim_doc_comment)]
+    pub adaptive_fps: bool,
+    /// Write at most about this many bytes of escape sequences to the terminal per frame (e.g.
+    /// 2000). The remaining changes are drawn in the next frames, so a terminal over a slow link
+    /// never lags seconds behind.
+    #[arg(long, value_name = "BYTES", value_parser = 1.., verbatim_doc_comment)]
+    pub max_bytes_per_frame: Option<i64>,
+    /// Simulate a slow remote terminal for testing: delay the output of each frame by this many
+    /// milliseconds and write it in small chunks.
+    #[arg(long, value_name = "MS", value_parser = 1.., verbatim_doc_comment)]
+    pub simulate_latency: Option<i64>,
+    /// Draw the first pipes at low brightness and ramp up to full colors over this time (e.g. 10),
+    /// so the screensaver doesn't flash on an idle dark screen.
+    #[arg(long, value_parser = parse_duration, verbatim_doc_comment)]
+    pub fade_in: Option<Duration>,
+    /// Wait this time (e.g. 3) before the animation begins.
+    #[arg(long, value_parser = parse_duration)]
+    pub start_delay: Option<Duration>,
+    /// Show a splash screen with the version and the theme during --start-delay.
+    #[arg(long, requires = "start_delay")]
+    pub splash: bool,
+    /// Run this number of steps before the first frame, so the screen starts already full of pipes.
+    #[arg(long, default_value_t = 0, value_name = "STEPS")]
+    pub warmup: u64,
+    /// Keep the frames of this last time (e.g. 10s) to be played in reverse with the `r` key.
+    /// Set it to 0 to disable.
+    #[arg(long, default_value = "10s", value_parser = parse_duration, verbatim_doc_comment)]
+    pub rewind_buffer: Duration,
+    /// On quit, print a summary of the run (duration, pieces, pipes, average FPS and the seed, so
+    /// the run can be reproduced).
+    #[arg(long, verbatim_doc_comment)]
+    pub summary: bool,
+    /// Be polite to screen readers and other accessibility tools: redraw the screen only every 5
+    /// seconds and announce major events (screen clears, new layers) in the window title.
+    #[arg(long, verbatim_doc_comment)]
+    pub screen_reader: bool,
+    /// Maximum drawn pieces of pipes on the screen, or a multiple of the screen area (e.g. 5x),
+    /// which follows the size of the terminal.
+    /// When this maximum is reached, the screen will be cleared (see --on-full).
     /// Set it to 0 to remove the limit.
-    #[arg(short, long, default_value_t = 10000, verbatim_doc_comment)]
-    pub max_drawn_pieces: u64,
+    #[arg(short, long, default_value = "10000", value_parser = parse_piece_budget, verbatim_doc_comment)]
+    pub max_drawn_pieces: PieceBudget,
+    /// What to do when --max-drawn-pieces is reached.
+    #[arg(long, default_value_t, value_enum)]
+    pub on_full: OnFull,
+    /// How the screen is cleared when it's full. With --reduced-motion, the pipes always fade
+    /// out.
+    #[arg(long, default_value_t, value_enum, verbatim_doc_comment)]
+    pub clear_animation: ClearAnimation,
+    /// Life-decay mode: number of generations to let pipes crumble before the screen is cleared.
+    #[arg(long, default_value_t = 30)]
+    pub decay_steps: u32,
+    /// Life-decay mode: start crumbling after this time (e.g. 90s, 5m), even if
+    /// --max-drawn-pieces hasn't been reached yet.
+    #[arg(long, value_parser = parse_duration, verbatim_doc_comment)]
+    pub decay_interval: Option<Duration>,
     /// Maximum length of pipe in pieces.
-    /// Must not equal to or be less than --min-pipe-length.
+    /// Must not be less than --min-pipe-length.
     #[arg(long, default_value_t = 300, verbatim_doc_comment)]
     pub max_pipe_length: u64,
     /// Minimal length of pipe in pieces.
-    /// Must not equal to or be greater than --max-pipe-length.
+    /// Must not be 0 or greater than --max-pipe-length.
     #[arg(long, default_value_t = 7, verbatim_doc_comment)]
     pub min_pipe_length: u64,
     /// Probability of turning a pipe as a percentage in a decimal form.
     #[arg(short = 't', long, default_value_t = 0.2)]
     pub turning_prob: f64,
+    /// Make the probability of turning grow with the length of the straight run: it's 0 right after
+    /// a turn and reaches --turning-prob after this number of straight pieces (e.g. 5).
+    #[arg(long, num_args = 0..=1, default_missing_value = "5", value_name = "PIECES", value_parser = 1.., verbatim_doc_comment)]
+    pub turn_momentum: Option<i64>,
+    /// Forbid turns reversing the direction of the pipe within this number of pieces (2 by
+    /// default), which would fold it back onto itself in tight zigzags.
+    #[arg(long, num_args = 0..=1, default_missing_value = "2", value_name = "PIECES", value_parser = 1.., verbatim_doc_comment)]
+    pub no_immediate_uturn: Option<i64>,
+    /// End a pipe when its next cell is already occupied by another piece, then spawn a new one.
+    /// A burst of sparks marks the impact (not with --reduced-motion).
+    #[arg(short = 'e', long, verbatim_doc_comment)]
+    pub end_on_collision: bool,
+    /// Surround the screen with walls. Pipes crawl along them instead of wrapping to the other side
+    /// (pipes and life-decay modes).
+    #[arg(long, verbatim_doc_comment)]
+    pub walled: bool,
+    /// Never let a pipe re-enter a cell it has visited itself. When a pipe is boxed in, it ends.
+    #[arg(short = 'a', long)]
+    pub self_avoid: bool,
+    /// Start each new pipe where the previous one has ended (with a new color), so the pipes form
+    /// one endless line snaking across the screen.
+    #[arg(long, verbatim_doc_comment)]
+    pub continuous: bool,
+    /// Draw a twin of every pipe in the complementary color, reflected through the center of the
+    /// screen (point) or shifted by half of it (offset). The pieces of both count towards
+    /// --max-drawn-pieces.
+    #[arg(long, num_args = 0..=1, default_missing_value = "point", value_enum, verbatim_doc_comment)]
+    pub mirror_pair: Option<MirrorPair>,
     /// Set of colors used for coloring each pipe.
     /// `None` disables this feature. Base colors are 16 colors predefined by the terminal.
     /// The RGB option is for terminals with true color support (all 16 million colors).
     #[arg(short, long, default_value_t, value_enum, verbatim_doc_comment)]
     pub palette: ColorPalette,
-    /// Enable gradient. Use only with RGB palette.
+    /// Pick the pipe colors from these colors instead of the palette. Each color may carry a
+    /// weight making it more likely, e.g. "#ff0000:5,#00ff00:1" (the default weight is 1).
+    #[arg(long, value_parser = parse_colors, conflicts_with = "color_walk", verbatim_doc_comment)]
+    pub colors: Option<CustomColors>,
+    /// Derive the color of each new pipe from the previous one, so the palette of the whole screen
+    /// slowly evolves. With RGB, the value is the maximal change of a color channel (0.0 - 1.0);
+    /// with base colors, it is the probability of switching to a neighbouring color.
+    #[arg(long, num_args = 0..=1, default_missing_value = "0.1", value_name = "STEP", verbatim_doc_comment)]
+    pub color_walk: Option<f32>,
+    /// Minimal luminance of random RGB colors (0.0 - 1.0). Darker colors are lightened, so they stay
+    /// visible on dark backgrounds.
+    #[arg(long, verbatim_doc_comment)]
+    pub min_luma: Option<f32>,
+    /// Maximal luminance of random RGB colors (0.0 - 1.0). Lighter colors are darkened, so they stay
+    /// visible on light backgrounds.
+    #[arg(long, verbatim_doc_comment)]
+    pub max_luma: Option<f32>,
+    /// Keep consecutive pipes visually distinguishable: reject new colors closer than this
+    /// distance (CIE76 ΔE, e.g. 25) to the color of the previous pipe or to the background.
+    #[arg(long, num_args = 0..=1, default_missing_value = "25", value_name = "DELTA_E", conflicts_with = "color_walk", verbatim_doc_comment)]
+    pub distinct_colors: Option<f32>,
+    /// Text attributes of the pipe pieces, separated by commas (e.g. bold,italic). Some fonts render
+    /// bold box-drawing characters noticeably thicker.
+    #[arg(long, value_enum, value_delimiter = ',', verbatim_doc_comment)]
+    pub attr: Vec<GlyphAttr>,
+    /// Gradually warm up the pipe colors (reduce blue) during the night hours.
+    #[arg(long)]
+    pub redshift: bool,
+    /// Redshift: the night hours in local time.
+    #[arg(long, default_value = "21-7", value_parser = parse_hour_range)]
+    pub night_hours: (u32, u32),
+    /// Enable gradient: pipes gradually lighten or darken. With base colors, pipes switch between
+    /// the normal and bright variants of their colors.
     #[arg(short, long)]
     pub gradient: bool,
     /// Gradient: the step to lighten/darken the color.
     #[arg(long, default_value_t = 0.005)]
     pub gradient_step: f32,
+    /// Scale the brightness of the corner pieces by this factor (e.g. 1.3 for brighter or 0.7 for
+    /// darker corners), so the turns of the pipes stand out. With base colors, the corners switch
+    /// to the bright or normal variant instead.
+    #[arg(long, verbatim_doc_comment)]
+    pub corner_tint: Option<f32>,
+    /// Color of the joints filling the columns skipped by wide piece sets (see `joint` in piece
+    /// set files), e.g. #808080.
+    #[arg(long, value_parser = parse_color, verbatim_doc_comment)]
+    pub joint_color: Option<SrgbaTuple>,
     /// In this mode multiple layers of pipes are drawn. If the number of currently drawn pieces in
     /// layer is >= layer_max_drawn_pieces, all pipe pieces are made darker and a new layer is created
     /// on top of them. See also darken_factor and darken_min. RGB palette only!
     #[arg(short, long, verbatim_doc_comment)]
     pub depth_mode: bool,
-    /// Depth-mode: maximum drawn pipe pieces in the current layer.
-    #[arg(long, default_value_t = 1000)]
-    pub layer_max_drawn_pieces: u64,
+    /// Depth-mode: maximum drawn pipe pieces in the current layer, or a multiple of the screen
+    /// area (e.g. 0.5x). Set it to 0 to start new layers only by --layer-interval or
+    /// --layer-coverage.
+    #[arg(long, default_value = "1000", value_parser = parse_piece_budget, verbatim_doc_comment)]
+    pub layer_max_drawn_pieces: PieceBudget,
+    /// Depth-mode: also start a new layer after the current one has been drawn for this time (e.g.
+    /// 30s).
+    #[arg(long, value_parser = parse_duration, verbatim_doc_comment)]
+    pub layer_interval: Option<Duration>,
+    /// Depth-mode: also start a new layer when the pipes of the current one cover this percentage
+    /// of the screen (e.g. 40). Unlike piece counts, it works the same on any terminal size.
+    #[arg(long, value_name = "PERCENT", value_parser = 1..=100, verbatim_doc_comment)]
+    pub layer_coverage: Option<i64>,
     /// Depth-mode: how much to darken pipe pieces in previous layers?
     #[arg(short = 'F', long, default_value_t = 0.8)]
     pub darken_factor: f32,
     /// Depth-mode: the color to gradually darken to.
     #[arg(short = 'M', long, default_value = "#000000")]
     pub darken_min: String,
+    /// Give each pipe a random depth. Deeper pipes are drawn dimmer and never overdraw
+    /// shallower ones.
+    #[arg(short = 'z', long, verbatim_doc_comment)]
+    pub z_depth: bool,
+    /// Z-depth: brightness of the deepest pipes (0.0 - 1.0).
+    #[arg(long, default_value_t = 0.3)]
+    pub z_depth_min_brightness: f32,
+    /// Pulses of light flow along the drawn pipes, like liquid running through them. Not shown
+    /// with --reduced-motion.
+    #[arg(long, verbatim_doc_comment)]
+    pub flow_animation: bool,
+    /// Pipes preferentially fill the strokes of this text rendered in large letters, so it
+    /// gradually emerges out of the pipework.
+    #[arg(long, verbatim_doc_comment)]
+    pub trace_text: Option<String>,
+    /// Pipes preferentially grow over the bright parts of this image (PGM or PPM), slowly
+    /// revealing the picture. With the RGB palette, pipes take the colors of the image.
+    #[arg(long, conflicts_with = "trace_text", verbatim_doc_comment)]
+    pub trace_image: Option<PathBuf>,
+    /// React to the system load (sampled once a second): the busier the system, the more often
+    /// new pipes spawn and the brighter their colors are (Linux only).
+    #[arg(long, value_enum, verbatim_doc_comment)]
+    pub react: Option<Metric>,
+    /// Listen for remote commands over HTTP on this address (e.g. 127.0.0.1:7878). The path is the
+    /// command (flash, clear or text) and the body is its argument, e.g. a color or a message.
+    #[cfg(feature = "remote")]
+    #[arg(long, verbatim_doc_comment)]
+    pub listen: Option<String>,
+    /// Subscribe to remote commands on this MQTT topic (requires mosquitto_sub). Messages are
+    /// e.g. `flash #ff0000`, `clear` or `text Build failed`.
+    #[cfg(feature = "remote")]
+    #[arg(long, verbatim_doc_comment)]
+    pub mqtt_topic: Option<String>,
+    /// Host of the MQTT broker.
+    #[cfg(feature = "remote")]
+    #[arg(long, default_value = "localhost")]
+    pub mqtt_host: String,
+    /// Send a desktop notification at milestones of a long run: every 24 hours of uptime and
+    /// 1 million, 10 million, ... pieces drawn.
+    #[cfg(feature = "notifications")]
+    #[arg(long, verbatim_doc_comment)]
+    pub notify_milestones: bool,
+    /// Seed of the random number generator, for reproducible runs.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Replay the seed and theme of a bookmark (N-th line of the bookmarks file in the config
+    /// directory, added with the `b` key).
+    #[arg(long, value_name = "N", value_parser = 1.., conflicts_with = "seed")]
+    pub from_bookmark: Option<i64>,
+    /// Broadcast the simulation to other instances connecting to this address (e.g.
+    /// 0.0.0.0:7879), so they render it in lockstep. The terminals should have the same size.
+    #[arg(long, conflicts_with = "sync_connect", verbatim_doc_comment)]
+    pub sync_server: Option<String>,
+    /// Render the simulation of the instance running --sync-server at this address in lockstep.
+    #[arg(long)]
+    pub sync_connect: Option<String>,
+    /// Text or glyph bouncing around the screen on top of the pipes.
+    #[arg(long)]
+    pub bouncer: Option<String>,
+    /// Show a large countdown (e.g. 25m) in the corner of the screen. When it elapses, the screen
+    /// flashes and is cleared.
+    #[arg(long, value_parser = parse_duration, verbatim_doc_comment)]
+    pub timer: Option<Duration>,
+    /// When running inside tmux, animate only after the user has been idle for this time (e.g. 5m)
+    /// and stop as soon as the activity resumes. GNU screen is not supported; use its `idle` and
+    /// `blankerprg` commands to launch rxpipes instead.
+    #[arg(long, value_parser = parse_duration, verbatim_doc_comment)]
+    pub idle_start: Option<Duration>,
+    /// Run as a screensaver daemon: watch the idle time of the session (requires xprintidle on X11
+    /// or swayidle on Wayland) and launch the animation in a new terminal after --idle-start (5m
+    /// by default), closing it as soon as the activity resumes.
+    #[arg(long, verbatim_doc_comment)]
+    pub daemon: bool,
+    /// Daemon: command launching a terminal which runs the command appended to it.
+    #[arg(long, default_value = "xterm -fullscreen -e")]
+    pub daemon_terminal: String,
+    /// Run the animation in a child process and restore the terminal if it dies abruptly (e.g.
+    /// killed by the OOM killer or SIGKILL), which the panic handler can't cover.
+    #[arg(long, conflicts_with = "daemon", verbatim_doc_comment)]
+    pub supervised: bool,
+    /// Quit on any key press and then run this command (via `sh -c`), e.g. `loginctl lock-session`.
+    /// Useful for putting rxpipes in front of a real screen locker.
+    #[arg(long, verbatim_doc_comment)]
+    pub until_keypress_cmd: Option<String>,
+    /// Run this command (via `sh -c`) on the events chosen with --sound-events, e.g.
+    /// `paplay /usr/share/sounds/freedesktop/stereo/bell.oga`. The name of the event is passed in
+    /// the RXPIPES_EVENT environment variable. Events coming less than a second after the last
+    /// run are ignored.
+    #[arg(long, value_name = "CMD", verbatim_doc_comment)]
+    pub sound_cmd: Option<String>,
+    /// Events running --sound-cmd, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "clear,layer")]
+    pub sound_events: Vec<SoundEvent>,
+    /// Draw only in the region of the terminal (COLSxROWS+X+Y, e.g. 80x24+10+5) instead of the
+    /// whole screen. Useful for tiling several instances across a big terminal.
+    #[arg(long, value_parser = parse_geometry, verbatim_doc_comment)]
+    pub geometry: Option<Geometry>,
+    /// Run many small independent screensavers in a grid of tiles (COLSxROWS, e.g. 8x4), each
+    /// clearing on its own. The stats, widgets, sync and remote commands are turned off; only q,
+    /// Esc and Space (pause) are handled.
+    #[arg(long, value_parser = parse_size, conflicts_with_all = ["daemon", "ticker_line"], verbatim_doc_comment)]
+    pub mosaic: Option<(usize, usize)>,
+    /// Show the output of this command (run via `sh -c`, e.g. uptime) in a box in the middle of the
+    /// screen, with the pipes animating only in the band around it. Its colors are stripped. The
+    /// command runs in the background and is killed after 5 seconds (e.g. htop), showing what it
+    /// has printed so far.
+    #[arg(long, verbatim_doc_comment)]
+    pub wrap_cmd: Option<String>,
+    /// Run --wrap-cmd again this often (e.g. 5s) to refresh its output.
+    #[arg(long, value_parser = parse_duration, requires = "wrap_cmd")]
+    pub wrap_interval: Option<Duration>,
+    /// Print a single line of slowly evolving pipes as plain text and exit. Each run advances the
+    /// pipes by one step (the state is kept in ~/.local/state/rxpipes). Suitable for embedding
+    /// into the tmux status line.
+    #[arg(long, verbatim_doc_comment)]
+    pub ticker_line: bool,
+    /// Ticker-line: width of the line.
+    #[arg(long, default_value_t = 20)]
+    pub ticker_width: usize,
     /// Color of the background.
     #[arg(short = 'b', long)]
     pub bg_color: Option<String>,
+    /// Cycle the background through these colors separated by commas (e.g.
+    /// "#101010,#001020,#100020"), advancing to the next one on each screen clear.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with = "bg_color",
+        verbatim_doc_comment
+    )]
+    pub bg_cycle: Vec<String>,
     /// A default set of pieces to use.
     /// Available piece sets:
     /// 0 - ASCII pipes:
@@ -80,17 +697,513 @@ pub struct Config {
     /// This parameter expects a numeric ID.
     #[arg(short = 'P', long, default_value_t = 6, value_parser = 0..=6, verbatim_doc_comment)]
     pub piece_set: i64,
+    /// Load the custom piece set from a file (see `rxpipes design`).
+    #[arg(long, conflicts_with = "custom-piece-set")]
+    pub piece_set_file: Option<PathBuf>,
     /// A string representing custom piece set (takes precedence over -P/--piece-set).
     /// The string must have length of 6 characters. Write it according to `│─┌┐└┘`.
-    /// This string must define all 6 pieces, otherwise rxpipes will crash.
+    /// This string must define all 6 pieces.
     /// Unicode grapheme clusters are supported and treated as single characters.
+    /// A piece written as glyphs in brackets, e.g. `[▖▘▝▗]`, is animated by cycling them.
     #[arg(name = "custom-piece-set", short = 'c', long, verbatim_doc_comment)]
     pub custom_piece_set_: Option<String>,
+    /// Use a theme: a named combination of palette, piece set, gradient and background color.
+    /// Accepts a name of a built-in theme, of a theme in the config directory or a path to a theme
+    /// file. Options set explicitly take precedence over the theme (see `rxpipes themes`).
+    #[arg(long, value_name = "NAME|FILE")]
+    pub theme: Option<String>,
+    /// Switch themes at local times while running, e.g. "08:00=paper,20:00=midnight". Options set
+    /// explicitly take precedence over the themes.
+    #[arg(long, value_parser = parse_schedule, conflicts_with = "theme", verbatim_doc_comment)]
+    pub schedule: Option<Schedule>,
+    /// Randomize the style of each run within the listed aspects (all of them if none are
+    /// listed), e.g. "palette,piece-set". Options set explicitly are kept. With --seed, the same
+    /// style is picked each time.
+    #[arg(long, value_enum, value_delimiter = ',', num_args = 0..=1, value_name = "ASPECTS", verbatim_doc_comment)]
+    pub surprise_me: Option<Vec<Surprise>>,
+    /// Switch to the next built-in theme (and so piece set) whenever the screen is cleared, so
+    /// long-running sessions evolve through all styles. The pipes fade out before each switch.
+    #[arg(long, conflicts_with = "schedule", verbatim_doc_comment)]
+    pub cycle_on_clear: bool,
+    /// Mimic the defaults and flags of another program. With pipes.sh, its flags -p, -t, -c, -f,
+    /// -s, -r, -R, -B, -C and -K replace the short options of rxpipes.
+    #[arg(long, value_enum, verbatim_doc_comment)]
+    pub compat: Option<Compat>,
     /// Show statistics in the bottom of screen (how many pieces drawn, pipes drawn, etc.)
     #[arg(short = 's', long)]
     pub show_stats: bool,
+    /// Draw the stats widget in a box with rounded corners.
+    #[arg(long)]
+    pub stats_box: bool,
+    /// How the stats widget labels its values.
+    #[arg(long, default_value_t, value_enum)]
+    pub stats_style: StatsStyle,
+    /// Refresh the stats widget at most this often (e.g. 1s) instead of every frame.
+    #[arg(long, value_parser = parse_duration)]
+    pub stats_interval: Option<Duration>,
+    /// Opacity of the layers drawn on top of the pipes (stats, countdown, texts), from 0 to 1.
+    /// Below 1, their background is blended with the pipes, which show through blank cells.
+    #[arg(long, default_value_t = 1.0, verbatim_doc_comment)]
+    pub overlay_opacity: f32,
+    /// Where to show the badge indicating that the animation is paused.
+    #[arg(long, default_value_t, value_enum)]
+    pub pause_badge: BadgePosition,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    // TODO: implement validation of length for custom-piece-set.
     #[clap(skip)]
     pub custom_piece_set: Option<Vec<String>>,
+    /// Behavior of pipes.sh without an rxpipes option (see --compat).
+    #[clap(skip)]
+    pub pipes_sh: Option<PipesSh>,
+    /// Metadata of the custom piece set loaded from a file.
+    #[clap(skip)]
+    pub custom_piece_set_meta: Option<PieceSetMeta>,
+    /// Background color of the terminal, if it could be detected.
+    #[clap(skip)]
+    pub term_bg_color: Option<SrgbaTuple>,
+    /// Colors supported by the terminal, if the configuration has been adapted to them.
+    #[clap(skip)]
+    pub term_color_level: Option<ColorLevel>,
+    /// IDs of options which were set explicitly by the user.
+    #[clap(skip)]
+    explicit_args: Vec<String>,
+}
+
+impl Config {
+    /// Build the CLI parser. Every option can also be set by an environment variable (see
+    /// [`env_var`]); options given on the command line take precedence. Options can be given after
+    /// the subcommands too.
+    pub fn cli() -> clap::Command {
+        Self::command().mut_args(|arg| match arg.get_long().map(env_var) {
+            Some(var) => arg.env(var).global(true),
+            None => arg,
+        })
+    }
+
+    /// Remember which options were set explicitly by the user (not by default values).
+    pub fn record_explicit_args(&mut self, matches: &ArgMatches) {
+        self.explicit_args = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) != Some(ValueSource::DefaultValue))
+            .map(|id| id.to_string())
+            .collect();
+    }
+
+    /// Check whether the option was set explicitly by the user.
+    pub fn is_explicit(&self, id: &str) -> bool {
+        self.explicit_args.iter().any(|a| a == id)
+    }
+
+    /// Restrict the style to what any terminal can show (see --safe-mode): the ASCII piece set
+    /// without colors or text attributes.
+    pub fn apply_safe_mode(&mut self) {
+        self.piece_set = 0;
+        self.custom_piece_set = None;
+        self.custom_piece_set_meta = None;
+        self.palette = ColorPalette::None;
+        self.colors = None;
+        self.gradient = false;
+        self.attr.clear();
+        self.bg_color = None;
+        self.bg_cycle.clear();
+        self.stats_box = false;
+    }
+
+    /// Replace the piece sets and colors which the Linux virtual console can't show (see
+    /// --console). Options set explicitly are kept.
+    pub fn apply_console(&mut self) {
+        // The console font has only the thin and double box-drawing characters
+        if !self.is_explicit("piece_set") && matches!(self.piece_set, 4 | 6) {
+            self.piece_set = 3;
+        }
+
+        if !self.is_explicit("palette") && self.palette == ColorPalette::Rgb {
+            self.palette = ColorPalette::BaseColors;
+        }
+    }
+
+    /// Adapt the configuration to the colors supported by the terminal, so the colors it can't show
+    /// are replaced instead of being drawn as the default color. Returns a warning for each
+    /// replaced option.
+    pub fn adapt_to_colors(&mut self, level: ColorLevel) -> Vec<String> {
+        let mut warnings = vec![];
+        let hint = "set COLORTERM=truecolor if it does";
+
+        self.term_color_level = Some(level);
+
+        if level == ColorLevel::TrueColor {
+            return warnings;
+        }
+
+        if self.palette == ColorPalette::Rgb {
+            self.palette = ColorPalette::BaseColors;
+            warnings.push(format!(
+                "the terminal doesn't support true colors, using the base colors instead of \
+                 --palette rgb ({hint})"
+            ));
+        }
+
+        if self.colors.take().is_some() {
+            warnings.push(format!(
+                "the terminal doesn't support true colors, ignoring --colors ({hint})"
+            ));
+        }
+
+        if self.bg_color.take().is_some() | !mem::take(&mut self.bg_cycle).is_empty() {
+            warnings.push(format!(
+                "the terminal doesn't support true colors, ignoring the background color ({hint})"
+            ));
+        }
+
+        if self.joint_color.take().is_some() {
+            warnings.push(format!(
+                "the terminal doesn't support true colors, ignoring --joint-color ({hint})"
+            ));
+        }
+
+        warnings
+    }
+
+    /// Retrieve the luminance range of RGB colors, if it's limited by --min-luma or --max-luma.
+    pub fn luma_range(&self) -> Option<(f32, f32)> {
+        if self.min_luma.is_none() && self.max_luma.is_none() {
+            return None;
+        }
+
+        Some((self.min_luma.unwrap_or(0.0), self.max_luma.unwrap_or(1.0)))
+    }
+
+    /// Check the options for values which would crash the screensaver or make no sense. All
+    /// problems are reported at once, each with a suggested fix.
+    pub fn validate(&self) -> Result<()> {
+        // (problem, suggested fix)
+        let mut problems: Vec<(String, String)> = vec![];
+        let mut check = |ok: bool, problem: String, fix: &str| {
+            if !ok {
+                problems.push((problem, fix.to_string()));
+            }
+        };
+
+        check(
+            self.min_pipe_length > 0,
+            "--min-pipe-length must not be 0".to_string(),
+            "use at least 1",
+        );
+        check(
+            self.min_pipe_length <= self.max_pipe_length,
+            format!(
+                "--min-pipe-length ({}) is greater than --max-pipe-length ({})",
+                self.min_pipe_length, self.max_pipe_length
+            ),
+            "lower --min-pipe-length or raise --max-pipe-length",
+        );
+        check(
+            (0.0..=1.0).contains(&self.turning_prob),
+            format!(
+                "--turning-prob ({}) is not a probability",
+                self.turning_prob
+            ),
+            "use a value between 0 and 1, e.g. 0.2 for 20 %",
+        );
+        check(
+            !self.gradient || self.palette != ColorPalette::None,
+            "--gradient has no effect without colors (--palette none)".to_string(),
+            "choose --palette base-colors or rgb, or drop --gradient",
+        );
+        check(
+            !self.walled
+                || matches!(
+                    self.mode,
+                    Mode::Pipes | Mode::LifeDecay | Mode::Weather | Mode::GitActivity
+                ),
+            "--walled works only in the pipes, life-decay, weather and git-activity modes"
+                .to_string(),
+            "drop --walled or use --mode pipes",
+        );
+        check(
+            self.mode != Mode::Weather || self.weather_cmd.is_some() || self.weather_file.is_some(),
+            "--mode weather needs to know the weather".to_string(),
+            "add --weather-cmd or --weather-file, e.g. --weather-cmd 'curl -s wttr.in/?format=%C'",
+        );
+        check(
+            self.mode != Mode::GitActivity || self.repo.is_some(),
+            "--mode git-activity needs a repository to watch".to_string(),
+            "add --repo, e.g. --repo .",
+        );
+        check(
+            self.spawn_on_line.is_none() || !matches!(self.mode, Mode::Maze | Mode::Curve),
+            "--spawn-on-line has no effect in the maze and curve modes".to_string(),
+            "drop --spawn-on-line or use --mode pipes",
+        );
+        check(
+            !self.stdin_mode || !io::stdin().is_terminal(),
+            "--stdin-mode needs input piped to rxpipes".to_string(),
+            "pipe something in, e.g. `cat /dev/urandom | rxpipes --stdin-mode`",
+        );
+        check(
+            self.mode != Mode::LifeDecay || self.decay_steps > 0,
+            "--decay-steps must not be 0 in the life-decay mode".to_string(),
+            "use at least 1, e.g. 30",
+        );
+        check(
+            self.wrap_interval.is_none_or(|i| !i.is_zero()),
+            "--wrap-interval must not be 0".to_string(),
+            "use e.g. 5s, or drop it to run --wrap-cmd only once",
+        );
+        check(
+            !self.weather_interval.is_zero(),
+            "--weather-interval must not be 0".to_string(),
+            "use e.g. 10m",
+        );
+        check(
+            self.gradient_step > 0.0 && self.gradient_step <= 1.0,
+            format!("--gradient-step ({}) is out of range", self.gradient_step),
+            "use a value greater than 0 and at most 1, e.g. 0.005",
+        );
+        if let Some(step) = self.color_walk {
+            check(
+                step > 0.0 && step <= 1.0,
+                format!("--color-walk ({step}) is out of range"),
+                "use a value greater than 0 and at most 1, e.g. 0.1",
+            );
+        }
+
+        if let Some(d) = self.distinct_colors {
+            check(
+                (0.0..=100.0).contains(&d),
+                format!("--distinct-colors ({d}) is out of range"),
+                "use a distance between 0 and 100, e.g. 25",
+            );
+        }
+
+        let (min_luma, max_luma) = self.luma_range().unwrap_or((0.0, 1.0));
+
+        check(
+            (0.0..=1.0).contains(&min_luma) && (0.0..=1.0).contains(&max_luma),
+            "--min-luma and --max-luma must be between 0 and 1".to_string(),
+            "use e.g. --min-luma 0.2 --max-luma 0.8",
+        );
+        check(
+            min_luma <= max_luma,
+            format!("--min-luma ({min_luma}) is greater than --max-luma ({max_luma})"),
+            "lower --min-luma or raise --max-luma",
+        );
+
+        check(
+            self.darken_factor > 0.0 && self.darken_factor <= 1.0,
+            format!("--darken-factor ({}) is out of range", self.darken_factor),
+            "use a value greater than 0 and at most 1, e.g. 0.8",
+        );
+        check(
+            HexColor::parse_rgb(&self.darken_min).is_ok(),
+            format!("--darken-min (`{}`) is not a color", self.darken_min),
+            "write it as #rrggbb, e.g. #000000",
+        );
+        check(
+            !self.depth_mode
+                || !self.layer_max_drawn_pieces.is_zero()
+                || self.layer_interval.is_some()
+                || self.layer_coverage.is_some(),
+            "--layer-max-drawn-pieces must not be 0 in depth mode".to_string(),
+            "use at least 1, e.g. 1000, or set --layer-interval or --layer-coverage",
+        );
+        check(
+            (0.0..=1.0).contains(&self.z_depth_min_brightness),
+            format!(
+                "--z-depth-min-brightness ({}) is out of range",
+                self.z_depth_min_brightness
+            ),
+            "use a value between 0 and 1, e.g. 0.3",
+        );
+        check(
+            self.corner_tint.is_none_or(|t| t > 0.0),
+            format!(
+                "--corner-tint ({}) must be greater than 0",
+                self.corner_tint.unwrap_or_default()
+            ),
+            "use e.g. 1.3 for brighter or 0.7 for darker corners",
+        );
+        check(
+            (0.0..=1.0).contains(&self.overlay_opacity),
+            format!(
+                "--overlay-opacity ({}) is out of range",
+                self.overlay_opacity
+            ),
+            "use a value between 0 and 1, e.g. 0.7",
+        );
+
+        if let Some(c) = &self.bg_color {
+            check(
+                HexColor::parse_rgb(c).is_ok(),
+                format!("--bg-color (`{c}`) is not a color"),
+                "write it as #rrggbb, e.g. #1e1e2e",
+            );
+        }
+
+        for c in &self.bg_cycle {
+            check(
+                HexColor::parse_rgb(c).is_ok(),
+                format!("--bg-cycle (`{c}`) is not a color"),
+                "write the colors as #rrggbb separated by commas, e.g. #101010,#001020",
+            );
+        }
+
+        if let Some(pieces) = &self.custom_piece_set {
+            check(
+                pieces.len() == PIECE_COUNT,
+                format!(
+                    "the custom piece set has {} pieces instead of {PIECE_COUNT}",
+                    pieces.len()
+                ),
+                "write all 6 pieces according to `│─┌┐└┘`",
+            );
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let report: String = problems
+            .iter()
+            .map(|(problem, fix)| format!("\n  - {problem}\n    hint: {fix}"))
+            .collect();
+
+        bail!("invalid configuration:{report}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse the command line arguments (without the program name).
+    fn config(args: &[&str]) -> Config {
+        Config::try_parse_from(["rxpipes"].iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn geometry_with_position() {
+        let g = parse_geometry("80x24+10+5").unwrap();
+
+        assert_eq!(g.size, (80, 24));
+        assert_eq!(g.pos, Point { x: 10, y: 5 });
+    }
+
+    #[test]
+    fn geometry_without_position() {
+        let g = parse_geometry("1x1").unwrap();
+
+        assert_eq!(g.size, (1, 1));
+        assert_eq!(g.pos, Point { x: 0, y: 0 });
+        assert_eq!(parse_geometry("1x40+3").unwrap().pos, Point { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn invalid_geometry() {
+        for s in [
+            "",
+            "80",
+            "80x",
+            "x24",
+            "0x24",
+            "80x0",
+            "80x24+1+2+3",
+            "80x24+-1",
+            "axb",
+        ] {
+            assert!(parse_geometry(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("1x1"), Ok((1, 1)));
+        assert_eq!(parse_size("1x200"), Ok((1, 200)));
+        assert_eq!(parse_size("200x1"), Ok((200, 1)));
+    }
+
+    #[test]
+    fn size_with_position() {
+        assert!(parse_size("80x24+0+0").is_err());
+        assert!(parse_size("80x24+1").is_err());
+        assert!(parse_size("0x0").is_err());
+    }
+
+    #[test]
+    fn piece_budget() {
+        assert_eq!(parse_piece_budget("10000"), Ok(PieceBudget::Pieces(10000)));
+        assert_eq!(parse_piece_budget("0"), Ok(PieceBudget::Pieces(0)));
+        assert_eq!(parse_piece_budget("5x"), Ok(PieceBudget::PerArea(5.0)));
+        assert_eq!(parse_piece_budget("0.5x"), Ok(PieceBudget::PerArea(0.5)));
+
+        for s in ["", "x", "-1", "-1x", "infx", "NaNx", "5y", "1.5"] {
+            assert!(parse_piece_budget(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn piece_budget_on_degenerate_screens() {
+        assert_eq!(PieceBudget::PerArea(5.0).pieces((1, 1)), 5);
+        assert_eq!(PieceBudget::PerArea(0.5).pieces((1, 7)), 4);
+        assert_eq!(PieceBudget::Pieces(20).pieces((1, 1)), 20);
+        assert!(PieceBudget::PerArea(0.0).is_zero());
+        assert!(!PieceBudget::PerArea(0.1).is_zero());
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(5 * 60)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(60 * 60)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+
+        for s in ["", "s", "5d", "1.5m", "-1", "5 m"] {
+            assert!(parse_duration(s).is_err(), "{s}");
+        }
+
+        assert_eq!(
+            parse_duration("18446744073709551615s"),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+
+        for s in ["18446744073709551615m", "5124095576030432h"] {
+            assert_eq!(
+                parse_duration(s),
+                Err(format!("duration `{s}` is out of range"))
+            );
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(config(&[]).validate().is_ok());
+    }
+
+    #[test]
+    fn zero_decay_steps_rejected() {
+        assert!(config(&["--mode", "life-decay", "--decay-steps", "0"])
+            .validate()
+            .is_err());
+        assert!(config(&["--mode", "life-decay", "--decay-steps", "1"])
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn zero_wrap_interval_rejected() {
+        let wrap = |interval| config(&["--wrap-cmd", "date", "--wrap-interval", interval]);
+
+        assert!(wrap("0").validate().is_err());
+        assert!(wrap("0m").validate().is_err());
+        assert!(wrap("1s").validate().is_ok());
+    }
+
+    #[test]
+    fn zero_weather_interval_rejected() {
+        assert!(config(&["--weather-interval", "0"]).validate().is_err());
+        assert!(config(&["--weather-interval", "1s"]).validate().is_ok());
+    }
 }
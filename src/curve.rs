@@ -0,0 +1,403 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::plane_2d::{Direction, Point};
+use clap::ValueEnum;
+use rand::{seq::SliceRandom, Rng};
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+/// Frequency ratios of the Lissajous figures.
+const LISSAJOUS_RATIOS: [(f64, f64); 6] =
+    [(1., 2.), (3., 2.), (3., 4.), (5., 4.), (5., 6.), (7., 6.)];
+
+/// Horizontal and vertical distance between the rings of the spiral.
+const SPIRAL_GAP: (isize, isize) = (3, 2);
+
+#[derive(Copy, Clone, Eq, Default, PartialEq, Debug, ValueEnum)]
+pub enum CurveKind {
+    /// Space-filling Hilbert curve.
+    #[default]
+    Hilbert,
+    /// Closed Lissajous figure.
+    Lissajous,
+    /// Rectangular spiral winding towards the center.
+    Spiral,
+}
+
+/// A piece of a curve.
+#[derive(Copy, Clone, Debug)]
+pub struct CurvePiece {
+    /// Position of the piece on the screen.
+    pub pos: Point,
+    /// Direction of the preceeding piece.
+    pub prev_dir: Direction,
+    /// Direction of the piece.
+    pub dir: Direction,
+}
+
+/// A curve traced piece by piece.
+#[derive(Debug)]
+pub struct Curve {
+    /// Cells of the curve, each one is next to the previous one.
+    path: Vec<Point>,
+    /// Indicates that the curve ends where it begins.
+    closed: bool,
+    /// Index of the next piece.
+    next: usize,
+}
+
+impl Curve {
+    /// Create a `Curve` of specified kind fitting into the screen of specified size.
+    pub fn new(kind: CurveKind, scr_size: (usize, usize), rng: &mut impl Rng) -> Self {
+        let w = scr_size.0.max(1) as isize;
+        let h = scr_size.1.max(1) as isize;
+
+        let (vertices, closed) = match kind {
+            CurveKind::Hilbert => (hilbert(w, h), false),
+            CurveKind::Lissajous => {
+                let ratio = *LISSAJOUS_RATIOS.choose(rng).unwrap_or(&LISSAJOUS_RATIOS[0]);
+
+                (lissajous(w, h, ratio), true)
+            }
+            CurveKind::Spiral => (spiral(w, h), false),
+        };
+
+        let mut path: Vec<Point> = vec![];
+
+        for v in vertices {
+            connect(&mut path, v);
+        }
+
+        // A curve squeezed into a single cell is just that cell
+        if closed && path.len() > 1 {
+            let first = path[0];
+
+            connect(&mut path, first);
+            path.pop();
+        }
+
+        Self {
+            path,
+            closed,
+            next: 0,
+        }
+    }
+
+    /// Retrieve the next piece of the curve. Returns `None` when the curve is complete.
+    pub fn next_piece(&mut self) -> Option<CurvePiece> {
+        let len = self.path.len();
+        let i = self.next;
+        let pos = *self.path.get(i)?;
+
+        let at = |i: usize| self.path[i % len];
+
+        let dir = if i + 1 < len || self.closed {
+            direction(pos, at(i + 1))
+        } else {
+            None
+        };
+
+        let prev_dir = if i > 0 || self.closed {
+            direction(at(i + len - 1), pos)
+        } else {
+            None
+        };
+
+        self.next += 1;
+
+        Some(CurvePiece {
+            pos,
+            prev_dir: prev_dir.or(dir).unwrap_or(Direction::Right),
+            dir: dir.or(prev_dir).unwrap_or(Direction::Right),
+        })
+    }
+}
+
+/// Extend the path to the point with horizontal and vertical steps.
+fn connect(path: &mut Vec<Point>, to: Point) {
+    let Some(&last) = path.last() else {
+        path.push(to);
+        return;
+    };
+
+    let mut p = last;
+
+    while p.x != to.x {
+        p.x += (to.x - p.x).signum();
+        path.push(p);
+    }
+
+    while p.y != to.y {
+        p.y += (to.y - p.y).signum();
+        path.push(p);
+    }
+}
+
+/// Direction of the step between two neighbouring cells.
+fn direction(from: Point, to: Point) -> Option<Direction> {
+    match (to.x - from.x, to.y - from.y) {
+        (0, -1) => Some(Direction::Up),
+        (0, 1) => Some(Direction::Down),
+        (1, 0) => Some(Direction::Right),
+        (-1, 0) => Some(Direction::Left),
+        _ => None,
+    }
+}
+
+/// Vertices of the largest Hilbert curve which fits into the screen, centered.
+///
+/// Columns are twice as far apart as rows, since the terminal cells are about twice as tall as
+/// they are wide.
+fn hilbert(w: isize, h: isize) -> Vec<Point> {
+    let mut side = 1;
+
+    while side * 2 <= h && (side * 2 - 1) * 2 < w {
+        side *= 2;
+    }
+
+    let offset = Point {
+        x: (w - ((side - 1) * 2 + 1)) / 2,
+        y: (h - side) / 2,
+    };
+
+    (0..side * side)
+        .map(|d| {
+            let (x, y) = hilbert_d2xy(side, d);
+
+            Point {
+                x: offset.x + x * 2,
+                y: offset.y + y,
+            }
+        })
+        .collect()
+}
+
+/// Convert a distance along the Hilbert curve to coordinates in a square of specified side (a power
+/// of two).
+fn hilbert_d2xy(side: isize, d: isize) -> (isize, isize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+/// Vertices of a Lissajous figure with specified frequency ratio spanning the whole screen.
+fn lissajous(w: isize, h: isize, (a, b): (f64, f64)) -> Vec<Point> {
+    let rx = (w - 1) as f64 / 2.;
+    let ry = (h - 1) as f64 / 2.;
+    let samples = ((w + h) as f64 * a.max(b) * 2.) as usize;
+
+    (0..samples)
+        .map(|i| {
+            let t = TAU * i as f64 / samples as f64;
+
+            Point {
+                x: (rx + rx * (a * t + FRAC_PI_2).sin()).round() as isize,
+                y: (ry + ry * (b * t).sin()).round() as isize,
+            }
+        })
+        .collect()
+}
+
+/// Vertices of a rectangular spiral starting in the top left corner.
+fn spiral(w: isize, h: isize) -> Vec<Point> {
+    let (gx, gy) = SPIRAL_GAP;
+    let (mut l, mut t, mut r, mut b) = (0, 0, w - 1, h - 1);
+    let mut res = vec![Point { x: l, y: t }];
+
+    loop {
+        res.push(Point { x: r, y: t });
+        t += gy;
+
+        if t > b {
+            break;
+        }
+
+        res.push(Point { x: r, y: b });
+        r -= gx;
+
+        if r < l {
+            break;
+        }
+
+        res.push(Point { x: l, y: b });
+        b -= gy;
+
+        if b < t {
+            break;
+        }
+
+        res.push(Point { x: l, y: t });
+        l += gx;
+
+        if l > r {
+            break;
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::HashSet;
+
+    const KINDS: [CurveKind; 3] = [CurveKind::Hilbert, CurveKind::Lissajous, CurveKind::Spiral];
+
+    /// Screen sizes including the degenerate ones.
+    const SIZES: [(usize, usize); 8] = [
+        (1, 1),
+        (1, 7),
+        (7, 1),
+        (2, 2),
+        (3, 2),
+        (0, 0),
+        (80, 24),
+        (213, 57),
+    ];
+
+    fn is_inside(p: Point, (w, h): (usize, usize)) -> bool {
+        (0..w.max(1) as isize).contains(&p.x) && (0..h.max(1) as isize).contains(&p.y)
+    }
+
+    fn is_neighbour(a: Point, b: Point) -> bool {
+        (a.x - b.x).abs() + (a.y - b.y).abs() == 1
+    }
+
+    #[test]
+    fn curves_are_continuous_and_fit_into_screen() {
+        for kind in KINDS {
+            for size in SIZES {
+                for seed in 0..LISSAJOUS_RATIOS.len() as u64 * 2 {
+                    let c = Curve::new(kind, size, &mut StdRng::seed_from_u64(seed));
+
+                    assert!(!c.path.is_empty(), "{kind:?} {size:?}");
+                    assert!(
+                        c.path.iter().all(|p| is_inside(*p, size)),
+                        "{kind:?} {size:?}"
+                    );
+                    assert!(
+                        c.path.windows(2).all(|w| is_neighbour(w[0], w[1])),
+                        "{kind:?} {size:?}"
+                    );
+
+                    if c.closed && c.path.len() > 1 {
+                        assert!(is_neighbour(c.path[c.path.len() - 1], c.path[0]));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn curves_end() {
+        for kind in KINDS {
+            for size in SIZES {
+                let mut c = Curve::new(kind, size, &mut StdRng::seed_from_u64(0));
+                let len = c.path.len();
+                let mut pieces = 0;
+
+                while c.next_piece().is_some() {
+                    pieces += 1;
+                }
+
+                assert_eq!(pieces, len, "{kind:?} {size:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn single_cell_lissajous() {
+        let mut c = Curve::new(CurveKind::Lissajous, (1, 1), &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(c.path, [Point { x: 0, y: 0 }]);
+        assert!(c.next_piece().is_some());
+        assert!(c.next_piece().is_none());
+    }
+
+    #[test]
+    fn pieces_follow_path() {
+        let mut c = Curve::new(CurveKind::Spiral, (30, 10), &mut StdRng::seed_from_u64(0));
+        let mut prev: Option<CurvePiece> = None;
+
+        while let Some(p) = c.next_piece() {
+            if let Some(prev) = prev {
+                assert_eq!(p.prev_dir, prev.dir);
+            }
+
+            prev = Some(p);
+        }
+    }
+
+    #[test]
+    fn hilbert_visits_each_cell_of_square_once() {
+        for side in [1, 2, 4, 8, 16] {
+            let cells: HashSet<(isize, isize)> =
+                (0..side * side).map(|d| hilbert_d2xy(side, d)).collect();
+
+            assert_eq!(cells.len(), (side * side) as usize);
+            assert!(cells
+                .iter()
+                .all(|&(x, y)| (0..side).contains(&x) && (0..side).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn hilbert_on_narrow_screens() {
+        assert_eq!(hilbert(1, 1), [Point { x: 0, y: 0 }]);
+        assert_eq!(hilbert(1, 9), [Point { x: 0, y: 4 }]);
+        assert_eq!(hilbert(9, 1), [Point { x: 4, y: 0 }]);
+        assert_eq!(hilbert(7, 4).len(), 16);
+        assert_eq!(hilbert(6, 4).len(), 4);
+    }
+
+    #[test]
+    fn lissajous_spans_screen() {
+        let v = lissajous(40, 10, LISSAJOUS_RATIOS[0]);
+
+        assert_eq!(v.iter().map(|p| p.x).min(), Some(0));
+        assert_eq!(v.iter().map(|p| p.x).max(), Some(39));
+        assert_eq!(v.iter().map(|p| p.y).min(), Some(0));
+        assert_eq!(v.iter().map(|p| p.y).max(), Some(9));
+        assert!(lissajous(1, 1, LISSAJOUS_RATIOS[5])
+            .iter()
+            .all(|p| *p == Point { x: 0, y: 0 }));
+    }
+
+    #[test]
+    fn spiral_on_narrow_screens() {
+        assert_eq!(spiral(1, 1), [Point { x: 0, y: 0 }, Point { x: 0, y: 0 }]);
+        assert_eq!(spiral(5, 1), [Point { x: 0, y: 0 }, Point { x: 4, y: 0 }]);
+        assert_eq!(
+            spiral(1, 5),
+            [
+                Point { x: 0, y: 0 },
+                Point { x: 0, y: 0 },
+                Point { x: 0, y: 4 },
+            ]
+        );
+    }
+}
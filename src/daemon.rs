@@ -0,0 +1,254 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::config::{env_var, Config};
+use eyre::{bail, eyre, Result, WrapErr};
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread::{self, sleep},
+    time::Duration,
+};
+
+/// How often to check the idle time of the user.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Idle time after which the screensaver is launched, unless `--idle-start` is specified.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Options which are meaningful only for the daemon, so they aren't passed to the screensaver.
+/// The boolean tells whether the option takes a value.
+const DAEMON_OPTIONS: [(&str, bool); 3] = [
+    ("--daemon", false),
+    ("--daemon-terminal", true),
+    ("--idle-start", true),
+];
+
+/// Source telling whether the user is idle.
+enum IdleSource {
+    /// The idle time of the X11 session is polled with `xprintidle`.
+    X11,
+    /// `swayidle` reports when the Wayland session becomes idle and active again, using the
+    /// ext-idle-notify protocol of the compositor.
+    Wayland {
+        swayidle: Child,
+        /// Whether the user is idle, reported by the reader of the output of `swayidle`.
+        changes: Receiver<bool>,
+        idle: bool,
+    },
+}
+
+impl IdleSource {
+    /// Detect the session and start watching it for the idle time of the timeout. Wayland is
+    /// preferred, since X11 clients running under Wayland (XWayland) don't see all activity.
+    fn new(timeout: Duration) -> Result<Self> {
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            return Self::wayland(timeout);
+        }
+
+        if env::var_os("DISPLAY").is_some() {
+            return Ok(Self::X11);
+        }
+
+        bail!("daemon mode requires an X11 or Wayland session")
+    }
+
+    /// Start `swayidle` reporting the idle state on its output.
+    fn wayland(timeout: Duration) -> Result<Self> {
+        // swayidle counts in whole seconds
+        let secs = timeout.as_secs_f64().ceil().max(1.0) as u64;
+
+        let mut swayidle = Command::new("swayidle")
+            .args(["-w", "timeout", &secs.to_string(), "echo idle"])
+            .args(["resume", "echo active"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .wrap_err("failed to run swayidle (is it installed?)")?;
+
+        let stdout = swayidle
+            .stdout
+            .take()
+            .ok_or_else(|| eyre!("cannot read the output of swayidle"))?;
+        let (tx, changes) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+
+                if tx.send(line.trim() == "idle").is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self::Wayland {
+            swayidle,
+            changes,
+            idle: false,
+        })
+    }
+
+    /// Check whether the user has been idle for the timeout.
+    fn is_idle(&mut self, timeout: Duration) -> Result<bool> {
+        match self {
+            Self::X11 => Ok(x11_idle_time()? >= timeout),
+            Self::Wayland {
+                swayidle,
+                changes,
+                idle,
+            } => loop {
+                match changes.try_recv() {
+                    Ok(i) => *idle = i,
+                    Err(TryRecvError::Empty) => return Ok(*idle),
+                    Err(TryRecvError::Disconnected) => {
+                        let status = swayidle.wait().wrap_err("failed to wait for swayidle")?;
+
+                        bail!(
+                            "swayidle has exited ({status}), does the compositor support the \
+                             ext-idle-notify protocol?"
+                        );
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl Drop for IdleSource {
+    fn drop(&mut self) {
+        if let Self::Wayland { swayidle, .. } = self {
+            let _ = swayidle.kill();
+            let _ = swayidle.wait();
+        }
+    }
+}
+
+/// Run rxpipes as a screensaver daemon: launch the animation in a terminal after the user has been
+/// idle for `--idle-start` and close it as soon as the activity resumes.
+///
+/// The idle time is read with `xprintidle` on X11 and with `swayidle` on Wayland.
+pub fn run(cfg: &Config) -> Result<()> {
+    let timeout = cfg.idle_start.unwrap_or(DEFAULT_TIMEOUT);
+    let mut source = IdleSource::new(timeout)?;
+    let mut screensaver: Option<Child> = None;
+
+    loop {
+        let idle = source.is_idle(timeout)?;
+
+        if let Some(child) = &mut screensaver {
+            if !idle {
+                // The process may have already exited, so ignore errors
+                let _ = child.kill();
+                child
+                    .wait()
+                    .wrap_err("failed to wait for the screensaver")?;
+                screensaver = None;
+            } else if child.try_wait()?.is_some() {
+                screensaver = None;
+            }
+        } else if idle {
+            screensaver = Some(launch(&cfg.daemon_terminal)?);
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+}
+
+/// Query the idle time of the X11 session.
+fn x11_idle_time() -> Result<Duration> {
+    let output = Command::new("xprintidle")
+        .output()
+        .wrap_err("failed to run xprintidle (is it installed?)")?;
+
+    let ms: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| eyre!("unexpected output of xprintidle"))?;
+
+    Ok(Duration::from_millis(ms))
+}
+
+/// Launch the screensaver in a new terminal with the same options as the daemon.
+fn launch(terminal: &str) -> Result<Child> {
+    let mut term_cmd = terminal.split_whitespace();
+    let program = term_cmd
+        .next()
+        .ok_or_else(|| eyre!("--daemon-terminal must not be empty"))?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(term_cmd)
+        .arg(env::current_exe().wrap_err("cannot find the rxpipes executable")?)
+        .args(screensaver_args(env::args().skip(1)))
+        .env_remove("TMUX");
+
+    for (name, _) in DAEMON_OPTIONS {
+        cmd.env_remove(env_var(name));
+    }
+
+    cmd.spawn()
+        .wrap_err_with(|| format!("failed to launch the terminal `{terminal}`"))
+}
+
+/// Retrieve the command line arguments of the daemon (without the program name) without the
+/// daemon-only options.
+fn screensaver_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args = args.into_iter();
+    let mut res = vec![];
+
+    while let Some(arg) = args.next() {
+        let daemon_opt = DAEMON_OPTIONS.iter().find(|(name, _)| {
+            arg == *name || arg.strip_prefix(name).is_some_and(|s| s.starts_with('='))
+        });
+
+        match daemon_opt {
+            Some((name, true)) if arg == *name => {
+                args.next();
+            }
+            Some(_) => {}
+            None => res.push(arg),
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        screensaver_args(s.split_whitespace().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn daemon_options_removed() {
+        assert_eq!(
+            args("--daemon -p rgb --idle-start 5m --daemon-terminal kitty -f 60"),
+            ["-p", "rgb", "-f", "60"]
+        );
+        assert_eq!(
+            args("--idle-start=5m --daemon-terminal=kitty --daemon --seed 1"),
+            ["--seed", "1"]
+        );
+    }
+
+    #[test]
+    fn similar_options_kept() {
+        assert_eq!(
+            args("--daemonize --idle-started 1 --daemon-terminals"),
+            ["--daemonize", "--idle-started", "1", "--daemon-terminals"]
+        );
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(args("-p rgb --idle-start"), ["-p", "rgb"]);
+        assert!(args("").is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas,
+    piece_set::{save_piece_set_file, split_pieces, CANONICAL_PIECES, PIECE_COUNT},
+    plane_2d::Point,
+    preview::Preview,
+    terminal::TerminalScreen,
+};
+use eyre::{Result, WrapErr};
+use std::{path::Path, time::Duration};
+use termwiz::{
+    input::{InputEvent, KeyCode, KeyEvent, Modifiers},
+    terminal::Terminal,
+};
+
+/// Candidate glyphs grouped by category.
+const CATEGORIES: [(&str, &str); 8] = [
+    ("Light", "│─┌┐└┘├┤┬┴┼"),
+    ("Heavy", "┃━┏┓┗┛┣┫┳┻╋"),
+    ("Double", "║═╔╗╚╝╠╣╦╩╬"),
+    ("Rounded", "│─╭╮╰╯"),
+    ("Dashed", "┆┄┊┈╎╌┇┅┋┉"),
+    ("ASCII", "|-+/\\*#=o"),
+    ("Dots", "·•●○◦∙⋅"),
+    ("Blocks", "█▓▒░▀▄▌▐■□"),
+];
+
+/// Delay between frames of the preview.
+const FRAME_DELAY: Duration = Duration::from_millis(80);
+
+/// Size of the preview area.
+const PREVIEW_SIZE: (usize, usize) = (40, 10);
+
+/// Row of the preview area.
+const PREVIEW_ROW: isize = 9;
+
+/// Interactive piece set designer.
+struct Designer {
+    /// Chosen pieces.
+    pieces: Vec<String>,
+    /// Index of the selected piece.
+    slot: usize,
+    /// Index of the selected category.
+    category: usize,
+    /// Index of the selected candidate in the category.
+    candidate: usize,
+    /// Layer with the controls.
+    ui: Canvas,
+    /// Animated sample.
+    preview: Preview,
+}
+
+impl Designer {
+    /// Create a `Designer` starting with the default pieces.
+    fn new(scr_size: (usize, usize)) -> Self {
+        let preview_size = (
+            PREVIEW_SIZE.0.min(scr_size.0.saturating_sub(2)).max(1),
+            PREVIEW_SIZE.1,
+        );
+
+        Self {
+            pieces: split_pieces(CANONICAL_PIECES),
+            slot: 0,
+            category: 0,
+            candidate: 0,
+            ui: Canvas::new(Point { x: 0, y: 0 }, scr_size),
+            preview: Preview::new(
+                Point {
+                    x: 1,
+                    y: PREVIEW_ROW + 1,
+                },
+                preview_size,
+                split_pieces(CANONICAL_PIECES),
+            ),
+        }
+    }
+
+    /// Retrieve the candidate glyphs of the selected category.
+    fn candidates(&self) -> Vec<String> {
+        split_pieces(CATEGORIES[self.category].1)
+    }
+
+    /// Handle a key press. Returns `Some(save)` when the designer should quit.
+    fn handle_key(&mut self, key: KeyCode) -> Option<bool> {
+        let candidates = self.candidates().len();
+
+        match key {
+            KeyCode::Escape | KeyCode::Char('q') => return Some(false),
+            KeyCode::Char('w') => return Some(true),
+            KeyCode::Tab => self.slot = (self.slot + 1) % PIECE_COUNT,
+            KeyCode::Char(c @ '1'..='6') => self.slot = c as usize - '1' as usize,
+            KeyCode::RightArrow => self.candidate = (self.candidate + 1) % candidates,
+            KeyCode::LeftArrow => self.candidate = (self.candidate + candidates - 1) % candidates,
+            KeyCode::DownArrow | KeyCode::UpArrow => {
+                self.category = if key == KeyCode::DownArrow {
+                    (self.category + 1) % CATEGORIES.len()
+                } else {
+                    (self.category + CATEGORIES.len() - 1) % CATEGORIES.len()
+                };
+                self.candidate = 0;
+            }
+            KeyCode::Enter => {
+                self.pieces[self.slot] = self.candidates()[self.candidate].clone();
+                self.slot = (self.slot + 1) % PIECE_COUNT;
+                self.preview.set_pieces(self.pieces.clone());
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Draw the controls.
+    fn draw_ui(&mut self) {
+        let candidates = self.candidates();
+        let ui = &mut self.ui;
+
+        ui.clear();
+
+        let mut line = |y: isize, s: &str| {
+            ui.move_to(Point { x: 1, y });
+            ui.put_str(s);
+        };
+
+        line(0, "rxpipes piece set designer");
+
+        let slots: Vec<String> = self
+            .pieces
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                if i == self.slot {
+                    format!(">{p}<")
+                } else {
+                    format!(" {p} ")
+                }
+            })
+            .collect();
+        let reference: Vec<String> = split_pieces(CANONICAL_PIECES)
+            .iter()
+            .map(|p| format!(" {p} "))
+            .collect();
+
+        line(2, &format!("Pieces:    {}", slots.concat()));
+        line(3, &format!("Stands for:{}", reference.concat()));
+
+        let candidates: Vec<String> = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                if i == self.candidate {
+                    format!("[{c}]")
+                } else {
+                    format!(" {c} ")
+                }
+            })
+            .collect();
+
+        line(5, &format!("Category:  {}", CATEGORIES[self.category].0));
+        line(6, &format!("Glyphs:   {}", candidates.concat()));
+        line(PREVIEW_ROW, "Preview:");
+
+        let help_row = PREVIEW_ROW + PREVIEW_SIZE.1 as isize + 2;
+
+        line(
+            help_row,
+            "Tab/1-6: piece  Left/Right: glyph  Up/Down: category  Enter: assign",
+        );
+        line(help_row + 1, "w: write the piece set and quit  q: quit");
+    }
+}
+
+/// Run the piece set designer. Returns `true` if the piece set has been written to the file.
+pub fn run(term_scr: &mut TerminalScreen, path: &Path) -> Result<bool> {
+    let mut designer = Designer::new(term_scr.size());
+
+    loop {
+        designer.preview.step();
+        designer.draw_ui();
+
+        term_scr.copy_canvas(&designer.ui);
+        term_scr.copy_canvas(designer.preview.canvas());
+        term_scr.render()?;
+
+        let event = term_scr
+            .terminal()
+            .terminal()
+            .poll_input(Some(FRAME_DELAY))
+            .wrap_err("cannot read incoming events")?;
+
+        match event {
+            Some(InputEvent::Key(KeyEvent {
+                key,
+                modifiers: Modifiers::NONE,
+            })) => match designer.handle_key(key) {
+                Some(true) => {
+                    save_piece_set_file(path, &designer.pieces)?;
+
+                    return Ok(true);
+                }
+                Some(false) => return Ok(false),
+                None => {}
+            },
+            Some(InputEvent::Resized { cols, rows }) => {
+                term_scr.resize((cols, rows));
+                designer.ui.resize((cols, rows));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+#[cfg(feature = "remote")]
+use crate::remote::RemoteCommand;
+use crate::{plane_2d::Point, sync::SyncMessage, weather::Weather};
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::Instant,
+};
+use termwiz::terminal::TerminalWaker;
+
+/// Event delivered to the main loop by a background source.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Message from the sync server (see --sync-connect).
+    Sync(SyncMessage),
+    /// The connection to the sync server has been lost.
+    SyncLost,
+    /// Command received from outside (see --listen and --mqtt-topic).
+    #[cfg(feature = "remote")]
+    Remote(RemoteCommand),
+    /// The current weather has been read (see --mode weather).
+    Weather(Weather),
+    /// Pipes requested by an external source (see --mode git-activity). The key picks their color
+    /// (see `ColorPool::keyed`).
+    Spawn { count: usize, key: u64 },
+    /// The lines printed by --wrap-cmd.
+    WrapOutput(Vec<String>),
+    /// Whether the user has been idle for long enough (see --idle-start).
+    Idle(bool),
+}
+
+/// Handle for background sources (threads) to deliver events to the main loop.
+#[derive(Clone)]
+pub struct EventSender {
+    tx: Sender<Event>,
+    /// Interrupts waiting for terminal input, so the event is handled right away.
+    waker: Option<TerminalWaker>,
+}
+
+impl EventSender {
+    /// Deliver the event to the main loop. Returns `false` if the main loop is gone.
+    pub fn send(&self, event: Event) -> bool {
+        if self.tx.send(event).is_err() {
+            return false;
+        }
+
+        if let Some(w) = &self.waker {
+            let _ = w.wake();
+        }
+
+        true
+    }
+}
+
+/// Events from background sources waiting to be handled by the main loop. The main loop waits for
+/// terminal input and these events at the same time: a delivered event wakes it up.
+pub struct EventQueue {
+    rx: Receiver<Event>,
+    sender: EventSender,
+}
+
+impl EventQueue {
+    /// Create an `EventQueue`. The waker of the terminal is used to wake up the main loop when
+    /// it's waiting for input.
+    pub fn new(waker: Option<TerminalWaker>) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            rx,
+            sender: EventSender { tx, waker },
+        }
+    }
+
+    /// Retrieve a handle for delivering events to the queue.
+    pub fn sender(&self) -> EventSender {
+        self.sender.clone()
+    }
+
+    /// Retrieve the oldest waiting event, if there is any.
+    pub fn try_next(&self) -> Option<Event> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Something that happened in the simulation. The events are published to all subsystems
+/// interested in them (statistics, undo history, announcements), so none of them has to be
+/// updated by hand where it happens.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SimEvent {
+    /// A new pipe has been started.
+    PipeSpawned,
+    /// The current pipe has changed its direction.
+    Turned,
+    /// The current pipe has run into another pipe at the cell and ended (see --end-on-collision).
+    Collided(Point),
+    /// The previous layers have been darkened and a new layer has been started.
+    LayerDarkened,
+    /// The screen has been cleared.
+    Cleared,
+    /// The drawing area has been resized to the size.
+    Resized((usize, usize)),
+}
+
+/// Simulation event with the time it happened.
+#[derive(Copy, Clone, Debug)]
+pub struct TimedEvent {
+    /// Time of the event.
+    pub at: Instant,
+    pub event: SimEvent,
+}
+
+impl TimedEvent {
+    /// Create a `TimedEvent` which happened just now.
+    pub fn now(event: SimEvent) -> Self {
+        Self {
+            at: Instant::now(),
+            event,
+        }
+    }
+}
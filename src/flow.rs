@@ -0,0 +1,99 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas, color::scale_brightness, grid::Grid, pipe::PipeRecord, plane_2d::Point,
+};
+use std::time::{Duration, Instant};
+use termwiz::cell::Cell;
+
+/// How long the pulses stay on a piece before moving to the next one.
+const FLOW_STEP_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Distance between the pulses following each other along a pipe (in pieces).
+const PULSE_SPACING: usize = 16;
+
+/// Brightness of the pieces of a pulse, from its head backwards.
+const PULSE_BRIGHTNESS: [f32; 3] = [1.8, 1.4, 1.15];
+
+/// Pulses of light flowing along the drawn pipes in their drawing order, like liquid running
+/// through the network (see --flow-animation). The pulses are put on the canvas only while it's
+/// copied to the terminal, so the pipes themselves (and the records of what they have overdrawn)
+/// stay untouched.
+#[derive(Debug)]
+pub struct Flow {
+    /// How far the pulses have travelled (in pieces).
+    phase: usize,
+    /// Time when the pulses last moved.
+    moved_at: Instant,
+    /// Cells lit by the pulses, with their original contents.
+    lit: Vec<(Point, Cell)>,
+}
+
+impl Flow {
+    /// Create a `Flow`.
+    pub fn new() -> Self {
+        Self {
+            phase: 0,
+            moved_at: Instant::now(),
+            lit: vec![],
+        }
+    }
+
+    /// Light the pulses on the pieces of the pipes which haven't been overdrawn by other pipes.
+    /// The pulses move on if it's time to, unless they are held still (e.g. while paused).
+    pub fn apply(
+        &mut self,
+        canv: &mut Canvas,
+        records: &[PipeRecord],
+        occupancy: &Grid<Option<u64>>,
+        hold: bool,
+    ) {
+        if !hold && self.moved_at.elapsed() >= FLOW_STEP_INTERVAL {
+            self.phase = self.phase.wrapping_add(1);
+            self.moved_at = Instant::now();
+        }
+
+        let head = self.phase % PULSE_SPACING;
+
+        for r in records {
+            for (i, piece) in r.pieces.iter().enumerate() {
+                let behind = (head + PULSE_SPACING - i % PULSE_SPACING) % PULSE_SPACING;
+
+                let Some(&brightness) = PULSE_BRIGHTNESS.get(behind) else {
+                    continue;
+                };
+
+                if occupancy.get(piece.pos) != Some(&Some(r.id)) {
+                    continue;
+                }
+
+                let Some(cell) = canv.cell(piece.pos) else {
+                    continue;
+                };
+
+                if cell.str().trim().is_empty() {
+                    continue;
+                }
+
+                let mut pulse = cell.clone();
+                let fg = pulse.attrs().foreground();
+
+                pulse
+                    .attrs_mut()
+                    .set_foreground(scale_brightness(fg, brightness));
+                canv.put_cell(piece.pos, &pulse);
+                self.lit.push((piece.pos, cell));
+            }
+        }
+    }
+
+    /// Put back the original contents of the cells lit by the pulses.
+    pub fn restore(&mut self, canv: &mut Canvas) {
+        // Pipes may cross themselves, so the cells are restored in reverse
+        for (p, cell) in self.lit.drain(..).rev() {
+            canv.put_cell(p, &cell);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{config::Config, screensaver::Screensaver};
+use eyre::Result;
+use std::io::{self, Write};
+use termwiz::{
+    caps::Capabilities,
+    terminal::{SystemTerminal, Terminal},
+};
+
+/// Size of the frame when the size of the terminal can't be detected (e.g. the output is
+/// redirected to a file).
+const FALLBACK_SIZE: (usize, usize) = (80, 24);
+
+/// Run the simulation for the number of steps without touching the terminal and print the final
+/// frame with ANSI escape sequences.
+pub fn run(cfg: Config, steps: u64, size: Option<(usize, usize)>) -> Result<()> {
+    let size = size.unwrap_or_else(|| terminal_size().unwrap_or(FALLBACK_SIZE));
+
+    let mut app = Screensaver::headless(size, cfg)?;
+    app.simulate(steps);
+
+    io::stdout().write_all(app.frame().as_bytes())?;
+
+    Ok(())
+}
+
+/// Retrieve the size of the terminal, if the standard streams are connected to one.
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let caps = Capabilities::new_from_env().ok()?;
+    let size = SystemTerminal::new_from_stdio(caps)
+        .ok()?
+        .get_screen_size()
+        .ok()?;
+
+    Some((size.cols, size.rows))
+}
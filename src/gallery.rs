@@ -0,0 +1,86 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{config::Config, mosaic::Mosaic, terminal::TerminalScreen};
+use eyre::{Result, WrapErr};
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+use termwiz::{
+    input::{InputEvent, KeyCode, KeyEvent, Modifiers},
+    terminal::Terminal,
+};
+
+/// Create a `Mosaic` with a tile for each seed, labeled with its number and seed.
+fn new_gallery(scr_size: (usize, usize), cfg: &Config, seeds: usize) -> Result<Mosaic> {
+    let cols = (seeds as f64).sqrt().ceil() as usize;
+    let rows = seeds.div_ceil(cols);
+
+    let mut mosaic = Mosaic::new(scr_size, cfg, (cols, rows))?;
+    let tile_seeds: Vec<u64> = mosaic.tiles().iter().map(|t| t.seed()).collect();
+
+    mosaic.label_tiles(|i| format!(" {} · seed {} ", i + 1, tile_seeds[i]));
+
+    Ok(mosaic)
+}
+
+/// Run the simulations with consecutive seeds side by side for the number of steps, until the
+/// user picks one of them. Returns the seed of the picked simulation, or `None` if the user quit.
+pub fn run(
+    term_scr: &mut TerminalScreen,
+    cfg: &Config,
+    seeds: usize,
+    steps: u64,
+) -> Result<Option<u64>> {
+    let delay = Duration::from_millis(1000 / cfg.fps as u64);
+    let mut cfg = cfg.clone();
+
+    // Seeds of the tiles are derived from this one, so the picked one can be reproduced
+    cfg.seed = Some(cfg.seed.unwrap_or_else(|| thread_rng().gen()));
+
+    let mut gallery = new_gallery(term_scr.size(), &cfg, seeds)?;
+    let mut step = 0;
+
+    loop {
+        if step < steps {
+            gallery.step();
+            step += 1;
+        }
+
+        gallery.render(term_scr)?;
+
+        // Once the simulations have stopped, there is nothing to do until the user picks one
+        let timeout = (step < steps).then_some(delay);
+        let event = term_scr
+            .terminal()
+            .terminal()
+            .poll_input(timeout)
+            .wrap_err("cannot read incoming events")?;
+
+        match event {
+            Some(InputEvent::Key(KeyEvent {
+                key,
+                modifiers: Modifiers::NONE,
+            })) => match key {
+                KeyCode::Escape | KeyCode::Char('q') => return Ok(None),
+                KeyCode::Char(c) => {
+                    let picked = c
+                        .to_digit(10)
+                        .and_then(|d| gallery.tiles().get((d as usize).checked_sub(1)?));
+
+                    if let Some(t) = picked {
+                        return Ok(Some(t.seed()));
+                    }
+                }
+                _ => {}
+            },
+            Some(InputEvent::Resized { cols, rows }) => {
+                term_scr.resize((cols, rows));
+                term_scr.clear();
+                gallery = new_gallery(term_scr.size(), &cfg, seeds)?;
+                step = 0;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::events::{Event, EventSender};
+use eyre::{bail, Result, WrapErr};
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// How often the repository is checked for new commits.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of the most recent commits looked at by each check. More commits than this appearing
+/// at once (e.g. after a big fetch) are partly missed, which is fine for an animation.
+const RECENT_COMMITS: usize = 200;
+
+/// Number of pipes spawned for each new commit.
+const PIPES_PER_COMMIT: usize = 3;
+
+/// Watch the git repository for new commits (made locally or fetched, on any branch) in the
+/// background and request a burst of pipes for each, colored by the author.
+pub fn watch(repo: &Path, events: EventSender) -> Result<()> {
+    let repo = repo.to_path_buf();
+
+    // The commits already there when rxpipes starts are not new
+    let mut seen: HashSet<String> = recent_commits(&repo)?
+        .into_iter()
+        .map(|(hash, _)| hash)
+        .collect();
+
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+
+        // The repository may be busy (e.g. in the middle of a rebase), so just try again later
+        let Ok(commits) = recent_commits(&repo) else {
+            continue;
+        };
+
+        // Oldest first, so the bursts come in the order of the commits
+        for (hash, author) in commits.into_iter().rev() {
+            if !seen.insert(hash) {
+                continue;
+            }
+
+            let mut hasher = DefaultHasher::new();
+            author.hash(&mut hasher);
+
+            let event = Event::Spawn {
+                count: PIPES_PER_COMMIT,
+                key: hasher.finish(),
+            };
+
+            if !events.send(event) {
+                return;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Retrieve the hashes and author emails of the most recent commits of the repository, newest
+/// first.
+fn recent_commits(repo: &Path) -> Result<Vec<(String, String)>> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["log", "--all", "--format=%H %ae", "-n"])
+        .arg(RECENT_COMMITS.to_string())
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .wrap_err("failed to run git (is it installed?)")?;
+
+    if !out.status.success() {
+        // An empty repository (unborn HEAD) has no commits yet, they may come later
+        if is_repository(repo) {
+            return Ok(vec![]);
+        }
+
+        bail!("{} is not a git repository", repo.display());
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|l| l.split_once(' '))
+        .map(|(hash, author)| (hash.to_string(), author.to_string()))
+        .collect())
+}
+
+/// Check whether the path is in a git repository.
+fn is_repository(path: &Path) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(["rev-parse", "--git-dir"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf};
+
+    /// Create an empty directory for the test, removing what's left of a previous run.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rxpipes-{name}-{}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let ok = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=a", "-c", "user.email=a@b"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success();
+
+        assert!(ok, "git {args:?}");
+    }
+
+    #[test]
+    fn empty_repository_has_no_commits_yet() {
+        let dir = empty_dir("empty-repo");
+
+        git(&dir, &["init", "-q"]);
+        assert_eq!(recent_commits(&dir).unwrap(), vec![]);
+
+        git(&dir, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        let commits = recent_commits(&dir).unwrap();
+
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].1, "a@b");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn not_a_repository() {
+        let dir = empty_dir("no-repo");
+
+        assert!(recent_commits(&dir).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::plane_2d::Point;
+
+/// 2D grid of per-cell values, addressed by points.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    /// Cells stored row by row.
+    cells: Vec<T>,
+    /// Size of the grid.
+    size: (usize, usize),
+}
+
+impl<T: Clone + Default> Grid<T> {
+    /// Create a `Grid` with specified size, filled with default values.
+    pub fn new(size: (usize, usize)) -> Self {
+        Self {
+            cells: vec![T::default(); size.0 * size.1],
+            size,
+        }
+    }
+
+    /// Resize the grid to specified size. All cells are reset to default values.
+    pub fn resize(&mut self, size: (usize, usize)) {
+        *self = Self::new(size);
+    }
+
+    /// Reset all cells to default values.
+    pub fn clear(&mut self) {
+        self.cells.fill(T::default());
+    }
+
+    /// Retrieve a reference to the cell at the point, if the point lies within the grid.
+    pub fn get(&self, p: Point) -> Option<&T> {
+        self.index(p).map(|i| &self.cells[i])
+    }
+
+    /// Retrieve a mutable reference to the cell at the point, if the point lies within the grid.
+    pub fn get_mut(&mut self, p: Point) -> Option<&mut T> {
+        self.index(p).map(|i| &mut self.cells[i])
+    }
+
+    /// Convert a point to an index into the cell buffer.
+    fn index(&self, p: Point) -> Option<usize> {
+        if p.x < 0 || p.y < 0 || p.x as usize >= self.size.0 || p.y as usize >= self.size.1 {
+            return None;
+        }
+
+        Some(p.y as usize * self.size.0 + p.x as usize)
+    }
+}
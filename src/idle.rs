@@ -0,0 +1,62 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    events::{Event, EventSender},
+    process::output_with_timeout,
+};
+use std::{
+    env,
+    process::Command,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How often to ask tmux about the client activity.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long tmux may take to answer. A tmux server which hangs is killed after this and the user
+/// is considered idle.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Watch the user's activity in the tmux session rxpipes is running in, and deliver to the main
+/// loop whether the user has been idle for at least the threshold. The queries run in the
+/// background, so a slow tmux doesn't hold up the animation. Returns `false` if rxpipes doesn't
+/// run inside tmux. GNU screen is not supported: it doesn't report the activity of its clients.
+pub fn watch(threshold: Duration, events: EventSender) -> bool {
+    if env::var_os("TMUX").is_none() {
+        return false;
+    }
+
+    thread::spawn(move || loop {
+        // If tmux can't be queried, the user is considered idle
+        let idle = query_idle_time().is_none_or(|t| t >= threshold);
+
+        if !events.send(Event::Idle(idle)) {
+            return;
+        }
+
+        thread::sleep(CHECK_INTERVAL);
+    });
+
+    true
+}
+
+/// Ask tmux for the time since the last activity of the client.
+fn query_idle_time() -> Option<Duration> {
+    let output = output_with_timeout(
+        Command::new("tmux").args(["display-message", "-p", "#{client_activity}"]),
+        QUERY_TIMEOUT,
+    )
+    .ok()
+    .filter(|out| !out.timed_out)?;
+
+    let last_activity: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+    Some(Duration::from_secs(now.saturating_sub(last_activity)))
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{
+    io::{self, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// Size of the chunks the piped input is read in.
+const CHUNK_SIZE: usize = 4096;
+
+/// Number of chunks read ahead of the simulation. When they are all waiting, the producer is
+/// blocked until the simulation catches up, so a fast one doesn't fill up the memory.
+const CHUNKS_AHEAD: usize = 16;
+
+/// Offset basis of the FNV-1a hash folding the input bytes into the seeds.
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Prime of the FNV-1a hash folding the input bytes into the seeds.
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Bytes piped to rxpipes, used as the source of randomness of the simulation instead of the
+/// seed (see --stdin-mode). Each step takes the next byte, so the same input always draws the
+/// same picture.
+pub struct InputEntropy {
+    rx: Receiver<Vec<u8>>,
+    /// Chunk being taken from and the position of its next byte.
+    chunk: Vec<u8>,
+    pos: usize,
+    /// Hash of all bytes taken so far.
+    state: u64,
+}
+
+impl InputEntropy {
+    /// Start reading the standard input in the background.
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::sync_channel(CHUNKS_AHEAD);
+
+        thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            let mut buf = [0; CHUNK_SIZE];
+
+            loop {
+                let n = match stdin.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => return,
+                };
+
+                if tx.send(buf[..n].to_vec()).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            rx,
+            chunk: vec![],
+            pos: 0,
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// Take the next byte of the input and retrieve the seed for the step made on it: a hash of
+    /// all bytes up to it. Returns `None` if the next byte hasn't arrived yet, or never will.
+    pub fn next_seed(&mut self) -> Option<u64> {
+        while self.pos >= self.chunk.len() {
+            self.chunk = self.rx.try_recv().ok()?;
+            self.pos = 0;
+        }
+
+        self.state = (self.state ^ self.chunk[self.pos] as u64).wrapping_mul(FNV_PRIME);
+        self.pos += 1;
+
+        Some(self.state)
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{
+    io::{self, stdout, Write},
+    thread::sleep,
+    time::Duration,
+};
+use termwiz::{
+    caps::{probed::ProbeCapabilities, Capabilities},
+    input::InputEvent,
+    render::{terminfo::TerminfoRenderer, RenderTty},
+    surface::Change,
+    terminal::{ScreenSize, SystemTerminal, Terminal, TerminalWaker},
+};
+
+/// Number of bytes written at once over the simulated link, about the payload of a TCP segment.
+const CHUNK_BYTES: usize = 1400;
+
+/// Pause between the chunks written over the simulated link.
+const CHUNK_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Output collected for one render before it's sent over the simulated link.
+struct Output {
+    /// Size of the terminal.
+    size: (usize, usize),
+    /// Bytes to send.
+    buf: Vec<u8>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl RenderTty for Output {
+    fn get_size_in_cells(&mut self) -> termwiz::Result<(usize, usize)> {
+        Ok(self.size)
+    }
+}
+
+/// Simulated slow link between rxpipes and the terminal.
+struct Link {
+    /// Delay before the output of a render starts arriving.
+    latency: Duration,
+    renderer: TerminfoRenderer,
+    out: Output,
+}
+
+/// System terminal whose output may be sent over a simulated slow link, to reproduce the artifacts
+/// of remote terminals locally (see --simulate-latency): the output of each render is delayed and
+/// then written in small chunks.
+pub struct LatencyTerminal {
+    term: SystemTerminal,
+    /// Simulated link, if enabled.
+    link: Option<Link>,
+}
+
+impl LatencyTerminal {
+    /// Create a `LatencyTerminal` with the capabilities of the system terminal. If the latency is
+    /// not specified, the output is written right away.
+    pub fn new(term: SystemTerminal, caps: Capabilities, latency: Option<Duration>) -> Self {
+        Self {
+            term,
+            link: latency.map(|latency| Link {
+                latency,
+                renderer: TerminfoRenderer::new(caps),
+                out: Output {
+                    size: (0, 0),
+                    buf: vec![],
+                },
+            }),
+        }
+    }
+}
+
+impl Terminal for LatencyTerminal {
+    fn set_raw_mode(&mut self) -> termwiz::Result<()> {
+        self.term.set_raw_mode()
+    }
+
+    fn set_cooked_mode(&mut self) -> termwiz::Result<()> {
+        self.term.set_cooked_mode()
+    }
+
+    fn enter_alternate_screen(&mut self) -> termwiz::Result<()> {
+        self.term.enter_alternate_screen()
+    }
+
+    fn exit_alternate_screen(&mut self) -> termwiz::Result<()> {
+        self.term.exit_alternate_screen()
+    }
+
+    fn get_screen_size(&mut self) -> termwiz::Result<ScreenSize> {
+        self.term.get_screen_size()
+    }
+
+    fn probe_capabilities(&mut self) -> Option<ProbeCapabilities<'_>> {
+        self.term.probe_capabilities()
+    }
+
+    fn set_screen_size(&mut self, size: ScreenSize) -> termwiz::Result<()> {
+        self.term.set_screen_size(size)
+    }
+
+    fn render(&mut self, changes: &[Change]) -> termwiz::Result<()> {
+        let Some(link) = &mut self.link else {
+            return self.term.render(changes);
+        };
+
+        let size = self.term.get_screen_size()?;
+
+        link.out.size = (size.cols, size.rows);
+        link.renderer.render_to(changes, &mut link.out)?;
+
+        if link.out.buf.is_empty() {
+            return Ok(());
+        }
+
+        // Everything written by the system terminal itself comes first
+        self.term.flush()?;
+        sleep(link.latency);
+
+        let mut stdout = stdout();
+
+        for chunk in link.out.buf.chunks(CHUNK_BYTES) {
+            stdout.write_all(chunk)?;
+            stdout.flush()?;
+            sleep(CHUNK_INTERVAL);
+        }
+
+        link.out.buf.clear();
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> termwiz::Result<()> {
+        self.term.flush()
+    }
+
+    fn poll_input(&mut self, wait: Option<Duration>) -> termwiz::Result<Option<InputEvent>> {
+        self.term.poll_input(wait)
+    }
+
+    fn waker(&self) -> TerminalWaker {
+        self.term.waker()
+    }
+}
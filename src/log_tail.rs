@@ -0,0 +1,105 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::events::{Event, EventSender};
+use eyre::{Result, WrapErr};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File, Metadata},
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+/// How long to wait before looking for new lines again when the end of the file is reached.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Follow the file like `tail -F` in the background and request a pipe for each new line, colored
+/// by the line. The lines already in the file are skipped. When the file is truncated or replaced
+/// (e.g. rotated), it's read again from the start.
+pub fn watch(path: &Path, events: EventSender) -> Result<()> {
+    let path = path.to_path_buf();
+    let mut reader = open(&path, true)?;
+
+    thread::spawn(move || {
+        let mut line = String::new();
+
+        loop {
+            match reader.read_line(&mut line) {
+                // Lines may be written in parts, so wait for the rest
+                Ok(_) if line.ends_with('\n') => {
+                    if !line.trim().is_empty() {
+                        let mut hasher = DefaultHasher::new();
+                        line.trim_end().hash(&mut hasher);
+
+                        let event = Event::Spawn {
+                            count: 1,
+                            key: hasher.finish(),
+                        };
+
+                        if !events.send(event) {
+                            return;
+                        }
+                    }
+
+                    line.clear();
+                }
+                Ok(_) => {
+                    thread::sleep(POLL_INTERVAL);
+
+                    if is_replaced(&path, &mut reader) {
+                        if let Ok(r) = open(&path, false) {
+                            reader = r;
+                            line.clear();
+                        }
+                    }
+                }
+                Err(_) => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Open the file for reading, optionally from its end.
+fn open(path: &Path, from_end: bool) -> Result<BufReader<File>> {
+    let mut file = File::open(path).wrap_err_with(|| format!("cannot open {}", path.display()))?;
+
+    if from_end {
+        file.seek(SeekFrom::End(0))
+            .wrap_err_with(|| format!("cannot seek in {}", path.display()))?;
+    }
+
+    Ok(BufReader::new(file))
+}
+
+/// Check whether the file has been truncated below the read position or replaced by another one.
+fn is_replaced(path: &Path, reader: &mut BufReader<File>) -> bool {
+    let (Ok(current), Ok(opened), Ok(pos)) = (
+        fs::metadata(path),
+        reader.get_ref().metadata(),
+        reader.stream_position(),
+    ) else {
+        return false;
+    };
+
+    current.len() < pos || file_id(&current) != file_id(&opened)
+}
+
+/// Retrieve the identity of a file (device and inode).
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Retrieve the identity of a file (not available on this platform).
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
@@ -0,0 +1,157 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use termwiz::color::SrgbaTuple;
+
+/// Number of values of an 8-bit color channel.
+const CHANNEL_VALUES: usize = 256;
+
+/// Color transformation precomputed for every 8-bit value of each RGB channel, so applying it to
+/// a cell is three table lookups instead of floating-point math. Terminals display 8-bit channels
+/// anyway, so nothing visible is lost.
+#[derive(Clone, Debug)]
+pub struct ColorLut {
+    /// Transformed values of the red, green and blue channels.
+    channels: [[u8; CHANNEL_VALUES]; 3],
+}
+
+impl ColorLut {
+    /// Create a `ColorLut` from the transformation of a channel value (0.0 - 1.0), which is given
+    /// the channel index (0 - 2) too.
+    pub fn new(f: impl Fn(usize, f32) -> f32) -> Self {
+        let mut channels = [[0; CHANNEL_VALUES]; 3];
+
+        for (i, table) in channels.iter_mut().enumerate() {
+            for (v, out) in table.iter_mut().enumerate() {
+                *out = to_u8(f(i, v as f32 / 255.0));
+            }
+        }
+
+        Self { channels }
+    }
+
+    /// Create a `ColorLut` which makes colors darker by the amount, up to the minimal color.
+    /// Channels darker than the minimal color are lightened instead.
+    ///
+    /// Unlike with [`darken_channel`], each channel moves by at least a step of 8 bits, because the
+    /// channels are kept in 8 bits between the darkenings: a dark channel would get stuck short of
+    /// the minimum otherwise (e.g. 2/255 * 0.8 rounds back to 2/255).
+    pub fn darken(amount: f32, min: SrgbaTuple) -> Self {
+        let min = [min.0, min.1, min.2];
+        let step = if amount < 1.0 { 1.0 / 255.0 } else { 0.0 };
+
+        Self::new(|i, x| {
+            let a = min[i];
+            let y = darken_channel(x, amount, a);
+
+            if x > a {
+                y.min(x - step).max(a)
+            } else if x < a {
+                y.max(x + step).min(a)
+            } else {
+                y
+            }
+        })
+    }
+
+    /// Transform the color. The alpha channel is kept.
+    pub fn apply(&self, c: SrgbaTuple) -> SrgbaTuple {
+        let [r, g, b] = &self.channels;
+
+        SrgbaTuple(
+            r[to_u8(c.0) as usize] as f32 / 255.0,
+            g[to_u8(c.1) as usize] as f32 / 255.0,
+            b[to_u8(c.2) as usize] as f32 / 255.0,
+            c.3,
+        )
+    }
+}
+
+/// Make the channel value darker by the amount, up to the minimal value, with floating-point math.
+/// Values darker than the minimum are lightened instead.
+pub fn darken_channel(x: f32, amount: f32, min: f32) -> f32 {
+    if x > min {
+        (x * amount).clamp(min, 1.0)
+    } else if x < min {
+        (x * (1.0 + amount)).clamp(0.0, min)
+    } else {
+        x
+    }
+}
+
+/// Convert a channel value (0.0 - 1.0) to 8 bits.
+fn to_u8(x: f32) -> u8 {
+    (x.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: SrgbaTuple = SrgbaTuple(0.0, 0.0, 0.0, 1.0);
+
+    /// Retrieve the 8-bit channels of the color.
+    fn rgb(c: SrgbaTuple) -> [u8; 3] {
+        [to_u8(c.0), to_u8(c.1), to_u8(c.2)]
+    }
+
+    #[test]
+    fn identity_keeps_colors() {
+        let lut = ColorLut::new(|_, x| x);
+
+        for v in 0..=255 {
+            let x = v as f32 / 255.0;
+
+            assert_eq!(rgb(lut.apply(SrgbaTuple(x, x, x, 1.0))), [v, v, v]);
+        }
+    }
+
+    #[test]
+    fn keeps_alpha() {
+        let lut = ColorLut::darken(0.8, BLACK);
+
+        assert_eq!(lut.apply(SrgbaTuple(1.0, 0.5, 0.2, 0.3)).3, 0.3);
+    }
+
+    #[test]
+    fn darkens_like_float_math() {
+        let lut = ColorLut::darken(0.8, BLACK);
+
+        for v in 0..=255u8 {
+            let x = v as f32 / 255.0;
+            let expected = to_u8(darken_channel(x, 0.8, 0.0)).min(v.saturating_sub(1));
+
+            assert_eq!(rgb(lut.apply(SrgbaTuple(x, x, x, 1.0)))[0], expected);
+        }
+    }
+
+    #[test]
+    fn dark_channels_reach_minimum() {
+        let min = SrgbaTuple(0.2, 0.0, 0.6, 1.0);
+        let lut = ColorLut::darken(0.9, min);
+        let mut c = SrgbaTuple(1.0, 3.0 / 255.0, 0.1, 1.0);
+
+        for _ in 0..255 {
+            c = lut.apply(c);
+        }
+
+        assert_eq!(rgb(c), rgb(min));
+    }
+
+    #[test]
+    fn minimum_is_kept() {
+        let min = SrgbaTuple(0.2, 0.4, 0.6, 1.0);
+        let c = ColorLut::darken(0.5, min).apply(min);
+
+        assert_eq!(rgb(c), rgb(min));
+    }
+
+    #[test]
+    fn no_darkening_keeps_colors() {
+        let lut = ColorLut::darken(1.0, BLACK);
+        let c = SrgbaTuple(0.9, 0.5, 2.0 / 255.0, 1.0);
+
+        assert_eq!(rgb(lut.apply(c)), rgb(c));
+    }
+}
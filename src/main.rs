@@ -2,27 +2,92 @@
 //
 // This file is licensed under the MIT License (see LICENSE.md).
 
+mod bench;
+mod bookmark;
+mod bouncer;
 mod canvas;
+mod clock;
 mod color;
+mod compat;
 mod config;
+mod curve;
+mod daemon;
+mod design;
+mod events;
+mod flow;
+mod frame;
+mod gallery;
+mod git_activity;
+mod grid;
+mod idle;
+mod input_entropy;
+mod latency;
+mod log_tail;
+mod lut;
+mod maze;
+#[cfg(feature = "notifications")]
+mod milestones;
+mod mosaic;
+mod multiplexer;
+mod paths;
+mod piece_set;
 mod pipe;
 mod plane_2d;
+mod postfx;
+mod preview;
+mod process;
+mod react;
+#[cfg(feature = "remote")]
+mod remote;
+mod replay;
 mod screensaver;
+mod simulation;
+mod sound;
+mod sparks;
+mod stencil;
+mod supervisor;
+mod surprise;
+mod sync;
 mod terminal;
+mod theme;
+mod ticker;
+mod timer;
+mod watchdog;
+mod weather;
+mod wrap_cmd;
 
-use crate::{config::Config, screensaver::Screensaver, terminal::TerminalScreen};
-use clap::Parser;
-use eyre::{Result, WrapErr};
-use std::panic::{set_hook, take_hook};
-use termwiz::{caps::Capabilities, terminal::SystemTerminal};
-use unicode_segmentation::UnicodeSegmentation;
+use crate::{
+    bookmark::Bookmark,
+    clock::local_minutes,
+    config::Config,
+    latency::LatencyTerminal,
+    piece_set::{load_piece_set_file, split_animated_pieces, PieceSetMeta},
+    screensaver::Screensaver,
+    terminal::TerminalScreen,
+    theme::Theme,
+};
+use clap::{error::ErrorKind, FromArgMatches};
+use eyre::{bail, Result, WrapErr};
+use std::{
+    env,
+    panic::{set_hook, take_hook},
+    process::Command,
+    time::Duration,
+};
+use termwiz::{
+    caps::{Capabilities, ColorLevel, ProbeHints},
+    terminal::SystemTerminal,
+};
 
 /// Set a panic hook that will restore the terminal state when the program panics.
 fn set_panic_hook() {
     let old_hook = take_hook();
 
     set_hook(Box::new(move |panic_info| {
-        let term = SystemTerminal::new_from_stdio(Capabilities::new_from_env().unwrap()).unwrap();
+        let caps = Capabilities::new_from_env().unwrap();
+        let term = SystemTerminal::new_from_stdio(caps.clone())
+            .or_else(|_| SystemTerminal::new(caps))
+            .unwrap();
         let mut term_scr = TerminalScreen::new(term).unwrap();
         let _ = term_scr.deinit();
 
@@ -31,14 +96,27 @@ fn set_panic_hook() {
 }
 
 fn parse_cli() -> Config {
-    let mut cfg = Config::parse();
+    let (args, pipes_sh) = compat::translate_args(std::env::args_os().collect())
+        .unwrap_or_else(|e| Config::cli().error(ErrorKind::InvalidValue, e).exit());
+    let matches = Config::cli().get_matches_from(args);
+    let mut cfg = Config::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    cfg.record_explicit_args(&matches);
+
+    if let Some(quirks) = pipes_sh {
+        quirks.apply(&mut cfg);
+    }
 
     if let Some(s) = &cfg.custom_piece_set_ {
-        cfg.custom_piece_set = Some(
-            s.graphemes(true) // true here means iterate over extended grapheme clusters (UAX #29).
-                .map(|s| s.to_string())
-                .collect(),
-        );
+        let (pieces, frames) = split_animated_pieces(s);
+
+        cfg.custom_piece_set = Some(pieces);
+
+        if !frames.is_empty() {
+            cfg.custom_piece_set_meta = Some(PieceSetMeta {
+                frames,
+                ..Default::default()
+            });
+        }
     }
 
     cfg
@@ -46,25 +124,231 @@ fn parse_cli() -> Config {
 
 /// An entry point.
 fn main() -> Result<()> {
-    let cfg = parse_cli();
+    let mut cfg = parse_cli();
+
+    if let Some(config::Command::Themes { list: true }) = &cfg.command {
+        theme::list();
+
+        return Ok(());
+    }
+
+    if let Some(n) = cfg.from_bookmark {
+        let b = Bookmark::load(n as usize)?;
+
+        cfg.seed = Some(b.seed);
+
+        if b.theme.is_some() {
+            cfg.theme = b.theme;
+        }
+    }
+
+    if let Some(s) = &cfg.schedule {
+        for name in s.themes() {
+            Theme::find(name)?;
+        }
+
+        cfg.theme = local_minutes().map(|now| s.at(now).to_string());
+    }
+
+    if let Some(name) = &cfg.theme {
+        Theme::find(name)?.apply(&mut cfg);
+    }
+
+    if let Some(aspects) = cfg.surprise_me.clone() {
+        surprise::apply(&mut cfg, &aspects);
+    }
+
+    if let Some(path) = &cfg.piece_set_file {
+        let (pieces, meta) = load_piece_set_file(path)?;
+
+        cfg.custom_piece_set = Some(pieces);
+        cfg.custom_piece_set_meta = Some(meta);
+    }
+
+    // Bold pipes and true colors render as garbage on the Linux virtual console
+    if env::var("TERM").is_ok_and(|t| t == "linux") {
+        cfg.console = true;
+    }
+
+    if cfg.console {
+        cfg.apply_console();
+    }
+
+    if cfg.safe_mode {
+        cfg.apply_safe_mode();
+    }
+
+    cfg.validate()?;
+
+    if cfg.supervised {
+        return supervisor::run();
+    }
+
+    if let Some(config::Command::Frame { steps, size }) = &cfg.command {
+        let (steps, size) = (*steps, *size);
+
+        return frame::run(cfg, steps, size);
+    }
+
+    if let Some(config::Command::Bench { steps, size }) = &cfg.command {
+        let (steps, size) = (*steps, *size);
+
+        return bench::run(cfg, steps, size);
+    }
+
+    if cfg.daemon {
+        return daemon::run(&cfg);
+    }
+
+    if cfg.ticker_line {
+        return ticker::run(&cfg);
+    }
+
+    let mut hints = ProbeHints::new_from_env();
+
+    // Terminals which don't know mouse reporting could print the escape sequences
+    if cfg.safe_mode {
+        hints = hints
+            .mouse_reporting(Some(false))
+            .bracketed_paste(Some(false));
+    }
 
-    let term = SystemTerminal::new_from_stdio(
-        Capabilities::new_from_env().wrap_err("cannot read terminal capabilities")?,
-    )
+    if cfg.console {
+        hints = hints.color_level(Some(ColorLevel::Sixteen));
+    }
+
+    let caps = Capabilities::new_with_hints(hints).wrap_err("cannot read terminal capabilities")?;
+
+    // The warnings are printed before the screen is taken over, so they are seen after exit
+    for w in cfg.adapt_to_colors(caps.color_level()) {
+        eprintln!("warning: {w}");
+    }
+
+    let latency = cfg
+        .simulate_latency
+        .map(|ms| Duration::from_millis(ms as u64));
+    // With --stdin-mode, the standard input is taken by the piped data, so the keys are read
+    // from the terminal itself
+    let term = if cfg.stdin_mode {
+        SystemTerminal::new(caps.clone())
+    } else {
+        SystemTerminal::new_from_stdio(caps.clone())
+    }
     .wrap_err("failed to associate terminal with screen buffer")?;
+    let term = LatencyTerminal::new(term, caps, latency);
     let mut term_scr = TerminalScreen::new(term).wrap_err("cannot set up terminal screen")?;
 
+    if cfg.safe_mode {
+        term_scr.set_plain();
+    }
+
+    if let Some(g) = cfg.geometry {
+        term_scr.set_viewport(g);
+    }
+
+    if let Some(b) = cfg.max_bytes_per_frame {
+        term_scr.set_byte_budget(b as usize);
+    }
+
     set_panic_hook();
 
     term_scr
         .init()
         .wrap_err("failed to prepare terminal for drawing")?;
 
+    if let Some(config::Command::Design { output }) = &cfg.command {
+        let r = design::run(&mut term_scr, output);
+
+        term_scr
+            .deinit()
+            .wrap_err("failed to restore the terminal previous state")?;
+
+        if r? {
+            println!("The piece set has been written to {}", output.display());
+        }
+
+        return Ok(());
+    }
+
+    if let Some(config::Command::Themes { .. }) = &cfg.command {
+        let r = theme::run(&mut term_scr);
+
+        term_scr
+            .deinit()
+            .wrap_err("failed to restore the terminal previous state")?;
+
+        return r;
+    }
+
+    if cfg.bg_color.is_none() && !cfg.safe_mode {
+        cfg.term_bg_color = term_scr.query_bg_color();
+    }
+
+    if let Some(grid) = cfg.mosaic {
+        let r = mosaic::run(&mut term_scr, &cfg, grid);
+
+        term_scr
+            .deinit()
+            .wrap_err("failed to restore the terminal previous state")?;
+
+        return r;
+    }
+
+    if let Some(config::Command::Gallery { seeds, steps }) = &cfg.command {
+        match gallery::run(&mut term_scr, &cfg, *seeds as usize, *steps) {
+            Ok(Some(seed)) => {
+                cfg.seed = Some(seed);
+                term_scr.clear();
+            }
+            r => {
+                term_scr
+                    .deinit()
+                    .wrap_err("failed to restore the terminal previous state")?;
+
+                return r.map(|_| ());
+            }
+        }
+    }
+
+    let keypress_cmd = cfg.until_keypress_cmd.clone();
+    let print_summary = cfg.summary;
+
     let mut app = Screensaver::new(term_scr, cfg)?;
     let r = app.run();
 
     app.deinit()
         .wrap_err("failed to restore the terminal previous state")?;
 
-    r
+    for w in app.watchdog_log().iter().chain(app.warnings()) {
+        eprintln!("warning: {w}");
+    }
+
+    if let Some(s) = app.summary().filter(|_| print_summary && r.is_ok()) {
+        println!("{s}");
+    }
+
+    // The command (e.g. a screen locker) runs even if the animation has failed
+    let cmd_r = keypress_cmd.map_or(Ok(()), |cmd| run_keypress_cmd(&cmd));
+
+    if let (Err(_), Err(e)) = (&r, &cmd_r) {
+        eprintln!("warning: {e:#}");
+    }
+
+    r?;
+    cmd_r
+}
+
+/// Run --until-keypress-cmd via `sh -c`. Fails if the command can't be run or exits unsuccessfully.
+fn run_keypress_cmd(cmd: &str) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .status()
+        .wrap_err_with(|| format!("failed to run `{cmd}`"))?;
+
+    if !status.success() {
+        bail!("`{cmd}` has failed ({status})");
+    }
+
+    Ok(())
 }
@@ -0,0 +1,129 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    grid::Grid,
+    plane_2d::{Direction, Point},
+};
+use rand::{seq::SliceRandom, Rng};
+use std::collections::VecDeque;
+
+/// A piece of a maze passage.
+#[derive(Copy, Clone, Debug)]
+pub struct MazePiece {
+    /// Position of the piece on the screen.
+    pub pos: Point,
+    /// Direction of the preceeding piece.
+    pub prev_dir: Direction,
+    /// Direction of the piece.
+    pub dir: Direction,
+    /// Indicates that the piece starts a new branch of passages.
+    pub new_branch: bool,
+}
+
+/// Randomized depth-first maze generator which carves passages piece by piece.
+///
+/// Each maze cell takes two screen cells in both directions, the second one is a wall which may be
+/// carved out to connect the neighbouring cells.
+#[derive(Debug)]
+pub struct Maze {
+    /// Maze cells which have been already visited.
+    visited: Grid<bool>,
+    /// Current path: cells, directions from which they were entered and whether they have been
+    /// drawn.
+    stack: Vec<(Point, Direction, bool)>,
+    /// Carved pieces which haven't been returned yet.
+    pending: VecDeque<MazePiece>,
+    /// Indicates that the next carved piece starts a new branch.
+    new_branch: bool,
+}
+
+impl Maze {
+    /// Create a `Maze` filling the screen of specified size.
+    pub fn new(scr_size: (usize, usize), rng: &mut impl Rng) -> Self {
+        let size = (scr_size.0.div_ceil(2), scr_size.1.div_ceil(2));
+        let mut visited = Grid::new(size);
+
+        let start = Point {
+            x: rng.gen_range(0..size.0) as isize,
+            y: rng.gen_range(0..size.1) as isize,
+        };
+
+        if let Some(v) = visited.get_mut(start) {
+            *v = true;
+        }
+
+        Self {
+            visited,
+            stack: vec![(start, rng.gen(), false)],
+            pending: VecDeque::new(),
+            new_branch: true,
+        }
+    }
+
+    /// Carve the next piece. Returns `None` when the maze is complete.
+    pub fn next_piece(&mut self, rng: &mut impl Rng) -> Option<MazePiece> {
+        while self.pending.is_empty() {
+            let (cell, entered, drawn) = *self.stack.last()?;
+
+            let dirs: Vec<Direction> = Direction::ALL
+                .into_iter()
+                .filter(|d| {
+                    let mut n = cell;
+                    n.advance(*d);
+
+                    self.visited.get(n) == Some(&false)
+                })
+                .collect();
+
+            let pos = Point {
+                x: cell.x * 2,
+                y: cell.y * 2,
+            };
+
+            if let Some(&dir) = dirs.choose(rng) {
+                if !drawn {
+                    self.emit(pos, entered, dir);
+                }
+
+                let mut wall = pos;
+                wall.advance(dir);
+                self.emit(wall, dir, dir);
+
+                let mut next = cell;
+                next.advance(dir);
+
+                if let Some(v) = self.visited.get_mut(next) {
+                    *v = true;
+                }
+
+                if let Some(top) = self.stack.last_mut() {
+                    top.2 = true;
+                }
+
+                self.stack.push((next, dir, false));
+            } else {
+                // Dead end, go back to the last cell with unvisited neighbours
+                if !drawn {
+                    self.emit(pos, entered, entered);
+                }
+
+                self.stack.pop();
+                self.new_branch = true;
+            }
+        }
+
+        self.pending.pop_front()
+    }
+
+    /// Queue a carved piece.
+    fn emit(&mut self, pos: Point, prev_dir: Direction, dir: Direction) {
+        self.pending.push_back(MazePiece {
+            pos,
+            prev_dir,
+            dir,
+            new_branch: std::mem::take(&mut self.new_branch),
+        });
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use notify_rust::Notification;
+use std::{thread, time::Duration};
+
+/// Number of drawn pieces of the first milestone. Each next one is 10 times further.
+const FIRST_PIECES_MILESTONE: u64 = 1_000_000;
+
+/// Uptime between the milestones.
+const UPTIME_MILESTONE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Tracks the milestones of a long run and announces them by desktop notifications, for those
+/// who leave rxpipes running as an ambient uptime companion (see --notify-milestones).
+#[derive(Debug)]
+pub struct Milestones {
+    /// Number of drawn pieces of the next milestone.
+    next_pieces: u64,
+    /// Uptime of the next milestone.
+    next_uptime: Duration,
+}
+
+impl Milestones {
+    /// Create `Milestones`.
+    pub fn new() -> Self {
+        Self {
+            next_pieces: FIRST_PIECES_MILESTONE,
+            next_uptime: UPTIME_MILESTONE,
+        }
+    }
+
+    /// Send a notification for each milestone reached by the number of pieces drawn since the
+    /// start and the uptime.
+    pub fn check(&mut self, pieces: u64, uptime: Duration) {
+        if pieces >= self.next_pieces {
+            notify(format!(
+                "{} million pieces drawn",
+                self.next_pieces / FIRST_PIECES_MILESTONE
+            ));
+
+            self.next_pieces = self.next_pieces.saturating_mul(10);
+        }
+
+        if uptime >= self.next_uptime {
+            let days = self.next_uptime.as_secs() / UPTIME_MILESTONE.as_secs();
+
+            notify(format!(
+                "Running for {days} day{}",
+                if days == 1 { "" } else { "s" }
+            ));
+
+            self.next_uptime += UPTIME_MILESTONE;
+        }
+    }
+}
+
+/// Show a desktop notification. It's sent from a separate thread, as talking to the notification
+/// server may take a while; failures are ignored, since there is nowhere to report them.
+fn notify(body: String) {
+    thread::spawn(move || {
+        let _ = Notification::new()
+            .appname("rxpipes")
+            .summary("rxpipes milestone")
+            .body(&body)
+            .show();
+    });
+}
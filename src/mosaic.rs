@@ -0,0 +1,219 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas, config::Config, plane_2d::Point, screensaver::Screensaver,
+    terminal::TerminalScreen,
+};
+use eyre::{Result, WrapErr};
+use std::time::Duration;
+use termwiz::{
+    cell::unicode_column_width,
+    color::ColorAttribute,
+    input::{InputEvent, KeyCode, KeyEvent, Modifiers},
+    terminal::Terminal,
+};
+
+/// Color of the separators between the tiles.
+const SEPARATOR_COLOR: ColorAttribute = ColorAttribute::PaletteIndex(8);
+
+/// Grid of small independent screensavers (see --mosaic).
+pub struct Mosaic {
+    /// Screensavers of the tiles, each simulating on its own canvas.
+    tiles: Vec<Screensaver>,
+    /// Layer with the separators between the tiles.
+    separators: Canvas,
+    /// Whether the separators have to be copied to the terminal.
+    separators_changed: bool,
+    /// Labels shown in the top left corner of the tiles.
+    labels: Vec<Canvas>,
+}
+
+impl Mosaic {
+    /// Create a `Mosaic` of the grid of tiles (COLSxROWS) filling the screen of specified size.
+    /// The grid is made coarser if the screen is too small for it.
+    pub fn new(scr_size: (usize, usize), cfg: &Config, grid: (usize, usize)) -> Result<Self> {
+        // Each tile needs at least one cell, and there is a separator between each two
+        let cols = grid.0.min(scr_size.0.div_ceil(2)).max(1);
+        let rows = grid.1.min(scr_size.1.div_ceil(2)).max(1);
+        let xs = spans(scr_size.0, cols);
+        let ys = spans(scr_size.1, rows);
+
+        let mut separators = Canvas::new(Point { x: 0, y: 0 }, scr_size);
+        separators.set_fg_color(SEPARATOR_COLOR);
+
+        for &(y, _) in &ys[1..] {
+            for x in 0..scr_size.0 {
+                let crossing = xs[1..].iter().any(|&(x0, _)| x + 1 == x0);
+
+                separators.move_to(Point {
+                    x: x as isize,
+                    y: y as isize - 1,
+                });
+                separators.put_str(if crossing { "┼" } else { "─" });
+            }
+        }
+
+        for &(x, _) in &xs[1..] {
+            for y in 0..scr_size.1 {
+                if ys[1..].iter().any(|&(y0, _)| y + 1 == y0) {
+                    continue;
+                }
+
+                separators.move_to(Point {
+                    x: x as isize - 1,
+                    y: y as isize,
+                });
+                separators.put_str("│");
+            }
+        }
+
+        let mut tiles = vec![];
+
+        for (i, &(y, h)) in ys.iter().enumerate() {
+            for (j, &(x, w)) in xs.iter().enumerate() {
+                let mut tile_cfg = cfg.clone();
+
+                // Each tile runs its own simulation
+                tile_cfg.seed = cfg.seed.map(|s| s.wrapping_add((i * cols + j) as u64));
+                // Only the animation itself fits into a tile
+                tile_cfg.show_stats = false;
+                tile_cfg.bouncer = None;
+                tile_cfg.timer = None;
+                tile_cfg.idle_start = None;
+                tile_cfg.screen_reader = false;
+                tile_cfg.wrap_cmd = None;
+                tile_cfg.sync_server = None;
+                tile_cfg.sync_connect = None;
+
+                #[cfg(feature = "remote")]
+                {
+                    tile_cfg.listen = None;
+                    tile_cfg.mqtt_topic = None;
+                }
+
+                let mut tile = Screensaver::headless((w, h), tile_cfg)?;
+                tile.canvas_mut().pos = Point {
+                    x: x as isize,
+                    y: y as isize,
+                };
+
+                tiles.push(tile);
+            }
+        }
+
+        Ok(Self {
+            tiles,
+            separators,
+            separators_changed: true,
+            labels: vec![],
+        })
+    }
+
+    /// Retrieve the screensavers of the tiles, from left to right and top to bottom.
+    pub fn tiles(&self) -> &[Screensaver] {
+        &self.tiles
+    }
+
+    /// Label each tile with the text returned for its index, shown in reverse colors on top of
+    /// the pipes.
+    pub fn label_tiles(&mut self, text: impl Fn(usize) -> String) {
+        self.labels = self
+            .tiles
+            .iter_mut()
+            .enumerate()
+            .map(|(i, t)| {
+                let canv = t.canvas_mut();
+                // Labels longer than the tile are cut
+                let s: String = text(i).chars().take(canv.size().0).collect();
+                let mut c = Canvas::new(canv.pos, (unicode_column_width(&s, None), 1));
+
+                c.fill(ColorAttribute::PaletteIndex(7));
+                c.set_fg_color(ColorAttribute::PaletteIndex(0));
+                c.put_str(s);
+
+                c
+            })
+            .collect();
+    }
+
+    /// Advance the simulation of each tile by one step.
+    pub fn step(&mut self) {
+        for t in &mut self.tiles {
+            t.simulate(1);
+        }
+    }
+
+    /// Copy the tiles and the separators to the terminal and render them.
+    pub fn render(&mut self, term_scr: &mut TerminalScreen) -> Result<()> {
+        if self.separators_changed {
+            term_scr.copy_canvas(&self.separators);
+            self.separators_changed = false;
+        }
+
+        for t in &mut self.tiles {
+            term_scr.copy_canvas_changes(t.canvas_mut(), false);
+        }
+
+        for l in &self.labels {
+            term_scr.copy_canvas(l);
+        }
+
+        term_scr.render()
+    }
+}
+
+/// Split the length into the number of spans separated by one cell. Returns the start and length
+/// of each span.
+fn spans(len: usize, n: usize) -> Vec<(usize, usize)> {
+    let avail = len.saturating_sub(n - 1);
+
+    (0..n)
+        .map(|i| {
+            let start = i * avail / n;
+            let end = (i + 1) * avail / n;
+
+            (start + i, end - start)
+        })
+        .collect()
+}
+
+/// Run the grid of small independent screensavers (COLSxROWS) until the user quits. Space pauses
+/// all of them.
+pub fn run(term_scr: &mut TerminalScreen, cfg: &Config, grid: (usize, usize)) -> Result<()> {
+    let delay = Duration::from_millis(1000 / cfg.fps as u64);
+    let mut mosaic = Mosaic::new(term_scr.size(), cfg, grid)?;
+    let mut pause = false;
+
+    loop {
+        if !pause {
+            mosaic.step();
+        }
+
+        mosaic.render(term_scr)?;
+
+        let event = term_scr
+            .terminal()
+            .terminal()
+            .poll_input(Some(delay))
+            .wrap_err("cannot read incoming events")?;
+
+        match event {
+            Some(InputEvent::Key(KeyEvent {
+                key,
+                modifiers: Modifiers::NONE,
+            })) => match key {
+                KeyCode::Escape | KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char(' ') => pause = !pause,
+                _ => {}
+            },
+            Some(InputEvent::Resized { cols, rows }) => {
+                term_scr.resize((cols, rows));
+                term_scr.clear();
+                mosaic = Mosaic::new(term_scr.size(), cfg, grid)?;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{env, process::Command};
+
+/// Longest string GNU screen passes through in one envelope.
+const SCREEN_CHUNK_LEN: usize = 768;
+
+/// Terminal multiplexer rxpipes runs inside. Multiplexers interpret the escape sequences of the
+/// programs themselves, so the sequences they don't know (e.g. OSC 52) must be wrapped in a
+/// passthrough envelope to reach the real terminal instead of being dropped or printed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    /// Detect the multiplexer from the environment, if rxpipes runs inside one which needs the
+    /// passthrough.
+    pub fn detect() -> Option<Self> {
+        if env::var_os("TMUX").is_some() {
+            // Otherwise tmux drops the wrapped sequences, but still handles those it knows itself
+            // (e.g. OSC 52 with set-clipboard)
+            tmux_allows_passthrough().then_some(Self::Tmux)
+        } else if env::var_os("STY").is_some() {
+            Some(Self::Screen)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap the escape sequence in the passthrough envelope of the multiplexer.
+    pub fn passthrough(&self, seq: &str) -> String {
+        match self {
+            // The escape characters inside are doubled
+            Self::Tmux => format!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b")),
+            // Screen limits the length of an envelope, but passes consecutive ones as a whole.
+            // The string terminator would end the envelope, so BEL is used instead
+            Self::Screen => {
+                let mut res = String::new();
+                let mut chunk = String::new();
+
+                for c in seq.replace("\x1b\\", "\x07").chars() {
+                    if chunk.len() + c.len_utf8() > SCREEN_CHUNK_LEN {
+                        res.push_str(&format!("\x1bP{chunk}\x1b\\"));
+                        chunk.clear();
+                    }
+
+                    chunk.push(c);
+                }
+
+                res.push_str(&format!("\x1bP{chunk}\x1b\\"));
+
+                res
+            }
+        }
+    }
+}
+
+/// Check whether tmux passes the wrapped escape sequences to the terminal (option
+/// `allow-passthrough`, tmux 3.3+).
+fn tmux_allows_passthrough() -> bool {
+    Command::new("tmux")
+        .args(["show-options", "-gqv", "allow-passthrough"])
+        .output()
+        .is_ok_and(|o| matches!(String::from_utf8_lossy(&o.stdout).trim(), "on" | "all"))
+}
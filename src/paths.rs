@@ -0,0 +1,25 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{env, path::PathBuf};
+
+/// Retrieve the directory for persistent state of rxpipes (`$XDG_STATE_HOME/rxpipes` or
+/// `~/.local/state/rxpipes`).
+pub fn state_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+
+    Some(base.join("rxpipes"))
+}
+
+/// Retrieve the directory for configuration of rxpipes (`$XDG_CONFIG_HOME/rxpipes` or
+/// `~/.config/rxpipes`).
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+
+    Some(base.join("rxpipes"))
+}
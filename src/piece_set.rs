@@ -0,0 +1,295 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{config::Config, plane_2d::Direction};
+use eyre::{bail, eyre, Result, WrapErr};
+use std::{fs, path::Path};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Number of pieces in a piece set.
+pub const PIECE_COUNT: usize = 6;
+
+/// Pieces of the default piece set written in the canonical order.
+pub const CANONICAL_PIECES: &str = "│─┌┐└┘";
+
+/// Map of default piece sets.
+const DEFAULT_PIECE_SETS: [[char; PIECE_COUNT]; 7] = [
+    ['|', '-', '+', '+', '+', '+'],
+    ['·', '·', '·', '·', '·', '·'],
+    ['•', '•', '•', '•', '•', '•'],
+    ['│', '─', '┌', '┐', '└', '┘'],
+    ['│', '─', '╭', '╮', '╰', '╯'],
+    ['║', '═', '╔', '╗', '╚', '╝'],
+    ['┃', '━', '┏', '┓', '┗', '┛'], // default
+];
+
+/// Maximal number of columns advanced by a horizontal step.
+pub const MAX_ADVANCE: usize = 4;
+
+/// Metadata of a piece set telling the renderer how to space the pieces.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PieceSetMeta {
+    /// How many columns a pipe advances with each horizontal step.
+    pub advance: usize,
+    /// Glyph filling the columns skipped by a horizontal step. Not needed by wide glyphs, which
+    /// cover the skipped columns themselves.
+    pub joint: Option<String>,
+    /// Glyphs each piece cycles through over time, empty for the pieces which aren't animated.
+    pub frames: Vec<Vec<String>>,
+}
+
+impl Default for PieceSetMeta {
+    fn default() -> Self {
+        Self {
+            advance: 1,
+            joint: None,
+            frames: vec![],
+        }
+    }
+}
+
+impl PieceSetMeta {
+    /// Retrieve the number of cells a pipe advances with each step in the direction.
+    pub fn steps(&self, dir: Direction) -> usize {
+        if dir.is_horizontal() {
+            self.advance
+        } else {
+            1
+        }
+    }
+
+    /// Retrieve the glyphs the piece cycles through, if it's animated.
+    pub fn frames(&self, idx: usize) -> Option<&[String]> {
+        self.frames
+            .get(idx)
+            .filter(|f| f.len() > 1)
+            .map(|f| f.as_slice())
+    }
+}
+
+/// Map from directions to indices for indexing default piece sets.
+///
+/// Index via `[DIRECTION OF THE PREVIOUS PIECE][CURRENT DIRECTION]`
+const PIECE_SETS_IDX_MAP: [[usize; 4]; 4] = [
+    // Up
+    [0, 0, 2, 3],
+    // Down
+    [0, 0, 4, 5],
+    // Right
+    [5, 3, 1, 1],
+    // Left
+    [4, 2, 1, 1],
+];
+
+/// Retrieve the index of the piece connecting the previous direction with the current one.
+pub fn piece_idx(prev_dir: Direction, dir: Direction) -> usize {
+    PIECE_SETS_IDX_MAP[prev_dir as usize][dir as usize]
+}
+
+/// Retrieve the pieces of the default piece set with specified ID.
+pub fn default_pieces(id: usize) -> Vec<String> {
+    DEFAULT_PIECE_SETS[id]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// Retrieve the metadata of the default piece set with specified ID.
+pub fn default_meta(id: usize) -> PieceSetMeta {
+    match id {
+        // Terminal cells are about twice as tall as wide, so space the dots evenly
+        1 | 2 => PieceSetMeta {
+            advance: 2,
+            ..Default::default()
+        },
+        _ => PieceSetMeta::default(),
+    }
+}
+
+/// Retrieve the metadata of the configured piece set.
+pub fn meta(cfg: &Config) -> PieceSetMeta {
+    if cfg.custom_piece_set.is_some() {
+        cfg.custom_piece_set_meta.clone().unwrap_or_default()
+    } else {
+        default_meta(cfg.piece_set as usize)
+    }
+}
+
+/// Retrieve the glyph of the piece from the configured piece set.
+pub fn glyph(cfg: &Config, idx: usize) -> String {
+    if let Some(pieces) = &cfg.custom_piece_set {
+        pieces[idx].clone()
+    } else {
+        DEFAULT_PIECE_SETS[cfg.piece_set as usize][idx].to_string()
+    }
+}
+
+/// Split a piece set string into pieces. Unicode grapheme clusters are treated as single pieces.
+pub fn split_pieces(s: &str) -> Vec<String> {
+    s.graphemes(true) // true here means iterate over extended grapheme clusters (UAX #29).
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Split a piece set string into pieces and their animation frames. A piece written as `[...]` is
+/// animated: it cycles through the glyphs inside the brackets over time (e.g. `[▖▘▝▗]`), starting
+/// with the first one, which is returned as the piece.
+pub fn split_animated_pieces(s: &str) -> (Vec<String>, Vec<Vec<String>>) {
+    let glyphs = split_pieces(s);
+    let mut pieces = vec![];
+    let mut frames = vec![];
+    let mut i = 0;
+
+    while i < glyphs.len() {
+        // A lone bracket is an ordinary piece
+        let group_len = (glyphs[i] == "[")
+            .then(|| glyphs[i + 1..].iter().position(|g| g == "]"))
+            .flatten()
+            .filter(|&n| n > 0);
+
+        match group_len {
+            Some(n) => {
+                let f = glyphs[i + 1..i + 1 + n].to_vec();
+
+                pieces.push(f[0].clone());
+                frames.push(f);
+                i += n + 2;
+            }
+            None => {
+                pieces.push(glyphs[i].clone());
+                frames.push(vec![]);
+                i += 1;
+            }
+        }
+    }
+
+    // Static piece sets need no frames
+    if frames.iter().all(|f| f.len() <= 1) {
+        frames.clear();
+    }
+
+    (pieces, frames)
+}
+
+/// Load a piece set from a file.
+///
+/// The file consists of `key = value` lines, lines starting with `#` are comments. The `pieces` key
+/// holds all 6 pieces written according to `│─┌┐└┘`; a piece written as `[...]` cycles through the
+/// glyphs inside the brackets. Optional keys `advance` (columns advanced by a horizontal step,
+/// 1 - 4) and `joint` (glyph filling the skipped columns) define the spacing.
+pub fn load_piece_set_file(path: &Path) -> Result<(Vec<String>, PieceSetMeta)> {
+    let text = fs::read_to_string(path)
+        .wrap_err_with(|| format!("cannot read the piece set file {}", path.display()))?;
+    let mut pieces = None;
+    let mut meta = PieceSetMeta::default();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+            Some(("pieces", v)) => {
+                let (p, frames) = split_animated_pieces(v);
+
+                pieces = Some(p);
+                meta.frames = frames;
+            }
+            Some(("advance", v)) => {
+                meta.advance = v
+                    .parse()
+                    .ok()
+                    .filter(|a| (1..=MAX_ADVANCE).contains(a))
+                    .ok_or_else(|| {
+                        eyre!(
+                            "{}:{}: `advance` must be a number from 1 to {MAX_ADVANCE}",
+                            path.display(),
+                            i + 1
+                        )
+                    })?;
+            }
+            Some(("joint", v)) => meta.joint = Some(v.to_string()).filter(|j| !j.is_empty()),
+            Some(_) => {}
+            None => bail!("{}:{}: expected `key = value`", path.display(), i + 1),
+        }
+    }
+
+    let Some(pieces) = pieces else {
+        bail!("{}: the `pieces` key is missing", path.display());
+    };
+
+    if pieces.len() != PIECE_COUNT {
+        bail!(
+            "{}: expected {PIECE_COUNT} pieces, found {}",
+            path.display(),
+            pieces.len()
+        );
+    }
+
+    Ok((pieces, meta))
+}
+
+/// Save a piece set to a file which can be loaded with [`load_piece_set_file`].
+pub fn save_piece_set_file(path: &Path, pieces: &[String]) -> Result<()> {
+    let text = format!(
+        "# rxpipes piece set (pieces are written according to {CANONICAL_PIECES})\npieces = {}\n",
+        pieces.concat()
+    );
+
+    fs::write(path, text).wrap_err_with(|| format!("cannot write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(s: &str) -> (Vec<String>, Vec<Vec<String>>) {
+        split_animated_pieces(s)
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn static_pieces() {
+        assert_eq!(split("┃━┏"), (strings(&["┃", "━", "┏"]), vec![]));
+        // A group of a single glyph is not animated
+        assert_eq!(split("a[b]c"), (strings(&["a", "b", "c"]), vec![]));
+    }
+
+    #[test]
+    fn animated_pieces() {
+        assert_eq!(
+            split("a[▖▘▝▗]b"),
+            (
+                strings(&["a", "▖", "b"]),
+                vec![vec![], strings(&["▖", "▘", "▝", "▗"]), vec![]]
+            )
+        );
+    }
+
+    #[test]
+    fn grapheme_clusters_in_groups() {
+        assert_eq!(
+            split("[e\u{301}o\u{308}]"),
+            (
+                strings(&["e\u{301}"]),
+                vec![strings(&["e\u{301}", "o\u{308}"])]
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_groups() {
+        // Lone and empty brackets are ordinary pieces
+        assert_eq!(split("[ab"), (strings(&["[", "a", "b"]), vec![]));
+        assert_eq!(split("ab]"), (strings(&["a", "b", "]"]), vec![]));
+        assert_eq!(split("[]"), (strings(&["[", "]"]), vec![]));
+        assert_eq!(split("["), (strings(&["["]), vec![]));
+        assert_eq!(split(""), (vec![], vec![]));
+    }
+}
@@ -3,11 +3,17 @@
 // This file is licensed under the MIT License (see LICENSE.md).
 
 use crate::{
-    color::{ColorPalette, GradientDir},
+    canvas::TextStyle,
+    color::{shift_base_color, shift_color, ColorPool, GradientDir},
+    config::GlyphAttr,
     plane_2d::{Direction, Point},
 };
-use rand::{thread_rng, Rng};
-use termwiz::color::{ColorAttribute, SrgbaTuple};
+use rand::Rng;
+use std::time::Duration;
+use termwiz::{cell::Cell, color::ColorAttribute};
+
+/// Gradient shift accumulated before a base color switches to its next variant.
+const BASE_COLOR_GRADIENT_SPAN: f32 = 0.25;
 
 /// Represents a piece of pipe.
 #[derive(Copy, Clone, Default, Debug)]
@@ -22,6 +28,12 @@ pub struct PipePiece {
     pub color: Option<ColorAttribute>,
     /// Gradient direction.
     pub gradient: GradientDir,
+    /// Gradient shift accumulated since a base color last switched its variant.
+    pub gradient_acc: f32,
+    /// Depth of the pipe (0.0 is the front, 1.0 is the back).
+    pub depth: f32,
+    /// Text attributes of the piece.
+    pub style: TextStyle,
 }
 
 impl PipePiece {
@@ -31,32 +43,128 @@ impl PipePiece {
     }
 
     /// Create a piece with random direction and color.
-    pub fn gen(palette: ColorPalette) -> Self {
-        let mut rng = thread_rng();
+    pub fn gen(pool: &mut ColorPool, rng: &mut impl Rng) -> Self {
         let initial_dir: Direction = rng.gen();
 
         Self {
             pos: Point { x: 0, y: 0 },
             prev_dir: initial_dir,
             dir: initial_dir,
-            color: gen_color(palette),
+            color: pool.gen(rng),
             gradient: rng.gen(),
+            gradient_acc: 0.0,
+            depth: rng.gen(),
+            style: TextStyle::default(),
         }
     }
+
+    /// Advance the gradient of the color by `step` in the gradient direction. RGB colors are
+    /// shifted gradually, base colors switch between their dim and bright variants.
+    pub fn advance_gradient(&mut self, step: f32) {
+        let step = match self.gradient {
+            GradientDir::Up => step,
+            GradientDir::Down => -step,
+        };
+
+        self.color = match self.color {
+            Some(ColorAttribute::PaletteIndex(i)) => {
+                self.gradient_acc += step;
+
+                if self.gradient_acc.abs() < BASE_COLOR_GRADIENT_SPAN {
+                    return;
+                }
+
+                let up = self.gradient_acc > 0.0;
+                self.gradient_acc = 0.0;
+
+                Some(ColorAttribute::PaletteIndex(shift_base_color(i, up)))
+            }
+            c => c.map(|c| shift_color(c, step)),
+        };
+    }
+}
+
+/// Create the text style of a new pipe from the attributes given by `--attr`.
+pub fn gen_style(attrs: &[GlyphAttr], rng: &mut impl Rng) -> TextStyle {
+    let has = |a| attrs.contains(&a);
+
+    if !has(GlyphAttr::Random) {
+        return TextStyle {
+            bold: has(GlyphAttr::Bold),
+            italic: has(GlyphAttr::Italic),
+            blink: has(GlyphAttr::Blink),
+        };
+    }
+
+    let any = attrs.len() == 1;
+
+    TextStyle {
+        bold: (any || has(GlyphAttr::Bold)) && rng.gen(),
+        italic: (any || has(GlyphAttr::Italic)) && rng.gen(),
+        blink: has(GlyphAttr::Blink) && rng.gen(),
+    }
+}
+
+/// Record of a piece drawn by a pipe.
+#[derive(Clone, Debug)]
+pub struct PieceRecord {
+    /// Position of the piece.
+    pub pos: Point,
+    /// Contents of the cell before the piece was drawn.
+    pub prev_cell: Cell,
+    /// ID of the pipe which occupied the cell before.
+    pub prev_occupancy: Option<u64>,
+}
+
+/// Record of all pieces drawn by a pipe, so it can be erased later.
+#[derive(Clone, Debug)]
+pub struct PipeRecord {
+    /// ID of the pipe.
+    pub id: u64,
+    /// Drawn pieces in the drawing order.
+    pub pieces: Vec<PieceRecord>,
+}
+
+/// Statistics of the pipes finished since the start.
+#[derive(Clone, Debug, Default)]
+pub struct PipeStats {
+    /// Number of finished pipes.
+    pub pipes: u64,
+    /// Total of the lengths of all pipes (in pieces).
+    pub pieces: u64,
+    /// Length of the longest pipe (in pieces).
+    pub longest: u64,
+    /// Total of the turns of all pipes.
+    pub turns: u64,
+    /// Total of the lifetimes of all pipes.
+    pub lifetime: Duration,
 }
 
-/// Pick random color from the specified palette.
-fn gen_color(palette: ColorPalette) -> Option<ColorAttribute> {
-    let mut rng = thread_rng();
-
-    match palette {
-        ColorPalette::None => None,
-        ColorPalette::BaseColors => Some(ColorAttribute::PaletteIndex(rng.gen_range(0..16))),
-        ColorPalette::Rgb => Some(ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
-            rng.gen(),
-            rng.gen(),
-            rng.gen(),
-            1.0,
-        ))),
+impl PipeStats {
+    /// Count a finished pipe.
+    pub fn add(&mut self, length: u64, turns: u64, lifetime: Duration) {
+        self.pipes += 1;
+        self.pieces += length;
+        self.longest = self.longest.max(length);
+        self.turns += turns;
+        self.lifetime += lifetime;
+    }
+
+    /// Retrieve the average number of turns of a pipe.
+    pub fn avg_turns(&self) -> f64 {
+        if self.pipes == 0 {
+            return 0.0;
+        }
+
+        self.turns as f64 / self.pipes as f64
+    }
+
+    /// Retrieve the average lifetime of a pipe.
+    pub fn avg_lifetime(&self) -> Duration {
+        if self.pipes == 0 {
+            return Duration::ZERO;
+        }
+
+        self.lifetime / self.pipes as u32
     }
 }
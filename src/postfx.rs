@@ -0,0 +1,149 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{canvas::Canvas, lut::ColorLut};
+use eyre::{Result, WrapErr};
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+use termwiz::{
+    cell::Cell,
+    color::{ColorAttribute, SrgbaTuple},
+};
+
+/// Lines of cells of a canvas.
+pub type Cells = Vec<Vec<Cell>>;
+
+/// Effect applied to all characters of a canvas.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pass {
+    /// Make the characters darker by the amount, up to the minimal color. Characters darker than
+    /// the minimal color are lightened instead.
+    Darken { amount: f32, min: SrgbaTuple },
+}
+
+impl Pass {
+    /// Apply the effect to the cells.
+    pub fn apply(&self, cells: &mut Cells) {
+        match *self {
+            Self::Darken { amount, min } => darken(cells, amount, min),
+        }
+    }
+}
+
+/// Make the RGB colors of all characters darker (see [`Pass::Darken`]).
+fn darken(cells: &mut Cells, amount: f32, min: SrgbaTuple) {
+    let lut = ColorLut::darken(amount, min);
+
+    for cell in cells.iter_mut().flatten() {
+        if cell.str().trim_ascii().is_empty() {
+            continue;
+        }
+
+        if let ColorAttribute::TrueColorWithDefaultFallback(c) = cell.attrs().foreground() {
+            cell.attrs_mut()
+                .set_foreground(ColorAttribute::TrueColorWithDefaultFallback(lut.apply(c)));
+        }
+    }
+}
+
+/// Applies effects to snapshots of a canvas on a worker thread, so the main thread can keep
+/// simulating in the meantime. The processed snapshot is merged back into the canvas once it's
+/// ready; the cells drawn in the meantime are kept.
+pub struct PostProcessor {
+    /// Snapshots to process, with the effects to apply.
+    jobs: Sender<(Cells, Vec<Pass>)>,
+    /// Processed snapshots.
+    done: Receiver<Cells>,
+    /// Whether a snapshot is being processed.
+    busy: bool,
+}
+
+impl PostProcessor {
+    /// Create a `PostProcessor` and start its worker thread. The thread ends when the
+    /// `PostProcessor` is dropped.
+    pub fn new() -> Result<Self> {
+        let (jobs, rx) = mpsc::channel::<(Cells, Vec<Pass>)>();
+        let (tx, done) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("postfx".to_string())
+            .spawn(move || {
+                for (mut cells, passes) in rx {
+                    for p in passes {
+                        p.apply(&mut cells);
+                    }
+
+                    if tx.send(cells).is_err() {
+                        break;
+                    }
+                }
+            })
+            .wrap_err("cannot start the post-processing thread")?;
+
+        Ok(Self {
+            jobs,
+            done,
+            busy: false,
+        })
+    }
+
+    /// Start applying the effects to a snapshot of the canvas. If the previous snapshot is still
+    /// being processed, it's merged first.
+    pub fn submit(&mut self, canv: &mut Canvas, passes: Vec<Pass>) {
+        self.finish(canv);
+
+        let mut cells = canv.snapshot();
+
+        match self.jobs.send((cells, passes)) {
+            Ok(()) => self.busy = true,
+            // The worker is gone, apply the effects right away
+            Err(mpsc::SendError((c, passes))) => {
+                cells = c;
+
+                for p in passes {
+                    p.apply(&mut cells);
+                }
+
+                canv.merge_snapshot(cells);
+            }
+        }
+    }
+
+    /// Merge the processed snapshot into the canvas if it's ready. Returns `true` if it was merged.
+    pub fn poll(&mut self, canv: &mut Canvas) -> bool {
+        if !self.busy {
+            return false;
+        }
+
+        match self.done.try_recv() {
+            Ok(cells) => {
+                self.busy = false;
+                canv.merge_snapshot(cells);
+
+                true
+            }
+            Err(mpsc::TryRecvError::Empty) => false,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.busy = false;
+
+                false
+            }
+        }
+    }
+
+    /// Wait for the snapshot being processed and merge it into the canvas.
+    pub fn finish(&mut self, canv: &mut Canvas) {
+        if !self.busy {
+            return;
+        }
+
+        self.busy = false;
+
+        if let Ok(cells) = self.done.recv() {
+            canv.merge_snapshot(cells);
+        }
+    }
+}
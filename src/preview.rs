@@ -0,0 +1,146 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas,
+    color::{ColorPalette, ColorPool},
+    piece_set::piece_idx,
+    pipe::PipePiece,
+    plane_2d::Point,
+};
+use rand::{thread_rng, Rng};
+use termwiz::color::ColorAttribute;
+
+/// After how many pieces the preview starts over.
+const MAX_PIECES: u32 = 150;
+
+/// Probability of turning the pipe.
+const TURNING_PROB: f64 = 0.2;
+
+/// Minimal and maximal length of the pipes.
+const PIPE_LENGTH: (u32, u32) = (20, 60);
+
+/// Small animated sample of pipes, used by the interactive tools.
+pub struct Preview {
+    /// Layer with the sample.
+    canv: Canvas,
+    /// Pieces to draw with.
+    pieces: Vec<String>,
+    /// Colors of the pipes.
+    pool: ColorPool,
+    /// Gradient step, if the gradient is enabled.
+    gradient_step: Option<f32>,
+    /// Background color.
+    bg: ColorAttribute,
+    /// Currently drawn pipe.
+    piece: PipePiece,
+    /// Pieces left to draw in the current pipe.
+    pieces_remaining: u32,
+    /// Pieces drawn since the preview was cleared.
+    drawn: u32,
+}
+
+impl Preview {
+    /// Create a colorless `Preview` at the position with specified size.
+    pub fn new(pos: Point, size: (usize, usize), pieces: Vec<String>) -> Self {
+        let mut s = Self {
+            canv: Canvas::new(pos, size),
+            pieces,
+            pool: ColorPool {
+                palette: ColorPalette::None,
+                ..Default::default()
+            },
+            gradient_step: None,
+            bg: ColorAttribute::Default,
+            piece: PipePiece::new(),
+            pieces_remaining: 0,
+            drawn: 0,
+        };
+        s.restart();
+
+        s
+    }
+
+    /// Change the look of the pipes and start over.
+    pub fn set_style(
+        &mut self,
+        pieces: Vec<String>,
+        pool: ColorPool,
+        gradient_step: Option<f32>,
+        bg: ColorAttribute,
+    ) {
+        self.pieces = pieces;
+        self.pool = pool;
+        self.gradient_step = gradient_step;
+        self.bg = bg;
+        self.restart();
+    }
+
+    /// Change the pieces and start over.
+    pub fn set_pieces(&mut self, pieces: Vec<String>) {
+        self.pieces = pieces;
+        self.restart();
+    }
+
+    /// Clear the preview.
+    pub fn restart(&mut self) {
+        self.canv.fill(self.bg);
+        self.pieces_remaining = 0;
+        self.drawn = 0;
+    }
+
+    /// Draw the next piece.
+    pub fn step(&mut self) {
+        let mut rng = thread_rng();
+        let size = self.canv.size();
+
+        if size.0 == 0 || size.1 == 0 {
+            return;
+        }
+
+        if self.drawn >= MAX_PIECES {
+            self.restart();
+        }
+
+        let piece = &mut self.piece;
+
+        if self.pieces_remaining == 0 {
+            self.pieces_remaining = rng.gen_range(PIPE_LENGTH.0..=PIPE_LENGTH.1);
+
+            *piece = PipePiece::gen(&mut self.pool, &mut rng);
+            piece.pos = Point {
+                x: rng.gen_range(0..size.0) as isize,
+                y: rng.gen_range(0..size.1) as isize,
+            };
+        }
+
+        piece.pos.advance(piece.dir);
+        piece.pos.wrap(size.0 as isize, size.1 as isize);
+        piece.prev_dir = piece.dir;
+
+        if rng.gen_bool(TURNING_PROB) {
+            let [a, b] = piece.dir.perpendicular();
+
+            piece.dir = if rng.gen() { a } else { b };
+        }
+
+        if let Some(step) = self.gradient_step {
+            piece.advance_gradient(step);
+        }
+
+        self.canv.move_to(piece.pos);
+        self.canv
+            .set_fg_color(piece.color.unwrap_or(ColorAttribute::Default));
+        self.canv
+            .put_str(&self.pieces[piece_idx(piece.prev_dir, piece.dir)]);
+
+        self.pieces_remaining -= 1;
+        self.drawn += 1;
+    }
+
+    /// Retrieve a reference to the preview layer.
+    pub fn canvas(&self) -> &Canvas {
+        &self.canv
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{
+    io::{self, Read},
+    process::{Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Standard output of a command run with a timeout.
+#[derive(Debug)]
+pub struct TimedOutput {
+    /// What the command has printed.
+    pub stdout: Vec<u8>,
+    /// Whether the command has been killed for running too long, so the output may be cut off.
+    pub timed_out: bool,
+}
+
+/// Run the command with the standard input and error closed and retrieve its output. The command
+/// is killed if it runs for longer than the timeout.
+pub fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> io::Result<TimedOutput> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let Some(mut stdout) = child.stdout.take() else {
+        return Err(io::Error::other("cannot read the output of the command"));
+    };
+
+    // The output is read on its own thread, so a command which never exits doesn't hold up the
+    // timeout
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+
+        while let Ok(n @ 1..) = stdout.read(&mut buf) {
+            if tx.send(buf[..n].to_vec()).is_err() {
+                return;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + timeout;
+    let mut out = TimedOutput {
+        stdout: vec![],
+        timed_out: false,
+    };
+
+    loop {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(chunk) => out.stdout.extend(chunk),
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = child.kill();
+                out.timed_out = true;
+
+                break;
+            }
+        }
+    }
+
+    let _ = child.wait();
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output() {
+        let out = output_with_timeout(
+            Command::new("sh").args(["-c", "echo a"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(out.stdout, b"a\n");
+        assert!(!out.timed_out);
+    }
+
+    #[test]
+    fn timeout_keeps_output_so_far() {
+        let start = Instant::now();
+        let out = output_with_timeout(
+            Command::new("sh").args(["-c", "echo a; exec sleep 10"]),
+            Duration::from_millis(200),
+        )
+        .unwrap();
+
+        assert_eq!(out.stdout, b"a\n");
+        assert!(out.timed_out);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
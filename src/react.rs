@@ -0,0 +1,165 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use clap::ValueEnum;
+use eyre::{eyre, Result};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// How often the metric is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Network throughput (bytes per second) which is considered full load.
+const NET_FULL_LOAD: f64 = 100.0 * 1024.0 * 1024.0;
+
+/// System metric the animation reacts to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, ValueEnum)]
+pub enum Metric {
+    /// CPU utilization.
+    Cpu,
+    /// Network throughput.
+    Net,
+    /// Disk utilization.
+    Disk,
+}
+
+/// Periodically samples a system metric and converts it to a load between 0.0 and 1.0.
+///
+/// The metrics are read from `/proc`, so only Linux is supported.
+#[derive(Debug)]
+pub struct LoadMeter {
+    /// Sampled metric.
+    metric: Metric,
+    /// Raw counters of the last sample.
+    prev: (u64, u64),
+    /// Time of the last sample.
+    sampled_at: Instant,
+    /// Current load.
+    load: f32,
+}
+
+impl LoadMeter {
+    /// Create a `LoadMeter` for the metric. Fails if the metric isn't available on this system.
+    pub fn new(metric: Metric) -> Result<Self> {
+        let prev = read_counters(metric).ok_or_else(|| {
+            eyre!("cannot read system metrics from /proc (only Linux is supported)")
+        })?;
+
+        Ok(Self {
+            metric,
+            prev,
+            sampled_at: Instant::now(),
+            load: 0.0,
+        })
+    }
+
+    /// Sample the metric if enough time has passed since the last sample.
+    pub fn update(&mut self) {
+        let elapsed = self.sampled_at.elapsed();
+
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let Some(cur) = read_counters(self.metric) else {
+            return;
+        };
+
+        let delta = (
+            cur.0.saturating_sub(self.prev.0),
+            cur.1.saturating_sub(self.prev.1),
+        );
+
+        let load = match self.metric {
+            // Busy and total jiffies
+            Metric::Cpu if delta.1 > 0 => delta.0 as f64 / delta.1 as f64,
+            Metric::Cpu => 0.0,
+            // Transferred bytes, on a logarithmic scale
+            Metric::Net => {
+                let rate = delta.0 as f64 / elapsed.as_secs_f64();
+
+                (1.0 + rate).ln() / (1.0 + NET_FULL_LOAD).ln()
+            }
+            // Milliseconds spent doing I/O by all disks
+            Metric::Disk => delta.0 as f64 / elapsed.as_millis().max(1) as f64,
+        };
+
+        self.load = load.clamp(0.0, 1.0) as f32;
+        self.prev = cur;
+        self.sampled_at = Instant::now();
+    }
+
+    /// Retrieve the current load (0.0 - 1.0).
+    pub fn load(&self) -> f32 {
+        self.load
+    }
+}
+
+/// Read the raw counters of the metric.
+fn read_counters(metric: Metric) -> Option<(u64, u64)> {
+    match metric {
+        Metric::Cpu => {
+            let stat = fs::read_to_string("/proc/stat").ok()?;
+            let jiffies: Vec<u64> = stat
+                .lines()
+                .next()?
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|f| f.parse().ok())
+                .collect();
+
+            // idle and iowait
+            let idle = jiffies.get(3)? + jiffies.get(4).unwrap_or(&0);
+            let total: u64 = jiffies.iter().sum();
+
+            Some((total - idle, total))
+        }
+        Metric::Net => {
+            let dev = fs::read_to_string("/proc/net/dev").ok()?;
+            let bytes = dev
+                .lines()
+                .skip(2)
+                .filter_map(|l| l.split_once(':'))
+                .filter(|(iface, _)| iface.trim() != "lo")
+                .map(|(_, stats)| {
+                    let fields: Vec<u64> = stats
+                        .split_whitespace()
+                        .filter_map(|f| f.parse().ok())
+                        .collect();
+
+                    // Received and transmitted bytes
+                    fields.first().unwrap_or(&0) + fields.get(8).unwrap_or(&0)
+                })
+                .sum();
+
+            Some((bytes, 0))
+        }
+        Metric::Disk => {
+            let stats = fs::read_to_string("/proc/diskstats").ok()?;
+            let ms = stats
+                .lines()
+                .filter_map(|l| {
+                    let fields: Vec<&str> = l.split_whitespace().collect();
+                    let name = fields.get(2)?;
+
+                    // Skip partitions, they are already accounted in their disks
+                    if name.starts_with("loop")
+                        || name.starts_with("ram")
+                        || !Path::new("/sys/block").join(name).exists()
+                    {
+                        return None;
+                    }
+
+                    // Milliseconds spent doing I/O
+                    fields.get(12)?.parse::<u64>().ok()
+                })
+                .sum();
+
+            Some((ms, 0))
+        }
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas,
+    config::Config,
+    events::{Event, EventSender},
+    plane_2d::Point,
+};
+use eyre::{Result, WrapErr};
+use hex_color::HexColor;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use termwiz::{
+    cell::unicode_column_width,
+    color::{ColorAttribute, SrgbaTuple},
+};
+
+/// For how many frames the screen flashes.
+const FLASH_FRAMES: u32 = 12;
+
+/// How long a text message stays on the screen.
+const MESSAGE_DURATION: Duration = Duration::from_secs(10);
+
+/// How long to wait for a slow HTTP client.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum length of an HTTP request body.
+const MAX_BODY_LEN: usize = 4096;
+
+/// Command sent to the running screensaver from outside.
+#[derive(Clone, Debug)]
+pub enum RemoteCommand {
+    /// Flash the screen, with white unless a color is specified.
+    Flash(Option<SrgbaTuple>),
+    /// Clear the screen.
+    Clear,
+    /// Show a text message.
+    Text(String),
+}
+
+impl RemoteCommand {
+    /// Parse a command: `flash [#rrggbb]`, `clear` or `text <message>`.
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (name, arg) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let arg = arg.trim();
+
+        match name {
+            "flash" => Some(Self::Flash(HexColor::parse_rgb(arg).ok().map(|hc| {
+                SrgbaTuple(
+                    hc.r as f32 / 255.0,
+                    hc.g as f32 / 255.0,
+                    hc.b as f32 / 255.0,
+                    1.0,
+                )
+            }))),
+            "clear" => Some(Self::Clear),
+            "text" if !arg.is_empty() => Some(Self::Text(arg.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// Receives commands over HTTP or MQTT and shows flashes and messages on top of the pipes.
+pub struct Remote {
+    /// Layer with the flash.
+    flash: Canvas,
+    /// Color of the flash.
+    flash_color: ColorAttribute,
+    /// Remaining frames of the flash.
+    flash_frames_left: u32,
+    /// Ignore flash commands (see --reduced-motion).
+    no_flash: bool,
+    /// Layer with the message and when it disappears.
+    message: Option<(Canvas, Instant)>,
+    /// Subscriber receiving the MQTT messages (see --mqtt-topic).
+    mqtt_sub: Option<Child>,
+}
+
+impl Remote {
+    /// Start listening for commands as configured by `--listen` and `--mqtt-topic`; they are
+    /// delivered to the main loop as events. Returns `None` if neither is specified.
+    pub fn new(cfg: &Config, tx: EventSender) -> Result<Option<Self>> {
+        if cfg.listen.is_none() && cfg.mqtt_topic.is_none() {
+            return Ok(None);
+        }
+
+        if let Some(addr) = &cfg.listen {
+            let listener =
+                TcpListener::bind(addr).wrap_err_with(|| format!("cannot listen on {addr}"))?;
+            let tx = tx.clone();
+
+            thread::spawn(move || serve_http(listener, tx));
+        }
+
+        let mut mqtt_sub = None;
+
+        if let Some(topic) = &cfg.mqtt_topic {
+            let mut child = Command::new("mosquitto_sub")
+                .args(["-h", &cfg.mqtt_host, "-t", topic])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .wrap_err("failed to run mosquitto_sub (is it installed?)")?;
+
+            if let Some(stdout) = child.stdout.take() {
+                thread::spawn(move || {
+                    for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+                        if let Some(cmd) = RemoteCommand::parse(&line) {
+                            if !tx.send(Event::Remote(cmd)) {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+
+            mqtt_sub = Some(child);
+        }
+
+        Ok(Some(Self {
+            flash: Canvas::new(Point { x: 0, y: 0 }, (0, 0)),
+            flash_color: ColorAttribute::PaletteIndex(15),
+            flash_frames_left: 0,
+            no_flash: cfg.reduced_motion,
+            message: None,
+            mqtt_sub,
+        }))
+    }
+
+    /// Process a received command. Returns `true` if the screen should be cleared.
+    pub fn handle(&mut self, cmd: RemoteCommand, scr_size: (usize, usize)) -> bool {
+        match cmd {
+            RemoteCommand::Flash(_) if self.no_flash => {}
+            RemoteCommand::Flash(color) => {
+                self.flash_color = color.map_or(
+                    ColorAttribute::PaletteIndex(15),
+                    ColorAttribute::TrueColorWithDefaultFallback,
+                );
+                self.flash_frames_left = FLASH_FRAMES;
+            }
+            RemoteCommand::Clear => return true,
+            RemoteCommand::Text(text) => {
+                self.message = Some((message_canvas(&text, scr_size), Instant::now()));
+            }
+        }
+
+        false
+    }
+
+    /// Advance the flash and hide the message when its time is up.
+    pub fn update(&mut self, scr_size: (usize, usize)) {
+        self.flash_frames_left = self.flash_frames_left.saturating_sub(1);
+
+        if self.flash_frames_left > 0 {
+            if self.flash.size() != scr_size {
+                self.flash.resize(scr_size);
+            }
+
+            self.flash.fill(self.flash_color);
+        }
+
+        if self
+            .message
+            .as_ref()
+            .is_some_and(|(_, shown)| shown.elapsed() >= MESSAGE_DURATION)
+        {
+            self.message = None;
+        }
+    }
+
+    /// Retrieve the layers which must be drawn on top of the pipes.
+    pub fn layers(&self) -> impl Iterator<Item = &Canvas> {
+        // Alternate the flash every two frames
+        let flash = (self.flash_frames_left & 2 != 0).then_some(&self.flash);
+
+        flash
+            .into_iter()
+            .chain(self.message.as_ref().map(|(c, _)| c))
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.mqtt_sub {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Create a layer with the message in a box centered on the screen.
+fn message_canvas(text: &str, scr_size: (usize, usize)) -> Canvas {
+    let width = unicode_column_width(text, None) + 4;
+    let pos = Point {
+        x: scr_size.0.saturating_sub(width) as isize / 2,
+        y: scr_size.1.saturating_sub(3) as isize / 2,
+    };
+
+    let mut canv = Canvas::new(pos, (width, 3));
+    canv.fill(ColorAttribute::PaletteIndex(0));
+    canv.set_fg_color(ColorAttribute::PaletteIndex(15));
+    canv.set_bg_color(ColorAttribute::PaletteIndex(0));
+
+    let border = "─".repeat(width - 2);
+    let rows = [
+        format!("┌{border}┐"),
+        format!("│ {text} │"),
+        format!("└{border}┘"),
+    ];
+
+    for (i, row) in rows.iter().enumerate() {
+        canv.move_to(Point {
+            x: 0,
+            y: i as isize,
+        });
+        canv.put_str(row);
+    }
+
+    canv
+}
+
+/// Accept commands as HTTP requests: the path is the command and the body is its argument, e.g.
+/// `POST /text` with body `Build failed`.
+fn serve_http(listener: TcpListener, tx: EventSender) {
+    for stream in listener.incoming().map_while(|s| s.ok()) {
+        let _ = stream.set_read_timeout(Some(HTTP_TIMEOUT));
+
+        if let Some(cmd) = read_http_command(&stream) {
+            if !tx.send(Event::Remote(cmd)) {
+                break;
+            }
+
+            let _ = (&stream).write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        } else {
+            let _ = (&stream).write_all(
+                b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            );
+        }
+    }
+}
+
+/// Read a single HTTP request and convert it to a command.
+fn read_http_command(stream: &TcpStream) -> Option<RemoteCommand> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).ok()?;
+
+    let path = line
+        .split_whitespace()
+        .nth(1)?
+        .trim_start_matches('/')
+        .to_string();
+    let mut content_length = 0;
+
+    loop {
+        line.clear();
+
+        // The connection closed before the end of the headers
+        reader.read_line(&mut line).ok().filter(|&n| n > 0)?;
+
+        let header = line.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_LEN {
+        return None;
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    RemoteCommand::parse(&format!("{path} {}", String::from_utf8_lossy(&body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+
+    /// Send the raw HTTP request over a local connection and read it as a command.
+    fn request(raw: &str) -> Option<RemoteCommand> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        client.write_all(raw.as_bytes()).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+
+        read_http_command(&stream)
+    }
+
+    #[test]
+    fn parse() {
+        assert!(matches!(
+            RemoteCommand::parse("clear"),
+            Some(RemoteCommand::Clear)
+        ));
+        assert!(matches!(
+            RemoteCommand::parse("flash"),
+            Some(RemoteCommand::Flash(None))
+        ));
+        assert!(matches!(
+            RemoteCommand::parse(" flash  #ff0000 "),
+            Some(RemoteCommand::Flash(Some(SrgbaTuple(1.0, 0.0, 0.0, 1.0))))
+        ));
+        assert!(matches!(
+            RemoteCommand::parse("text  Build   failed "),
+            Some(RemoteCommand::Text(t)) if t == "Build   failed"
+        ));
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for s in ["", "  ", "text", "text   ", "jump", "CLEAR", "cleared"] {
+            assert!(RemoteCommand::parse(s).is_none(), "{s:?}");
+        }
+
+        // A color which can't be read is ignored
+        assert!(matches!(
+            RemoteCommand::parse("flash red"),
+            Some(RemoteCommand::Flash(None))
+        ));
+    }
+
+    #[test]
+    fn http_command() {
+        assert!(matches!(
+            request("POST /clear HTTP/1.1\r\nHost: x\r\n\r\n"),
+            Some(RemoteCommand::Clear)
+        ));
+        assert!(matches!(
+            request("POST /text HTTP/1.1\r\ncontent-length: 5\r\n\r\nhello"),
+            Some(RemoteCommand::Text(t)) if t == "hello"
+        ));
+    }
+
+    #[test]
+    fn http_malformed() {
+        for raw in [
+            "",
+            "POST\r\n\r\n",
+            "POST /jump HTTP/1.1\r\n\r\n",
+            "POST /text HTTP/1.1\r\n\r\n",
+            // Headers or body cut off
+            "POST /clear HTTP/1.1\r\n",
+            "POST /text HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello",
+            "POST /text HTTP/1.1\r\nContent-Length: x\r\n\r\nhello",
+            "POST /text HTTP/1.1\r\nContent-Length: 5000\r\n\r\nhello",
+        ] {
+            assert!(request(raw).is_none(), "{raw:?}");
+        }
+    }
+}
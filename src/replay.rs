@@ -0,0 +1,128 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{canvas::Canvas, plane_2d::Point, postfx::Cells};
+use std::collections::VecDeque;
+use termwiz::cell::Cell;
+
+/// Cell which has changed between two frames: its position and contents before and after.
+type CellChange = (Point, Cell, Cell);
+
+/// Direction of the playback of the recorded frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Direction {
+    Backward,
+    Forward,
+}
+
+/// Ring buffer of the last frames of a canvas, recorded as the cells which have changed between
+/// them. The frames can be played back in reverse ("rewind") and then forward again, up to the
+/// present (see --rewind-buffer).
+pub struct Replay {
+    /// Changes of the recorded frames, oldest first.
+    frames: VecDeque<Vec<CellChange>>,
+    /// Cells of the canvas in the last recorded frame.
+    last: Cells,
+    /// Maximal number of recorded frames.
+    capacity: usize,
+    /// Index of the next frame to play and the direction, if the frames are being played.
+    playback: Option<(usize, Direction)>,
+}
+
+impl Replay {
+    /// Create a `Replay` keeping the last frames, up to the capacity.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            last: vec![],
+            capacity,
+            playback: None,
+        }
+    }
+
+    /// Record the cells of the canvas which have changed since the last frame. The recorded
+    /// frames are dropped if the canvas has been resized.
+    pub fn record(&mut self, canv: &mut Canvas) {
+        let cells = canv.cells();
+
+        if cells.len() != self.last.len()
+            || cells.first().map(Vec::len) != self.last.first().map(Vec::len)
+        {
+            self.frames.clear();
+            self.last = cells;
+
+            return;
+        }
+
+        let mut changes = vec![];
+
+        for (y, (line, last_line)) in cells.iter().zip(&self.last).enumerate() {
+            for (x, (cell, last)) in line.iter().zip(last_line).enumerate() {
+                // The right half of a wide glyph is drawn with its left half
+                let wide_left = x > 0 && (line[x - 1].width() > 1 || last_line[x - 1].width() > 1);
+
+                if cell != last && !wide_left {
+                    let p = Point {
+                        x: x as isize,
+                        y: y as isize,
+                    };
+
+                    changes.push((p, last.clone(), cell.clone()));
+                }
+            }
+        }
+
+        self.last = cells;
+
+        // Frames with no changes aren't worth replaying
+        if changes.is_empty() {
+            return;
+        }
+
+        if self.frames.len() >= self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(changes);
+    }
+
+    /// Start playing the recorded frames in reverse, from the present.
+    pub fn rewind(&mut self) {
+        if !self.frames.is_empty() {
+            self.playback = Some((self.frames.len(), Direction::Backward));
+        }
+    }
+
+    /// Check whether the recorded frames are being played.
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Show the next frame of the playback on the canvas. Once the oldest frame is reached, the
+    /// frames are played forward again. The playback ends in the present.
+    pub fn play_step(&mut self, canv: &mut Canvas) {
+        let Some((i, dir)) = self.playback else {
+            return;
+        };
+
+        self.playback = match dir {
+            Direction::Backward if i == 0 => Some((0, Direction::Forward)),
+            Direction::Backward => {
+                for (p, before, _) in self.frames[i - 1].iter().rev() {
+                    canv.put_cell(*p, before);
+                }
+
+                Some((i - 1, Direction::Backward))
+            }
+            Direction::Forward if i == self.frames.len() => None,
+            Direction::Forward => {
+                for (p, _, after) in &self.frames[i] {
+                    canv.put_cell(*p, after);
+                }
+
+                Some((i + 1, Direction::Forward))
+            }
+        };
+    }
+}
@@ -3,65 +3,160 @@
 // This file is licensed under the MIT License (see LICENSE.md).
 
 use crate::{
-    canvas::Canvas,
-    color::GradientDir,
-    config::Config,
-    pipe::PipePiece,
-    plane_2d::{Direction, Point},
+    bookmark::Bookmark,
+    bouncer::Bouncer,
+    canvas::{Canvas, TextStyle},
+    clock::{local_minutes, night_depth},
+    color::{blend, ensure_contrast, heat_color, luminance, scale_brightness, to_srgba, ColorPool},
+    config::{
+        BadgePosition, ClearAnimation, Config, Geometry, GlyphAttr, Mode, OnFull, StatsStyle,
+    },
+    curve::Curve,
+    events::{Event, EventQueue, SimEvent, TimedEvent},
+    flow::Flow,
+    git_activity,
+    grid::Grid,
+    idle,
+    input_entropy::InputEntropy,
+    log_tail,
+    maze::Maze,
+    piece_set::{glyph, meta, PieceSetMeta},
+    pipe::{PieceRecord, PipeRecord, PipeStats},
+    plane_2d::Point,
+    postfx::{Pass, PostProcessor},
+    react::LoadMeter,
+    replay::Replay,
+    simulation::{CellUpdate, CurrentPipe, Simulation, WALL_ID},
+    sound::SoundHooks,
+    sparks::Sparks,
+    stencil::Stencil,
+    sync::{self, SyncMessage, SyncServer},
     terminal::TerminalScreen,
+    theme::Theme,
+    timer::Countdown,
+    watchdog::Watchdog,
+    weather::{self, WeatherScene, WeatherSource},
+    wrap_cmd,
 };
+
+#[cfg(feature = "notifications")]
+use crate::milestones::Milestones;
+#[cfg(feature = "remote")]
+use crate::remote::Remote;
 use eyre::{Result, WrapErr};
 use hex_color::HexColor;
-use rand::{thread_rng, Rng};
-use std::time::Duration;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 use termwiz::{
+    caps::ColorLevel,
+    cell::{unicode_column_width, Cell},
     color::{ColorAttribute, SrgbaTuple},
     input::{InputEvent, KeyCode, KeyEvent, Modifiers},
     terminal::Terminal,
 };
 
-/// Map of default piece sets.
-const DEFAULT_PIECE_SETS: [[char; 6]; 7] = [
-    ['|', '-', '+', '+', '+', '+'],
-    ['·', '·', '·', '·', '·', '·'],
-    ['•', '•', '•', '•', '•', '•'],
-    ['│', '─', '┌', '┐', '└', '┘'],
-    ['│', '─', '╭', '╮', '╰', '╯'],
-    ['║', '═', '╔', '╗', '╚', '╝'],
-    ['┃', '━', '┏', '┓', '┗', '┛'], // default
-];
-
-/// Map from directions to indices for indexing default piece sets.
-///
-/// Index via `[DIRECTION OF THE PREVIOUS PIECE][CURRENT DIRECTION]`
-const PIECE_SETS_IDX_MAP: [[usize; 4]; 4] = [
-    // Up
-    [0, 0, 2, 3],
-    // Down
-    [0, 0, 4, 5],
-    // Right
-    [5, 3, 1, 1],
-    // Left
-    [4, 2, 1, 1],
-];
+/// For how many frames the screen flashes.
+const FLASH_FRAMES: u32 = 12;
+
+/// For how many frames the pipes fade out before the screen is cleared (see --reduced-motion).
+const FADE_FRAMES: u32 = 20;
+
+/// For how many frames the pipes retract before the screen is cleared (see --clear-animation).
+const RETRACT_FRAMES: usize = 48;
+
+/// How much the RGB colors are darkened in each frame of the fade (see --reduced-motion).
+const FADE_FACTOR: f32 = 0.85;
+
+/// Longest time to wait for input at once, so keys are handled promptly even at low FPS.
+const INPUT_POLL_SLICE: Duration = Duration::from_millis(20);
+
+/// How often the screen is redrawn for screen readers (see --screen-reader).
+const SCREEN_READER_RENDER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Maximal FPS with reduced motion.
+const REDUCED_MOTION_MAX_FPS: i64 = 10;
+
+/// How many steps can be undone when stepping backward.
+const HISTORY_LEN: usize = 1000;
+
+/// Per-frame probability of spawning a new pipe when the system is idle (see --react).
+const IDLE_SPAWN_PROB: f64 = 0.02;
+
+/// Maximal number of pipes requested by external sources waiting to be spawned. Requests beyond it
+/// are dropped, so a flood of them doesn't keep the pipes coming for hours.
+const MAX_QUEUED_SPAWNS: usize = 64;
+
+/// Brightness of the pipe colors when the system is idle (see --react).
+const IDLE_BRIGHTNESS: f32 = 0.4;
+
+/// How much longer than the frame delay the gap between frames must be to be taken for a system
+/// suspend.
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+
+/// Rendering may take at most this fraction of a frame before the FPS is lowered (see
+/// --adaptive-fps).
+const ADAPTIVE_FPS_RENDER_SHARE: f64 = 0.5;
+
+/// Weight of the latest render time in the smoothed one (see --adaptive-fps).
+const RENDER_TIME_SMOOTHING: f64 = 0.1;
+
+/// Number of simulation steps per frame in turbo mode.
+const TURBO_STEPS_PER_FRAME: u32 = 20;
+
+/// Columns and rows around the box with the output of --wrap-cmd left for the pipes.
+const WRAP_BAND: (usize, usize) = (8, 4);
+
+/// Text of the badge shown while paused.
+const PAUSE_BADGE: &str = " ⏸ paused ";
+
+/// Brightness of the first pipes (see --fade-in).
+const FADE_IN_MIN_BRIGHTNESS: f32 = 0.1;
+
+/// How much the background of the stats widget is lightened (or darkened on light backgrounds)
+/// compared with the screen background.
+const STATS_BAR_TINT: f32 = 0.12;
+
+/// How much the text of the stats widget is lightened (or darkened) compared with the screen
+/// background, before its contrast is ensured.
+const STATS_TEXT_TINT: f32 = 0.75;
+
+/// Minimal contrast ratio of the text of the stats widget with its background (WCAG AA).
+const STATS_CONTRAST: f32 = 4.5;
+
+/// How long each frame of the animated pieces is shown.
+const GLYPH_FRAME_INTERVAL: Duration = Duration::from_millis(200);
 
 /// State of the screensaver.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct State {
-    /// Current pipe piece to be drawn.
-    pipe_piece: PipePiece,
     /// Total of all drawn pieces.
     pieces_total: u64,
     /// Total of all drawn pieces in the current layer.
     layer_pieces_total: u64,
-    /// Number of currently drawn pieces.
-    currently_drawn_pieces: u64,
-    /// Number of pieces not drawn yet.
-    pieces_remaining: u64,
+    /// Total of all drawn pieces when the animation was last stopped on a full screen (see
+    /// --on-full stop). After resuming, as many pieces are drawn again before the next stop.
+    pieces_at_stop: u64,
     /// Total of all drawn pipes.
     pipes_total: u64,
     /// Total of all drawn layers since last screen clear.
     layers_drawn: u64,
+    /// Number of generations left until the crumbling pipes are cleared (life-decay mode).
+    decay_steps_left: u32,
+    /// Time of the last screen clear.
+    cleared_at: Instant,
+    /// Time when the current layer was started (depth mode).
+    layer_started_at: Instant,
+    /// Number of frames left until the flashing screen is cleared.
+    flash_frames_left: u32,
+    /// Number of frames left until the fading screen is cleared (see --reduced-motion).
+    fade_frames_left: u32,
+    /// Number of pieces erased in each frame while the pipes retract, 0 if they don't (see
+    /// --clear-animation).
+    retract_per_frame: usize,
     /// Indicates when to end the main loop.
     quit: bool,
     /// Indicates when to stop updating the state.
@@ -71,13 +166,17 @@ struct State {
 impl Default for State {
     fn default() -> Self {
         Self {
-            pipe_piece: PipePiece::new(),
             pieces_total: 0,
             layer_pieces_total: 0,
-            currently_drawn_pieces: 0,
-            pieces_remaining: 0,
+            pieces_at_stop: 0,
             pipes_total: 0,
             layers_drawn: 0,
+            decay_steps_left: 0,
+            cleared_at: Instant::now(),
+            layer_started_at: Instant::now(),
+            flash_frames_left: 0,
+            fade_frames_left: 0,
+            retract_per_frame: 0,
             quit: false,
             pause: false,
         }
@@ -91,14 +190,140 @@ impl State {
     }
 }
 
+/// What a simulation step has changed, so it can be undone.
+struct Undo {
+    /// State before the step.
+    state: State,
+    /// Pipe before the step.
+    pipe: CurrentPipe,
+    /// Cells overwritten by the step.
+    cells: Vec<SavedCell>,
+}
+
+/// Previous contents of a cell overwritten by a simulation step.
+struct SavedCell {
+    /// Position of the cell.
+    pos: Point,
+    /// Previous contents of the cell.
+    cell: Cell,
+    /// Previous occupancy of the cell.
+    occupancy: Option<u64>,
+    /// Previous depth of the cell.
+    depth: Option<f32>,
+}
+
 /// Represents the screensaver application.
 pub struct Screensaver {
     state: State,
-    term_scr: TerminalScreen,
+    /// Terminal to draw on (none when running headless, see `rxpipes frame`).
+    term_scr: Option<TerminalScreen>,
     canv: Canvas,
+    /// Core of the simulation.
+    sim: Simulation,
+    /// Maze being carved (maze mode only).
+    maze: Option<Maze>,
+    /// Curve being traced (curve mode only).
+    curve: Option<Curve>,
     darken_min: SrgbaTuple,
     bg_color: Option<SrgbaTuple>,
+    /// Background colors to cycle through on each clear (see --bg-cycle).
+    bg_cycle: Vec<SrgbaTuple>,
+    /// Index of the current background color in `bg_cycle`.
+    bg_cycle_pos: usize,
     stats_canv: Canvas,
+    /// Heatmap of the visits of the cells, shown instead of the pipes when toggled.
+    heatmap: Option<Canvas>,
+    /// Index of the animated piece drawn in each cell (see `PieceSetMeta::frames`).
+    animated: Grid<Option<usize>>,
+    /// Number of the current frame of the animated pieces and when it was shown.
+    glyph_frame: (usize, Instant),
+    /// Pulses flowing along the pipes (see --flow-animation).
+    flow: Option<Flow>,
+    /// Sparks at the collisions of the pipes (see --end-on-collision).
+    sparks: Option<Sparks>,
+    /// Command run on the chosen events (see --sound-cmd).
+    sound: Option<SoundHooks>,
+    /// Weather shown over the pipes (weather mode only).
+    weather: Option<WeatherScene>,
+    /// Color keys of the pipes requested by external sources, waiting to be spawned (none unless
+    /// the pipes are spawned only on their requests, e.g. in git-activity mode or with
+    /// --spawn-on-line).
+    spawn_queue: Option<VecDeque<u64>>,
+    /// Piped input driving the simulation instead of the seed (see --stdin-mode).
+    input: Option<InputEntropy>,
+    /// Badge shown on top of the pipes while paused.
+    pause_badge: Option<Canvas>,
+    /// Box with the output of --wrap-cmd.
+    wrap_box: Option<Canvas>,
+    /// Lines of the last output of --wrap-cmd.
+    wrap_output: Option<Vec<String>>,
+    /// Seed of the random number generator.
+    seed: u64,
+    /// Random number generator driving the simulation.
+    rng: StdRng,
+    /// Random number generator deciding when the spawns are held back (see --react). It's apart
+    /// from `rng`, so holding the spawns doesn't change the pipes themselves.
+    spawn_rng: StdRng,
+    /// Broadcaster of the simulation steps (see --sync-server).
+    sync_server: Option<SyncServer>,
+    /// Whether the simulation steps are received from the sync server (see --sync-connect).
+    following_sync: bool,
+    /// Sampled system load (see --react).
+    load_meter: Option<LoadMeter>,
+    bouncer: Option<Bouncer>,
+    /// Receiver of remote commands (see --listen and --mqtt-topic).
+    #[cfg(feature = "remote")]
+    remote: Option<Remote>,
+    /// Notifier of the milestones of the run (see --notify-milestones).
+    #[cfg(feature = "notifications")]
+    milestones: Option<Milestones>,
+    countdown: Option<Countdown>,
+    /// Pipes drawn since the last clear or new layer.
+    pipe_records: Vec<PipeRecord>,
+    /// Statistics of the finished pipes.
+    pipe_stats: PipeStats,
+    /// Time when the first piece of the current pipe was drawn.
+    pipe_started_at: Instant,
+    /// Time when the animation has started (none when running headless).
+    started_at: Option<Instant>,
+    /// Number of frames animated since the start.
+    frames: u64,
+    /// Time of the last frame on the monotonic and the wall clock, used to detect system suspends.
+    frame_at: (Instant, SystemTime),
+    /// Whether the simulation runs at full speed without rendering.
+    frozen: bool,
+    /// Whether the simulation is fast-forwarded by several steps per frame.
+    turbo: bool,
+    /// Recent simulation steps which can be undone.
+    history: VecDeque<Undo>,
+    /// Recent frames which can be replayed in reverse (see --rewind-buffer).
+    replay: Option<Replay>,
+    /// Whether the user is active in the tmux session, so the animation stays still (see
+    /// --idle-start).
+    user_active: bool,
+    /// Time of the last render.
+    rendered_at: Instant,
+    /// Lines shown by the stats widget.
+    stats_lines: Vec<String>,
+    /// Colors of the stats widget (foreground and background).
+    stats_colors: (ColorAttribute, ColorAttribute),
+    /// Time when the stats widget was last refreshed (see --stats-interval).
+    stats_drawn_at: Instant,
+    /// Smoothed time taken by rendering a frame (see --adaptive-fps).
+    render_time: Duration,
+    /// Number of layers drawn on top of the pipes in the last render.
+    overlays_drawn: usize,
+    /// Major event to be announced in the window title (see --screen-reader).
+    announcement: Option<String>,
+    /// Events delivered by the background sources (sync server and remote commands).
+    events: EventQueue,
+    /// Worker applying the effects to the pipes (none when running headless, so the frames stay
+    /// reproducible).
+    postfx: Option<PostProcessor>,
+    /// Throttles the main loop if it's running busy (none when running headless).
+    watchdog: Option<Watchdog>,
+    /// Failures of the actions of the keys (e.g. saving a bookmark), reported after exit.
+    warnings: Vec<String>,
     delay: Duration,
     cfg: Config,
 }
@@ -108,10 +333,130 @@ impl Screensaver {
     pub fn new(term_scr: TerminalScreen, cfg: Config) -> Result<Self> {
         let scr_size = term_scr.size();
 
+        Self::with_screen(Some(term_scr), scr_size, cfg)
+    }
+
+    /// Create a `Screensaver` which only simulates the pipes on a screen of specified size,
+    /// without a terminal.
+    pub fn headless(scr_size: (usize, usize), cfg: Config) -> Result<Self> {
+        Self::with_screen(None, scr_size, cfg)
+    }
+
+    /// Create a `Screensaver` drawing on the terminal screen, if there is any.
+    fn with_screen(
+        mut term_scr: Option<TerminalScreen>,
+        scr_size: (usize, usize),
+        mut cfg: Config,
+    ) -> Result<Self> {
+        if cfg.reduced_motion {
+            cfg.fps = cfg.fps.min(REDUCED_MOTION_MAX_FPS);
+            cfg.attr.retain(|a| *a != GlyphAttr::Blink);
+        }
+
+        let seed = cfg.seed.unwrap_or_else(|| thread_rng().gen());
+        let events = EventQueue::new(term_scr.as_mut().map(|t| t.waker()));
+
+        if let Some(addr) = &cfg.sync_connect {
+            sync::follow(addr, events.sender())?;
+        }
+
+        let postfx = term_scr
+            .as_ref()
+            .map(|_| PostProcessor::new())
+            .transpose()?;
+        let watchdog = term_scr.as_ref().and_then(|_| Watchdog::new());
+        let flow = term_scr
+            .as_ref()
+            .filter(|_| cfg.flow_animation && !cfg.reduced_motion)
+            .map(|_| Flow::new());
+        let sparks = term_scr
+            .as_ref()
+            .filter(|_| cfg.end_on_collision && !cfg.reduced_motion)
+            .map(|_| Sparks::default());
+        let sound = term_scr
+            .as_ref()
+            .and(cfg.sound_cmd.clone())
+            .map(|cmd| SoundHooks::new(cmd, cfg.sound_events.clone()));
+        let weather_source = match (&cfg.weather_cmd, &cfg.weather_file) {
+            (Some(cmd), _) => Some(WeatherSource::Command(cmd.clone())),
+            (_, Some(path)) => Some(WeatherSource::File(path.clone())),
+            _ => None,
+        };
+        let weather = weather_source
+            .filter(|_| term_scr.is_some() && cfg.mode == Mode::Weather)
+            .map(|source| {
+                weather::watch(source, cfg.weather_interval, events.sender());
+
+                WeatherScene::new(!cfg.reduced_motion, side_rng(seed, SideRng::Weather))
+            });
+        // Pipes are spawned only on the requests of the external sources, if there are any
+        let mut external_spawns = false;
+
+        if let Some(repo) = cfg
+            .repo
+            .as_ref()
+            .filter(|_| term_scr.is_some() && cfg.mode == Mode::GitActivity)
+        {
+            git_activity::watch(repo, events.sender())?;
+            external_spawns = true;
+        }
+
+        if let Some(path) = cfg.spawn_on_line.as_ref().filter(|_| term_scr.is_some()) {
+            log_tail::watch(path, events.sender())?;
+            external_spawns = true;
+        }
+
+        let spawn_queue = external_spawns.then(VecDeque::new);
+
+        if let Some(cmd) = cfg.wrap_cmd.clone().filter(|_| term_scr.is_some()) {
+            wrap_cmd::watch(cmd, cfg.wrap_interval, events.sender());
+        }
+
+        // Until tmux has been asked, the user is considered active
+        let user_active = term_scr.is_some()
+            && cfg
+                .idle_start
+                .is_some_and(|t| idle::watch(t, events.sender()));
+
+        let input = term_scr
+            .as_ref()
+            .filter(|_| cfg.stdin_mode)
+            .map(|_| InputEntropy::new());
+        let replay = term_scr
+            .as_ref()
+            .filter(|_| !cfg.rewind_buffer.is_zero())
+            .map(|_| Replay::new((cfg.rewind_buffer.as_secs() * cfg.fps as u64) as usize));
+
         let mut s = Ok(Self {
             state: State::new(),
             term_scr,
             canv: Canvas::new(Point { x: 0, y: 0 }, scr_size),
+            sim: Simulation::new(
+                scr_size,
+                ColorPool {
+                    palette: cfg.palette,
+                    custom: cfg.colors.clone(),
+                    walk: cfg.color_walk,
+                    luma: cfg.luma_range(),
+                    distinct: cfg.distinct_colors,
+                    ..Default::default()
+                },
+                if matches!(
+                    cfg.mode,
+                    Mode::Pipes | Mode::LifeDecay | Mode::Weather | Mode::GitActivity
+                ) {
+                    meta(&cfg)
+                } else {
+                    PieceSetMeta::default()
+                },
+                if let Some(path) = &cfg.trace_image {
+                    Some(Stencil::from_image(path)?)
+                } else {
+                    cfg.trace_text.as_deref().map(Stencil::from_text)
+                },
+            ),
+            maze: None,
+            curve: None,
             darken_min: {
                 let hc = HexColor::parse_rgb(&cfg.darken_min)?;
 
@@ -136,342 +481,2270 @@ impl Screensaver {
                     None
                 }
             },
+            bg_cycle: cfg
+                .bg_cycle
+                .iter()
+                .map(|c| {
+                    let hc = HexColor::parse_rgb(c)?;
+
+                    Ok(SrgbaTuple(
+                        hc.r as f32 / 255.0,
+                        hc.g as f32 / 255.0,
+                        hc.b as f32 / 255.0,
+                        1.0,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            bg_cycle_pos: 0,
+            heatmap: None,
+            animated: Grid::new(scr_size),
+            glyph_frame: (0, Instant::now()),
+            flow,
+            sparks,
+            sound,
+            weather,
+            spawn_queue,
+            input,
+            pause_badge: None,
+            wrap_box: None,
+            wrap_output: None,
             stats_canv: Canvas::new(
                 Point {
                     x: 0,
                     y: scr_size.1 as isize - 1,
                 },
-                (scr_size.0, 3),
+                (scr_size.0, 1),
             ),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            spawn_rng: side_rng(seed, SideRng::Spawn),
+            sync_server: cfg
+                .sync_server
+                .as_deref()
+                .map(SyncServer::bind)
+                .transpose()?,
+            following_sync: cfg.sync_connect.is_some(),
+            load_meter: cfg.react.map(LoadMeter::new).transpose()?,
+            bouncer: None,
+            #[cfg(feature = "remote")]
+            remote: Remote::new(&cfg, events.sender())?,
+            #[cfg(feature = "notifications")]
+            milestones: cfg.notify_milestones.then(Milestones::new),
+            countdown: None,
+            pipe_records: vec![],
+            pipe_stats: PipeStats::default(),
+            pipe_started_at: Instant::now(),
+            started_at: None,
+            frame_at: (Instant::now(), SystemTime::now()),
+            frames: 0,
+            frozen: false,
+            turbo: false,
+            history: VecDeque::new(),
+            replay,
+            user_active,
+            rendered_at: Instant::now(),
+            stats_lines: vec![],
+            stats_colors: (ColorAttribute::Default, ColorAttribute::Default),
+            stats_drawn_at: Instant::now(),
+            render_time: Duration::ZERO,
+            overlays_drawn: 0,
+            announcement: None,
+            events,
+            postfx,
+            watchdog,
+            warnings: vec![],
             delay: Screensaver::calculate_delay(cfg.fps),
             cfg,
         });
 
         if let Ok(ref mut s) = s {
+            s.set_bg_color(s.bg_cycle.first().copied().or(s.bg_color));
+
+            s.bouncer = s.cfg.bouncer.as_ref().map(|t| {
+                let rng = side_rng(s.seed, SideRng::Bouncer);
+
+                Bouncer::new(t, s.sim.color_pool.clone(), scr_size, rng)
+            });
+
+            s.countdown = s.cfg.timer.map(|d| Countdown::new(d, s.bg_attr()));
+
+            // Without a terminal (e.g. rendering a single frame), there is no main loop to wait for
+            // the output in the background
+            if s.term_scr.is_none() {
+                s.wrap_output = s.cfg.wrap_cmd.as_deref().map(wrap_cmd::run);
+                s.draw_wrap_box();
+            }
+
+            if s.cfg.show_stats {
+                s.draw_stats();
+            }
+
             s.draw_bg();
+
+            if let Some(t) = s.term_scr.as_mut().filter(|_| s.cfg.screen_reader) {
+                t.push_title()?;
+            }
         }
         s
     }
 
     /// Free all resources.
     pub fn deinit(&mut self) -> Result<()> {
-        self.term_scr.deinit()
+        let Some(term_scr) = &mut self.term_scr else {
+            return Ok(());
+        };
+
+        if self.cfg.screen_reader {
+            term_scr.pop_title()?;
+        }
+
+        term_scr.deinit()
     }
 
-    /// Generate the next pipe pieces.
-    fn gen_next_piece(&mut self) {
-        // Aliases with shorter names
-        let state = &mut self.state;
-        let canv = &mut self.canv;
-        let cfg = &self.cfg;
-        let piece = &mut state.pipe_piece;
+    /// Advance the simulation by the number of steps without rendering.
+    pub fn simulate(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.update();
+        }
+    }
+
+    /// Retrieve the pipes as text with ANSI escape sequences.
+    pub fn frame(&mut self) -> String {
+        self.canv.ansi_text()
+    }
 
-        let mut rng = thread_rng();
+    /// Retrieve the seed of the random number generator.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        if state.pieces_remaining == 0 {
-            state.pieces_remaining = rng.gen_range(cfg.min_pipe_length..=cfg.max_pipe_length);
+    /// Retrieve the layer with the pipes.
+    pub fn canvas_mut(&mut self) -> &mut Canvas {
+        &mut self.canv
+    }
 
-            *piece = PipePiece::gen(cfg.palette);
-            piece.pos = Point {
-                x: rng.gen_range(0..canv.size().0) as isize,
-                y: rng.gen_range(0..canv.size().1) as isize,
-            };
+    /// Carve the next piece of the maze.
+    fn gen_next_maze_piece(&mut self) {
+        let rng = &mut self.rng;
+        let size = self.sim.area();
+        let maze = self.maze.get_or_insert_with(|| Maze::new(size, rng));
 
-            if state.pieces_total > 0 {
-                state.pipes_total += 1;
-            }
+        let Some(next) = maze.next_piece(rng) else {
+            // The maze is complete, so start a new one
+            self.clear();
 
-            state.currently_drawn_pieces = 0;
+            return self.gen_next_maze_piece();
+        };
+
+        if next.new_branch {
+            self.finish_pipe();
+            self.sim.start_pipe(&self.cfg, &mut self.rng);
+            self.emit(SimEvent::PipeSpawned);
         }
 
-        piece.pos.advance(piece.dir);
-        piece
-            .pos
-            .wrap(canv.size().0 as isize, canv.size().1 as isize);
-        piece.prev_dir = piece.dir;
+        let piece = &mut self.sim.pipe.piece;
+        piece.pos = next.pos;
+        piece.prev_dir = next.prev_dir;
+        piece.dir = next.dir;
+    }
+
+    /// Trace the next piece of the curve. Returns `false` if no curve fits into the screen.
+    fn gen_next_curve_piece(&mut self) -> bool {
+        let size = self.sim.area();
+        let kind = self.cfg.curve;
 
-        // Try to turn the pipe in other direction
-        if rng.gen_bool(cfg.turning_prob) {
-            let choice = rng.gen_bool(0.5);
+        let (next, new_curve) = loop {
+            let rng = &mut self.rng;
+            let new_curve = self.curve.is_none();
+            let curve = self
+                .curve
+                .get_or_insert_with(|| Curve::new(kind, size, rng));
 
-            piece.dir = match piece.dir {
-                Direction::Up | Direction::Down => {
-                    if choice {
-                        Direction::Right
-                    } else {
-                        Direction::Left
-                    }
-                }
-                Direction::Right | Direction::Left => {
-                    if choice {
-                        Direction::Up
-                    } else {
-                        Direction::Down
-                    }
-                }
+            if let Some(next) = curve.next_piece() {
+                break (next, new_curve);
+            }
+
+            if new_curve {
+                return false;
             }
+
+            // The curve is complete, so start again on a clean screen
+            self.clear();
+        };
+
+        if new_curve {
+            self.finish_pipe();
+            self.sim.start_pipe(&self.cfg, &mut self.rng);
+            self.emit(SimEvent::PipeSpawned);
         }
-    }
 
-    /// Display the current state.
-    fn draw_pipe_piece(&mut self) {
-        // Aliases with shorter names
-        let state = &mut self.state;
-        let canv = &mut self.canv;
-        let cfg = &self.cfg;
-        let piece = &mut state.pipe_piece;
+        let piece = &mut self.sim.pipe.piece;
+        piece.pos = next.pos;
+        piece.prev_dir = next.prev_dir;
+        piece.dir = next.dir;
 
-        canv.move_to(piece.pos);
+        true
+    }
 
-        if let Some(color) = piece.color {
-            let color = if cfg.gradient {
-                let step = match piece.gradient {
-                    GradientDir::Up => cfg.gradient_step,
-                    GradientDir::Down => -cfg.gradient_step,
-                };
+    /// Count the current pipe in the statistics, if any of it has been drawn.
+    fn finish_pipe(&mut self) {
+        let pipe = &self.sim.pipe;
 
-                let srgba = if let ColorAttribute::TrueColorWithDefaultFallback(srgba) = color {
-                    let r = (srgba.0 + step).clamp(0.0, 1.0);
-                    let g = (srgba.1 + step).clamp(0.0, 1.0);
-                    let b = (srgba.2 + step).clamp(0.0, 1.0);
+        if pipe.pieces_drawn > 0 {
+            self.pipe_stats.add(
+                pipe.pieces_drawn,
+                pipe.turns,
+                self.pipe_started_at.elapsed(),
+            );
+        }
+    }
 
-                    SrgbaTuple(r, g, b, 1.0)
-                } else {
-                    unreachable!()
+    /// Draw the cells updated by a simulation step.
+    fn draw_updates(&mut self, updates: Vec<CellUpdate>) {
+        let pipe_id = self.sim.pipe.id;
+
+        for u in updates {
+            if let Some(prev_cell) = self.canv.cell(u.pos) {
+                let record = PieceRecord {
+                    pos: u.pos,
+                    prev_cell,
+                    prev_occupancy: u.prev_occupancy,
                 };
 
-                ColorAttribute::TrueColorWithDefaultFallback(srgba)
+                match self.pipe_records.last_mut() {
+                    Some(r) if r.id == pipe_id => r.pieces.push(record),
+                    _ => self.pipe_records.push(PipeRecord {
+                        id: pipe_id,
+                        pieces: vec![record],
+                    }),
+                }
+            }
+
+            let mut color = if let Some(m) = &self.load_meter {
+                scale_brightness(
+                    u.color,
+                    IDLE_BRIGHTNESS + (1.0 - IDLE_BRIGHTNESS) * m.load(),
+                )
             } else {
-                color
+                u.color
             };
 
-            piece.color = Some(color);
-            canv.set_fg_color(color)
-        }
+            if let Some(b) = self.fade_in_brightness() {
+                color = scale_brightness(color, b);
+            }
 
-        let piece_idx = PIECE_SETS_IDX_MAP[piece.prev_dir as usize][piece.dir as usize];
+            if let Some(w) = &self.weather {
+                color = w.tint(color);
+            }
 
-        if let Some(pieces) = &cfg.custom_piece_set {
-            canv.put_str(&pieces[piece_idx]);
-        } else {
-            canv.put_str(DEFAULT_PIECE_SETS[cfg.piece_set as usize][piece_idx].to_string());
+            self.canv.set_fg_color(color);
+            self.canv.set_style(u.style);
+            self.canv.move_to(u.pos);
+            self.canv.put_str(u.glyph);
+
+            if let Some(a) = self.animated.get_mut(u.pos) {
+                *a = u.piece.filter(|&i| {
+                    self.cfg
+                        .custom_piece_set_meta
+                        .as_ref()
+                        .is_some_and(|m| m.frames(i).is_some())
+                });
+            }
         }
 
-        state.pieces_total += 1;
-        state.layer_pieces_total += 1;
-        state.currently_drawn_pieces += 1;
-        state.pieces_remaining -= 1;
+        // The twin counts as a piece of its own (see --mirror-pair)
+        let pieces = if self.cfg.mirror_pair.is_some() { 2 } else { 1 };
+        let state = &mut self.state;
 
-        if cfg.max_drawn_pieces != 0 && state.pieces_total >= cfg.max_drawn_pieces {
-            self.clear();
-        } else if cfg.depth_mode && state.layer_pieces_total >= cfg.layer_max_drawn_pieces {
+        state.pieces_total += pieces;
+        state.layer_pieces_total += pieces;
+
+        // The budgets follow the size of the screen
+        let max = self.cfg.max_drawn_pieces.pieces(self.canv.size());
+
+        if max != 0 && state.pieces_total.saturating_sub(state.pieces_at_stop) >= max {
+            match self.cfg.on_full {
+                _ if self.cfg.mode == Mode::LifeDecay => {
+                    state.decay_steps_left = self.cfg.decay_steps;
+                }
+                OnFull::Clear => self.clear_gently(),
+                OnFull::Stop => {
+                    state.pause = true;
+                    state.pieces_at_stop = state.pieces_total;
+                }
+                OnFull::Darken => {
+                    state.pieces_total = 0;
+                    self.darken_previous_layers();
+                }
+            }
+        } else if self.cfg.depth_mode && self.is_layer_complete() {
             self.darken_previous_layers();
         }
     }
 
+    /// Check whether the current layer is complete in depth mode: it has enough pieces, has been
+    /// drawn for long enough or covers enough of the screen.
+    fn is_layer_complete(&self) -> bool {
+        let max = self.cfg.layer_max_drawn_pieces.pieces(self.canv.size());
+
+        (max != 0 && self.state.layer_pieces_total >= max)
+            || self
+                .cfg
+                .layer_interval
+                .is_some_and(|i| self.state.layer_started_at.elapsed() >= i)
+            || self
+                .cfg
+                .layer_coverage
+                .is_some_and(|c| self.sim.coverage() * 100.0 >= c as f64)
+    }
+
+    /// Brightness of the pipes drawn now, if they are still fading in (see --fade-in).
+    fn fade_in_brightness(&self) -> Option<f32> {
+        let t = self.started_at?.elapsed().as_secs_f32() / self.cfg.fade_in?.as_secs_f32();
+
+        (t < 1.0).then_some(FADE_IN_MIN_BRIGHTNESS + (1.0 - FADE_IN_MIN_BRIGHTNESS) * t)
+    }
+
     /// Clear the screen and reset all pipe/piece/layer counters.
     fn clear(&mut self) {
-        self.state.currently_drawn_pieces = 0;
-        self.state.pieces_remaining = 0;
-        self.state.layer_pieces_total = 0;
-        self.state.pieces_total = 0;
-        self.state.layers_drawn = 0;
-        self.state.pipes_total = 0;
+        self.emit(SimEvent::Cleared);
+        self.state.decay_steps_left = 0;
+        self.maze = None;
+        self.curve = None;
+        self.sim.clear();
+
+        if !self.bg_cycle.is_empty() {
+            self.bg_cycle_pos = (self.bg_cycle_pos + 1) % self.bg_cycle.len();
+            self.set_bg_color(Some(self.bg_cycle[self.bg_cycle_pos]));
+        }
+
+        if self.cfg.cycle_on_clear {
+            self.cycle_theme();
+        }
 
         self.draw_bg();
     }
 
-    /// Fill the screen with background color.
-    fn draw_bg(&mut self) {
-        if let Some(c) = self.bg_color {
-            self.canv
-                .fill(ColorAttribute::TrueColorWithDefaultFallback(c));
-        } else {
-            self.canv.fill(ColorAttribute::Default);
+    /// Clear the screen with the animation (see --clear-animation). The pipes fade out first with
+    /// reduced motion or when the themes are cycled (see --cycle-on-clear).
+    fn clear_gently(&mut self) {
+        if self.state.fade_frames_left > 0 || self.state.retract_per_frame > 0 {
+            return;
         }
-    }
 
-    /// Make all pipe pieces in previous layers darker.
-    fn darken_previous_layers(&mut self) {
-        self.state.currently_drawn_pieces = 0;
-        self.state.pieces_remaining = 0;
-        self.state.layer_pieces_total = 0;
-        self.state.layers_drawn += 1;
+        let fade = self.cfg.reduced_motion || self.cfg.cycle_on_clear;
 
-        self.canv.darken(self.cfg.darken_factor, self.darken_min);
+        match self.cfg.clear_animation {
+            // Retracting pipes are a lot of motion
+            ClearAnimation::Rewind if !self.cfg.reduced_motion => self.start_retracting(),
+            ClearAnimation::None if !fade => self.clear(),
+            _ => self.state.fade_frames_left = FADE_FRAMES,
+        }
     }
 
-    /// Render pipes and maybe stats.
-    fn render(&mut self) -> Result<()> {
-        self.term_scr.copy_canvas(&self.canv);
+    /// Let the pipes retract in the reverse drawing order before the screen is cleared.
+    fn start_retracting(&mut self) {
+        let pieces: usize = self.pipe_records.iter().map(|r| r.pieces.len()).sum();
 
-        if self.cfg.show_stats {
-            self.term_scr.copy_canvas(&self.stats_canv);
+        if pieces == 0 {
+            self.clear();
+        } else {
+            self.state.retract_per_frame = pieces.div_ceil(RETRACT_FRAMES);
         }
+    }
 
-        self.term_scr.render()?;
+    /// Let pipe pieces evolve by one generation of a cellular automaton in which cells can only die:
+    /// a piece survives only if it has 2 or 3 neighbours. When the last generation is reached, the
+    /// screen is cleared.
+    fn decay_step(&mut self) {
+        self.history.clear();
 
-        Ok(())
-    }
+        let (w, h) = self.canv.size();
+        let is_alive =
+            |p: Point| matches!(self.sim.occupancy.get(p), Some(Some(id)) if *id != WALL_ID);
+        let mut dying = vec![];
 
-    /// Run the main loop in the current thread until an external event is received (a key press or
-    /// signal) or some internal error is occurred.
-    pub fn run(&mut self) -> Result<()> {
-        while !self.state.quit {
-            self.handle_events(self.delay)?;
+        for y in 0..h as isize {
+            for x in 0..w as isize {
+                let p = Point { x, y };
 
-            if !self.state.pause {
-                self.gen_next_piece();
-                self.draw_pipe_piece();
+                if !is_alive(p) {
+                    continue;
+                }
 
-                if self.cfg.show_stats {
-                    self.draw_stats();
+                let neighbours = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|&(dx, dy)| {
+                        (dx, dy) != (0, 0)
+                            && is_alive(Point {
+                                x: p.x + dx,
+                                y: p.y + dy,
+                            })
+                    })
+                    .count();
+
+                if !(2..=3).contains(&neighbours) {
+                    dying.push(p);
                 }
+            }
+        }
 
-                self.render()?;
+        for p in dying {
+            if let Some(o) = self.sim.occupancy.get_mut(p) {
+                *o = None;
+            }
+
+            if let Some(d) = self.sim.depth_buf.get_mut(p) {
+                *d = None;
             }
+
+            self.canv.move_to(p);
+            self.canv.put_str(" ");
         }
 
-        Ok(())
+        self.state.decay_steps_left = self.state.decay_steps_left.saturating_sub(1);
+
+        if self.state.decay_steps_left == 0 {
+            self.clear();
+        }
     }
 
-    fn calculate_delay(fps: i64) -> Duration {
-        Duration::from_millis(1000 / fps as u64)
+    /// Fill the screen with background color.
+    fn draw_bg(&mut self) {
+        self.canv.fill(self.bg_attr());
+
+        if self.cfg.walled {
+            self.draw_walls();
+        }
     }
 
-    /// Handle input and incoming events.
-    fn handle_events(&mut self, delay: Duration) -> Result<()> {
-        // The poll_input function blocks the thread if the argument is nonzero, so we can use it
-        // for a frame rate limit. The only downside is that if the incoming events are
-        // received (e.g., a key press or window resize), this function immediately returns,
-        // so the delay isn't always the same. But since the user isn't expected to make
-        // thousands of key presses or crazily drag the corner of the window while using
-        // screensaver, we can ignore this.
-        if let Some(event) = self
-            .term_scr
-            .terminal()
-            .terminal()
-            .poll_input(Some(delay))
-            .wrap_err("cannot read incoming events")?
-        {
-            match event {
-                InputEvent::Key(KeyEvent {
-                    key,
-                    modifiers: Modifiers::NONE,
-                }) => match key {
-                    KeyCode::Escape | KeyCode::Char('q') | KeyCode::Char('Q') => {
-                        self.state.quit = true
-                    }
-                    KeyCode::Char(' ') => self.state.pause = !self.state.pause,
-                    KeyCode::Char('c') => self.clear(),
-                    KeyCode::Char('l') => self.redraw()?,
-                    KeyCode::Char('s') => self.cfg.show_stats = !self.cfg.show_stats,
-                    KeyCode::Char(',') => {
-                        self.cfg.fps -= 1;
-                        self.cfg.fps = self.cfg.fps.clamp(1, i64::MAX);
+    /// Draw the walls along the edges of the screen and mark them as obstacles.
+    fn draw_walls(&mut self) {
+        let (w, h) = self.sim.area();
 
-                        self.delay = Self::calculate_delay(self.cfg.fps)
-                    }
-                    KeyCode::Char('.') => {
-                        self.cfg.fps = self.cfg.fps.saturating_add(1);
+        if w < 3 || h < 3 {
+            return;
+        }
 
-                        self.delay = Self::calculate_delay(self.cfg.fps)
-                    }
-                    KeyCode::Char('<') => {
-                        self.cfg.fps -= 10;
-                        self.cfg.fps = self.cfg.fps.clamp(1, i64::MAX);
+        let (w, h) = (w as isize, h as isize);
 
-                        self.delay = Self::calculate_delay(self.cfg.fps)
-                    }
-                    KeyCode::Char('>') => {
-                        self.cfg.fps = self.cfg.fps.saturating_add(10);
+        self.canv.set_style(TextStyle::default());
+        self.canv.set_fg_color(ColorAttribute::PaletteIndex(8));
 
-                        self.delay = Self::calculate_delay(self.cfg.fps)
-                    }
-                    _ => {}
-                },
-                InputEvent::Key(KeyEvent {
-                    key: KeyCode::Char('c'),
-                    modifiers: Modifiers::CTRL,
-                }) => self.state.quit = true,
-                InputEvent::Resized { cols, rows } => {
-                    self.canv.resize((cols, rows));
-                    self.draw_bg();
+        for y in 0..h {
+            for x in 0..w {
+                // Indices of the pieces as written in `│─┌┐└┘`
+                let idx = match (x, y) {
+                    (0, 0) => 2,
+                    (x, 0) if x == w - 1 => 3,
+                    (0, y) if y == h - 1 => 4,
+                    (x, y) if x == w - 1 && y == h - 1 => 5,
+                    (_, y) if y == 0 || y == h - 1 => 1,
+                    (x, _) if x == 0 || x == w - 1 => 0,
+                    _ => continue,
+                };
+                let p = Point { x, y };
 
-                    // self.stats_canv.resize((cols, self.stats_canv.size().1));
-                    self.stats_canv.pos.y = rows as isize - 1;
-                    self.stats_canv.resize((cols, self.stats_canv.size().1));
-                    self.term_scr.resize((cols, rows));
+                self.canv.move_to(p);
+                self.canv.put_str(glyph(&self.cfg, idx));
 
-                    self.redraw()?
+                if let Some(o) = self.sim.occupancy.get_mut(p) {
+                    *o = Some(WALL_ID);
                 }
-                _ => {}
             }
         }
+    }
 
-        Ok(())
+    /// Change the background color and adapt the colors of the pipes to it. The screen isn't
+    /// redrawn.
+    fn set_bg_color(&mut self, c: Option<SrgbaTuple>) {
+        self.bg_color = c;
+        self.sim.color_pool.bg = c.or(self.cfg.term_bg_color);
+        // Adapt the colors to light backgrounds
+        self.sim.color_pool.dark = self.sim.color_pool.bg.is_some_and(|c| luminance(c) > 0.5);
     }
 
-    fn redraw(&mut self) -> Result<()> {
-        self.term_scr.clear();
-        self.render()?;
+    /// Retrieve the color to darken the previous layers to. On light backgrounds, it's white
+    /// unless set explicitly.
+    fn darken_min(&self) -> SrgbaTuple {
+        if self.sim.color_pool.dark && !self.cfg.is_explicit("darken_min") {
+            SrgbaTuple(1.0, 1.0, 1.0, 1.0)
+        } else {
+            self.darken_min
+        }
+    }
 
-        Ok(())
+    /// Retrieve the background color as a color attribute.
+    fn bg_attr(&self) -> ColorAttribute {
+        self.bg_color.map_or(
+            ColorAttribute::Default,
+            ColorAttribute::TrueColorWithDefaultFallback,
+        )
     }
 
-    /// Draw a stats widget which shows pipe/piece/layers counters and the current pipe color.
-    fn draw_stats(&mut self) {
-        // Stats string will have a black background
-        self.stats_canv.fill(ColorAttribute::PaletteIndex(0));
-        // Stats string will have a gray foreground
-        self.stats_canv
-            .set_fg_color(ColorAttribute::PaletteIndex(7));
-
-        let pipe_len = self.state.currently_drawn_pieces + self.state.pieces_remaining;
-
-        let color = self
-            .state
-            .pipe_piece
-            .color
-            .map_or("DEFAULT".to_string(), |c| match c {
-                ColorAttribute::Default => "DEFAULT".to_string(),
-                ColorAttribute::PaletteIndex(i) => match i {
-                    0 => "BLACK",
-                    1 => "RED",
-                    2 => "GREEN",
-                    3 => "YELLOW",
-                    4 => "BLUE",
-                    5 => "MAGENTA",
-                    6 => "CYAN",
-                    7 => "WHITE",
-                    8 => "BRIGHT BLACK",
-                    9 => "BRIGHT RED",
-                    10 => "BRIGHT GREEN",
-                    11 => "BRIGHT YELLOW",
-                    12 => "BRIGHT BLUE",
-                    13 => "BRIGHT MAGENTA",
-                    14 => "BRIGHT CYAN",
-                    15 => "BRIGHT GRAY",
-                    _ => unreachable!(),
-                }
-                .to_string(),
-                ColorAttribute::TrueColorWithPaletteFallback(c, _)
-                | ColorAttribute::TrueColorWithDefaultFallback(c) => c.to_rgb_string(),
-            });
+    /// Flash the screen for a frame. When the flash ends, the screen is cleared.
+    fn flash_step(&mut self) {
+        self.history.clear();
+        self.state.flash_frames_left = self.state.flash_frames_left.saturating_sub(1);
 
-        let s = format!(
-            "pcs. drawn: {}, lpcs. drawn: {}, c. pcs. drawn: {}, pps. drawn: {}, pcs. rem: {}, l. drawn: {}, pps. len: {}, pipe color: {}, fps: {}",
-            self.state.pieces_total,
-            self.state.layer_pieces_total,
-            self.state.currently_drawn_pieces,
-            self.state.pipes_total,
-            self.state.pieces_remaining,
-            self.state.layers_drawn,
-            pipe_len,
-            color,
-            self.cfg.fps,
-        );
+        if self.state.flash_frames_left == 0 {
+            self.clear();
+        } else if self.state.flash_frames_left & 2 == 0 {
+            // Alternate every two frames
+            self.canv.fill(ColorAttribute::PaletteIndex(15));
+        } else {
+            self.draw_bg();
+        }
+    }
+
+    /// Fade the pipes out by a frame: darken them and erase some of them. When the fade ends, the
+    /// screen is cleared.
+    fn fade_step(&mut self) {
+        self.history.clear();
+
+        let frames_left = self.state.fade_frames_left;
+        self.state.fade_frames_left -= 1;
+
+        if self.state.fade_frames_left == 0 {
+            self.clear();
+
+            return;
+        }
+
+        let bg = self.bg_color.or(self.cfg.term_bg_color);
+
+        self.canv
+            .darken(FADE_FACTOR, bg.unwrap_or(SrgbaTuple(0.0, 0.0, 0.0, 1.0)));
+        self.canv
+            .dissolve(1.0 / frames_left as f64, self.bg_attr(), &mut self.rng);
+
+        if self.cfg.walled {
+            self.draw_walls();
+        }
+    }
+
+    /// Retract the pipes by a frame: erase their most recently drawn pieces, restoring what they
+    /// have overdrawn. When all recorded pieces are gone, the screen is cleared.
+    fn retract_step(&mut self) {
+        self.history.clear();
+
+        for _ in 0..self.state.retract_per_frame {
+            let Some(r) = self.pipe_records.last_mut() else {
+                break;
+            };
+
+            if let Some(piece) = r.pieces.pop() {
+                self.canv.put_cell(piece.pos, &piece.prev_cell);
+            }
+
+            if r.pieces.is_empty() {
+                self.pipe_records.pop();
+            }
+        }
+
+        if self.pipe_records.is_empty() {
+            self.state.retract_per_frame = 0;
+            self.clear();
+        }
+    }
+
+    /// Make all pipe pieces in previous layers darker.
+    fn darken_previous_layers(&mut self) {
+        self.emit(SimEvent::LayerDarkened);
+        self.sim.new_layer();
+
+        let min = self.darken_min();
+
+        // The simulation goes on while the worker darkens the previous layers
+        match &mut self.postfx {
+            Some(p) => p.submit(
+                &mut self.canv,
+                vec![Pass::Darken {
+                    amount: self.cfg.darken_factor,
+                    min,
+                }],
+            ),
+            None => self.canv.darken(self.cfg.darken_factor, min),
+        }
+
+        if self.cfg.walled {
+            self.draw_walls();
+        }
+    }
+
+    /// Publish the simulation event to all subsystems interested in it.
+    fn emit(&mut self, event: SimEvent) {
+        let e = TimedEvent::now(event);
+
+        self.count_event(&e);
+        self.record_event(&e);
+        self.forget_animated(&e);
+        self.spark_event(&e);
+        self.announce_event(&e);
+
+        if let Some(s) = &mut self.sound {
+            s.handle(&e);
+        }
+    }
+
+    /// Update the counters shown in the stats with the simulation event.
+    fn count_event(&mut self, e: &TimedEvent) {
+        match e.event {
+            SimEvent::PipeSpawned => {
+                // Each new pipe completes the previous one
+                if self.state.pieces_total > 0 {
+                    self.state.pipes_total += 1;
+                }
+
+                self.pipe_started_at = e.at;
+            }
+            SimEvent::Turned | SimEvent::Collided(_) => {}
+            SimEvent::LayerDarkened => {
+                self.finish_pipe();
+
+                let state = &mut self.state;
+
+                state.layer_pieces_total = 0;
+                state.layer_started_at = e.at;
+                state.layers_drawn += 1;
+            }
+            SimEvent::Cleared => {
+                self.finish_pipe();
+
+                let state = &mut self.state;
+
+                state.layer_pieces_total = 0;
+                state.pieces_total = 0;
+                state.pieces_at_stop = 0;
+                state.layers_drawn = 0;
+                state.pipes_total = 0;
+                state.cleared_at = e.at;
+                state.layer_started_at = e.at;
+            }
+            SimEvent::Resized(_) => {
+                if self.cfg.show_stats {
+                    self.draw_stats();
+                }
+            }
+        }
+    }
+
+    /// Forget the drawn pipes which can no longer be undone or retracted after the simulation
+    /// event.
+    fn record_event(&mut self, e: &TimedEvent) {
+        match e.event {
+            // The new layer is drawn on top of all previous ones
+            SimEvent::LayerDarkened | SimEvent::Cleared | SimEvent::Resized(_) => {
+                self.pipe_records.clear();
+                self.history.clear();
+            }
+            SimEvent::PipeSpawned | SimEvent::Turned | SimEvent::Collided(_) => {}
+        }
+    }
+
+    /// Forget the animated pieces wiped out by the simulation event.
+    fn forget_animated(&mut self, e: &TimedEvent) {
+        match e.event {
+            SimEvent::Cleared => self.animated.clear(),
+            SimEvent::Resized(size) => self.animated.resize(size),
+            _ => {}
+        }
+    }
+
+    /// Start or drop the sparks (see --end-on-collision) with the simulation event.
+    fn spark_event(&mut self, e: &TimedEvent) {
+        let Some(s) = &mut self.sparks else {
+            return;
+        };
+
+        match e.event {
+            SimEvent::Collided(p) => s.ignite(&mut self.canv, p),
+            SimEvent::Cleared | SimEvent::Resized(_) => s.clear(),
+            _ => {}
+        }
+    }
+
+    /// Show the next frame of the animated pieces if it's time to. Neighbouring cells are a frame
+    /// apart, so the pipes shimmer. Cells whose glyph is no longer a frame of their piece (e.g.
+    /// after undo) are forgotten.
+    fn animate_glyphs(&mut self) {
+        let Some(meta) = &self.cfg.custom_piece_set_meta else {
+            return;
+        };
+
+        if meta.frames.is_empty() || self.glyph_frame.1.elapsed() < GLYPH_FRAME_INTERVAL {
+            return;
+        }
+
+        let frame = self.glyph_frame.0.wrapping_add(1);
+        let (w, h) = self.canv.size();
+
+        self.glyph_frame = (frame, Instant::now());
+
+        for y in 0..h {
+            for x in 0..w {
+                let p = Point {
+                    x: x as isize,
+                    y: y as isize,
+                };
+
+                let Some(a) = self.animated.get_mut(p) else {
+                    continue;
+                };
+                let Some(idx) = *a else {
+                    continue;
+                };
+
+                let frames = meta.frames(idx).unwrap_or_default();
+                let Some(cell) = self.canv.cell(p) else {
+                    continue;
+                };
+
+                if !frames.iter().any(|f| f == cell.str()) {
+                    *a = None;
+
+                    continue;
+                }
+
+                let next = &frames[(frame + x + y) % frames.len()];
+
+                if next != cell.str() {
+                    self.canv
+                        .put_cell(p, &Cell::new_grapheme(next, cell.attrs().clone(), None));
+                }
+            }
+        }
+    }
+
+    /// Announce the major simulation events (see --screen-reader).
+    fn announce_event(&mut self, e: &TimedEvent) {
+        match e.event {
+            SimEvent::Cleared => self.announce("screen cleared".to_string()),
+            SimEvent::LayerDarkened => {
+                self.announce(format!("layer {}", self.state.layers_drawn + 1))
+            }
+            _ => {}
+        }
+    }
+
+    /// Announce a major event in the window title with the next render (see --screen-reader).
+    fn announce(&mut self, event: String) {
+        if self.cfg.screen_reader {
+            self.announcement = Some(event);
+        }
+    }
+
+    /// Render pipes and maybe stats.
+    fn render(&mut self) -> Result<()> {
+        self.draw_heatmap();
+        self.draw_pause_badge();
+
+        let Some(term_scr) = &mut self.term_scr else {
+            return Ok(());
+        };
+
+        if let Some(p) = &mut self.postfx {
+            p.poll(&mut self.canv);
+        }
+
+        if let Some(f) = &mut self.flow {
+            f.apply(
+                &mut self.canv,
+                &self.pipe_records,
+                &self.sim.occupancy,
+                self.state.pause,
+            );
+        }
+
+        if let Some(w) = &mut self.weather {
+            w.apply(&mut self.canv);
+        }
+
+        if let Some(s) = &mut self.sparks {
+            s.apply(&mut self.canv);
+        }
+
+        let mut overlays = vec![];
+
+        if let Some(b) = &self.bouncer {
+            overlays.push(b.canvas());
+        }
+
+        if let Some(c) = &self.countdown {
+            overlays.push(c.canvas());
+        }
+
+        #[cfg(feature = "remote")]
+        if let Some(r) = &self.remote {
+            overlays.extend(r.layers());
+        }
+
+        if let Some(b) = &self.wrap_box {
+            overlays.push(b);
+        }
+
+        if self.cfg.show_stats {
+            overlays.push(&self.stats_canv);
+        }
+
+        if let Some(b) = &self.pause_badge {
+            overlays.push(b);
+        }
+
+        // The overlays are blended with what's beneath them (see --overlay-opacity)
+        let blended: Vec<Canvas>;
+
+        if self.cfg.overlay_opacity < 1.0 {
+            let beneath = self.heatmap.as_ref().unwrap_or(&self.canv);
+            let default = (
+                to_srgba(ColorAttribute::PaletteIndex(7)).unwrap_or(SrgbaTuple(1.0, 1.0, 1.0, 1.0)),
+                self.bg_color
+                    .or(self.cfg.term_bg_color)
+                    .unwrap_or(SrgbaTuple(0.0, 0.0, 0.0, 1.0)),
+            );
+
+            blended = overlays
+                .iter()
+                .map(|o| o.composite_over(beneath, self.cfg.overlay_opacity, default))
+                .collect();
+            overlays = blended.iter().collect();
+        }
+
+        // Overlays which move, appear or vanish leave stale cells behind, so the pipes must be
+        // copied whole
+        let full = self.bouncer.is_some() || overlays.len() != self.overlays_drawn;
+
+        match &self.heatmap {
+            Some(h) => term_scr.copy_canvas(h),
+            None => term_scr.copy_canvas_changes(&mut self.canv, full),
+        }
+
+        for o in &overlays {
+            term_scr.copy_canvas(o);
+        }
+
+        self.overlays_drawn = overlays.len();
+
+        if let Some(s) = &mut self.sparks {
+            s.restore(&mut self.canv);
+        }
+
+        if let Some(w) = &mut self.weather {
+            w.restore(&mut self.canv);
+        }
+
+        if let Some(f) = &mut self.flow {
+            f.restore(&mut self.canv);
+        }
+
+        if let Some(event) = self.announcement.take() {
+            term_scr.set_title(&format!("rxpipes: {event}"));
+        }
+
+        let render_start = Instant::now();
+
+        term_scr.render()?;
+        self.rendered_at = Instant::now();
+
+        if self.cfg.adaptive_fps {
+            self.render_time = self.render_time.mul_f64(1.0 - RENDER_TIME_SMOOTHING)
+                + render_start.elapsed().mul_f64(RENDER_TIME_SMOOTHING);
+        }
+
+        Ok(())
+    }
+
+    /// Show or hide the stats widget.
+    fn toggle_stats(&mut self) {
+        self.cfg.show_stats = !self.cfg.show_stats;
+
+        if self.cfg.show_stats {
+            self.draw_stats();
+        } else {
+            self.reserve_stats_rows();
+        }
+
+        // The walls, the maze and the curve are laid out for the whole area
+        if self.cfg.walled || matches!(self.cfg.mode, Mode::Maze | Mode::Curve) {
+            self.clear();
+        }
+    }
+
+    /// Keep the pipes away from the rows of the stats widget while it's shown.
+    fn reserve_stats_rows(&mut self) {
+        let rows = if self.cfg.show_stats {
+            self.stats_canv.size().1
+        } else {
+            0
+        };
+
+        let area = self.sim.area();
+
+        self.sim.reserve_rows(rows);
+
+        // The box must fit into the rest of the screen
+        if self.sim.area() != area {
+            self.draw_wrap_box();
+        }
+    }
+
+    /// Draw the output of --wrap-cmd in a box filling the screen except for a band around it, and
+    /// keep the pipes out of the box. Lines which don't fit are cut.
+    fn draw_wrap_box(&mut self) {
+        let Some(lines) = &self.wrap_output else {
+            return;
+        };
+
+        let (cols, rows) = self.sim.area();
+        let size = (
+            cols.saturating_sub(2 * WRAP_BAND.0),
+            rows.saturating_sub(2 * WRAP_BAND.1),
+        );
+
+        if size.0 < 3 || size.1 < 3 {
+            self.wrap_box = None;
+            self.sim.reserve_rect(None);
+
+            return;
+        }
+
+        let pos = Point {
+            x: WRAP_BAND.0 as isize,
+            y: WRAP_BAND.1 as isize,
+        };
+        let mut b = Canvas::new(pos, size);
+        let (w, h) = size;
+        let inner = "─".repeat(w - 2);
+
+        b.fill(self.bg_attr());
+        b.set_fg_color(ColorAttribute::PaletteIndex(7));
+        b.put_str(format!("╭{inner}╮"));
+
+        for y in 1..h as isize - 1 {
+            b.move_to(Point { x: 0, y });
+            b.put_str("│");
+            b.move_to(Point {
+                x: w as isize - 1,
+                y,
+            });
+            b.put_str("│");
+        }
+
+        b.move_to(Point {
+            x: 0,
+            y: h as isize - 1,
+        });
+        b.put_str(format!("╰{inner}╯"));
+
+        for (y, l) in lines.iter().take(h - 2).enumerate() {
+            let mut width = 0;
+            let line: String = l
+                .chars()
+                .take_while(|c| {
+                    width += unicode_column_width(&c.to_string(), None);
+
+                    width <= w - 2
+                })
+                .collect();
+
+            b.move_to(Point {
+                x: 1,
+                y: y as isize + 1,
+            });
+            b.put_str(line);
+        }
+
+        self.wrap_box = Some(b);
+        self.sim.reserve_rect(Some(Geometry { size, pos }));
+    }
+
+    /// Show the pause badge while paused, or remove it (see --pause-badge).
+    fn draw_pause_badge(&mut self) {
+        if !self.state.pause || self.cfg.pause_badge == BadgePosition::None {
+            self.pause_badge = None;
+
+            return;
+        }
+
+        let (w, h) = self.canv.size();
+        let width = unicode_column_width(PAUSE_BADGE, None);
+        let (right, bottom) = (
+            w.saturating_sub(width) as isize,
+            h.saturating_sub(1) as isize,
+        );
+
+        let pos = match self.cfg.pause_badge {
+            BadgePosition::TopLeft => Point { x: 0, y: 0 },
+            BadgePosition::TopRight | BadgePosition::None => Point { x: right, y: 0 },
+            BadgePosition::BottomLeft => Point { x: 0, y: bottom },
+            BadgePosition::BottomRight => Point {
+                x: right,
+                y: bottom,
+            },
+            BadgePosition::Center => Point {
+                x: right / 2,
+                y: bottom / 2,
+            },
+        };
+
+        let badge = self.pause_badge.get_or_insert_with(|| {
+            let mut c = Canvas::new(pos, (width, 1));
+
+            c.fill(ColorAttribute::PaletteIndex(7));
+            c.set_fg_color(ColorAttribute::PaletteIndex(0));
+            c.put_str(PAUSE_BADGE);
+
+            c
+        });
+
+        badge.pos = pos;
+    }
+
+    /// Show the heatmap instead of the pipes, or the pipes again.
+    fn toggle_heatmap(&mut self) {
+        if self.heatmap.take().is_some() {
+            self.canv.invalidate();
+        } else {
+            self.heatmap = Some(Canvas::new(self.canv.pos, self.canv.size()));
+        }
+    }
+
+    /// Draw how many times each cell has been drawn, if the heatmap is shown. The counts are
+    /// scaled logarithmically, so the rarely visited cells don't all look the same.
+    fn draw_heatmap(&mut self) {
+        let bg = self.bg_attr();
+        let Some(heatmap) = &mut self.heatmap else {
+            return;
+        };
+
+        let (w, h) = heatmap.size();
+        let points: Vec<Point> = (0..h as isize)
+            .flat_map(|y| (0..w as isize).map(move |x| Point { x, y }))
+            .collect();
+        let visits = |p: Point| self.sim.visits.get(p).copied().unwrap_or(0);
+        let max = points.iter().map(|p| visits(*p)).max().unwrap_or(0);
+
+        heatmap.fill(bg);
+
+        for p in points {
+            let v = visits(p);
+
+            if v == 0 {
+                continue;
+            }
+
+            let heat = (v as f32).ln_1p() / (max as f32).ln_1p();
+
+            heatmap.move_to(p);
+            heatmap.set_fg_color(ColorAttribute::TrueColorWithDefaultFallback(heat_color(
+                heat,
+            )));
+            heatmap.put_str("█");
+        }
+    }
+
+    /// Render, but with --screen-reader only every few seconds or when there is something to
+    /// announce, to spare accessibility tools the constant cursor movement.
+    fn render_throttled(&mut self) -> Result<()> {
+        if self.frozen {
+            return Ok(());
+        }
+
+        if self.cfg.screen_reader
+            && self.announcement.is_none()
+            && self.rendered_at.elapsed() < SCREEN_READER_RENDER_INTERVAL
+        {
+            return Ok(());
+        }
+
+        self.render()
+    }
+
+    /// Run the main loop in the current thread until an external event is received (a key press or
+    /// signal) or some internal error is occurred.
+    pub fn run(&mut self) -> Result<()> {
+        if let Some(d) = self.cfg.start_delay {
+            self.wait_start(d)?;
+        }
+
+        if self.cfg.warmup > 0 {
+            self.simulate(self.cfg.warmup);
+            self.render()?;
+        }
+
+        self.started_at = Some(Instant::now());
+        self.frame_at = (Instant::now(), SystemTime::now());
+
+        while !self.state.quit {
+            let delay = self.frame_delay();
+
+            if let Some(w) = &mut self.watchdog {
+                w.tick(delay, self.frozen);
+            }
+
+            // While frozen, the simulation skips ahead as fast as possible
+            if self.frozen && self.is_running() {
+                self.handle_events(Duration::ZERO)?;
+            } else {
+                self.wait_frame()?;
+            }
+
+            if self.state.quit {
+                break;
+            }
+
+            // The terminal may have been redrawn by something else in the meantime. The frame
+            // pacing starts over, nothing is caught up
+            if self.resumed_from_suspend() {
+                self.redraw()?;
+            }
+
+            // The simulation waits until the rewound frames are played up to the present
+            if let Some(r) = self.replay.as_mut().filter(|r| r.is_playing()) {
+                r.play_step(&mut self.canv);
+                self.render()?;
+
+                continue;
+            }
+
+            // The steps of the sync server are replayed as they arrive
+            if !self.following_sync && self.is_running() {
+                let steps = if self.turbo { TURBO_STEPS_PER_FRAME } else { 1 };
+
+                for _ in 0..steps {
+                    self.update();
+                }
+
+                if !self.frozen && !self.cfg.reduced_motion {
+                    self.animate_glyphs();
+                }
+
+                self.render_throttled()?;
+
+                if !self.frozen {
+                    self.frames += 1;
+
+                    if let Some(r) = &mut self.replay {
+                        r.record(&mut self.canv);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the system has been suspended since the last frame, i.e. the gap between
+    /// frames is far longer than the frame delay. The monotonic clock may stop while the system is
+    /// suspended, so the wall clock is checked too.
+    fn resumed_from_suspend(&mut self) -> bool {
+        let (at, wall_at) = self.frame_at;
+        let gap = at.elapsed().max(wall_at.elapsed().unwrap_or_default());
+
+        self.frame_at = (Instant::now(), SystemTime::now());
+
+        gap > self.frame_delay() + SUSPEND_GAP
+    }
+
+    /// Summarize the run: its duration, drawn pieces and pipes, the average FPS and the seed.
+    /// Returns `None` if the animation hasn't started.
+    pub fn summary(&self) -> Option<String> {
+        let elapsed = self.started_at?.elapsed();
+        let secs = elapsed.as_secs();
+        let pipe = &self.sim.pipe;
+
+        Some(format!(
+            "Ran for {}h {:02}m {:02}s\n\
+             Pieces drawn: {}\n\
+             Pipes drawn: {}\n\
+             Average FPS: {:.1}\n\
+             Seed: {} (reproduce with --seed {})",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            self.pipe_stats.pieces + pipe.pieces_drawn,
+            self.pipe_stats.pipes + (pipe.pieces_drawn > 0) as u64,
+            self.frames as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            self.seed,
+            self.seed,
+        ))
+    }
+
+    /// Retrieve the descriptions of the busy loops detected by the watchdog.
+    pub fn watchdog_log(&self) -> &[String] {
+        self.watchdog.as_ref().map_or(&[], |w| w.log())
+    }
+
+    /// Retrieve the failures of the actions of the keys.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Keep the failure of an action of a key to report it after exit. A key shouldn't end the
+    /// screensaver just because its action has failed.
+    fn warn_on_failure(&mut self, r: Result<()>) {
+        if let Err(e) = r {
+            self.warnings.push(format!("{e:#}"));
+        }
+    }
+
+    /// Wait before the animation begins, showing the splash screen if enabled (see --splash). The
+    /// input is handled in the meantime, so the user can quit.
+    fn wait_start(&mut self, delay: Duration) -> Result<()> {
+        let deadline = Instant::now() + delay;
+
+        if self.cfg.splash {
+            let theme = self.cfg.theme.as_deref().unwrap_or("none");
+            let lines = [
+                format!("rxpipes v{}", env!("CARGO_PKG_VERSION")),
+                format!("theme: {theme}"),
+                "press q to quit".to_string(),
+            ];
+            let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+            let (w, h) = self.canv.size();
+
+            let mut splash = Canvas::new(
+                Point {
+                    x: w.saturating_sub(width) as isize / 2,
+                    y: h.saturating_sub(lines.len()) as isize / 2,
+                },
+                (width, lines.len()),
+            );
+
+            splash.fill(self.bg_attr());
+            splash.set_fg_color(ColorAttribute::PaletteIndex(7));
+
+            for (i, l) in lines.iter().enumerate() {
+                splash.move_to(Point {
+                    x: (width - l.chars().count()) as isize / 2,
+                    y: i as isize,
+                });
+                splash.put_str(l);
+            }
+
+            if let Some(t) = &mut self.term_scr {
+                t.copy_canvas(&splash);
+                t.render()?;
+            }
+        }
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            if self.state.quit || left.is_zero() {
+                break;
+            }
+
+            self.handle_events(left.min(INPUT_POLL_SLICE))?;
+        }
+
+        if self.cfg.splash {
+            self.redraw()?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait until the next frame is due, handling the input in the meantime. The input is polled
+    /// in short slices, so quitting, pausing and changing the FPS take effect right away
+    /// regardless of the FPS.
+    fn wait_frame(&mut self) -> Result<()> {
+        let start = Instant::now();
+
+        loop {
+            // The FPS may change while waiting
+            let left = (start + self.frame_delay()).saturating_duration_since(Instant::now());
+
+            if self.state.quit || left.is_zero() {
+                return Ok(());
+            }
+
+            // A delivered event wakes up the wait for input
+            self.handle_events(left.min(INPUT_POLL_SLICE))?;
+
+            // The events are handled while the user is active too, otherwise the end of the
+            // activity would never be noticed (see --idle-start)
+            if !self.state.pause {
+                self.handle_queued_events()?;
+            }
+        }
+    }
+
+    /// Check whether the simulation runs: it's not paused and the user isn't active (see
+    /// --idle-start).
+    fn is_running(&mut self) -> bool {
+        !self.state.pause && !self.is_user_active()
+    }
+
+    /// Handle the events delivered by the background sources: replay the steps received from the
+    /// sync server and carry out the remote commands. If the connection to the sync server is
+    /// lost, the simulation continues on its own.
+    fn handle_queued_events(&mut self) -> Result<()> {
+        let mut changed = false;
+
+        while let Some(event) = self.events.try_next() {
+            match event {
+                Event::Sync(SyncMessage::Reset(seed)) => self.reseed(seed),
+                Event::Sync(SyncMessage::Step) => self.update(),
+                Event::SyncLost => self.following_sync = false,
+                #[cfg(feature = "remote")]
+                Event::Remote(cmd) => {
+                    let size = self.canv.size();
+
+                    if self.remote.as_mut().is_some_and(|r| r.handle(cmd, size)) {
+                        self.clear_gently();
+                    }
+                }
+                Event::Weather(w) => {
+                    if let Some(s) = &mut self.weather {
+                        s.set(w);
+                    }
+                }
+                Event::WrapOutput(lines) => {
+                    self.wrap_output = Some(lines);
+                    self.draw_wrap_box();
+                }
+                Event::Idle(idle) => self.user_active = !idle,
+                Event::Spawn { count, key } => {
+                    if let Some(q) = &mut self.spawn_queue {
+                        let room = MAX_QUEUED_SPAWNS.saturating_sub(q.len());
+
+                        for _ in 0..count.min(room) {
+                            q.push_back(key);
+                        }
+                    }
+                }
+            }
+
+            changed = true;
+        }
+
+        if changed {
+            self.render_throttled()?;
+        }
+
+        Ok(())
+    }
+
+    /// Start the simulation again on a clean screen with specified seed.
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.spawn_rng = side_rng(seed, SideRng::Spawn);
+        self.state.flash_frames_left = 0;
+        self.state.fade_frames_left = 0;
+        self.state.retract_per_frame = 0;
+        self.clear();
+    }
+
+    /// Remember what the updates of the step are going to overwrite, so the step can be undone.
+    fn record_step(&mut self, prev_state: State, prev_pipe: CurrentPipe, updates: &[CellUpdate]) {
+        // The maze and curve generators can't go back
+        if matches!(self.cfg.mode, Mode::Maze | Mode::Curve) {
+            return;
+        }
+
+        let cells: Vec<SavedCell> = updates
+            .iter()
+            .filter_map(|u| {
+                Some(SavedCell {
+                    pos: u.pos,
+                    cell: self.canv.cell(u.pos)?,
+                    occupancy: u.prev_occupancy,
+                    depth: u.prev_depth,
+                })
+            })
+            .collect();
+
+        if self.history.len() >= HISTORY_LEN {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(Undo {
+            state: prev_state,
+            pipe: prev_pipe,
+            cells,
+        });
+    }
+
+    /// Undo the last simulation step.
+    fn step_back(&mut self) {
+        let Some(undo) = self.history.pop_back() else {
+            return;
+        };
+
+        let (quit, pause) = (self.state.quit, self.state.pause);
+
+        self.state = undo.state;
+        self.state.quit = quit;
+        self.state.pause = pause;
+        self.sim.pipe = undo.pipe;
+
+        for saved in undo.cells.iter().rev() {
+            self.canv.put_cell(saved.pos, &saved.cell);
+
+            if let Some(r) = self.pipe_records.last_mut() {
+                if r.pieces.last().is_some_and(|p| p.pos == saved.pos) {
+                    r.pieces.pop();
+
+                    if r.pieces.is_empty() {
+                        self.pipe_records.pop();
+                    }
+                }
+            }
+
+            if let Some(o) = self.sim.occupancy.get_mut(saved.pos) {
+                *o = saved.occupancy;
+            }
+
+            if let Some(d) = self.sim.depth_buf.get_mut(saved.pos) {
+                *d = saved.depth;
+            }
+        }
+
+        if self.cfg.show_stats {
+            self.draw_stats();
+        }
+    }
+
+    /// Check whether spawning of the next pipe should be postponed because of low system load, or
+    /// because no external source has requested it.
+    fn is_spawn_held(&mut self) -> bool {
+        if self.sim.pipe.pieces_remaining == 0
+            && self.spawn_queue.as_ref().is_some_and(|q| q.is_empty())
+        {
+            return true;
+        }
+
+        let Some(m) = &self.load_meter else {
+            return false;
+        };
+
+        let spawn_prob = IDLE_SPAWN_PROB + (1.0 - IDLE_SPAWN_PROB) * m.load() as f64;
+
+        matches!(
+            self.cfg.mode,
+            Mode::Pipes | Mode::LifeDecay | Mode::Weather | Mode::GitActivity
+        ) && self.sim.pipe.pieces_remaining == 0
+            && !self.spawn_rng.gen_bool(spawn_prob)
+    }
+
+    /// Reseed the random number generator for the next step from the next byte of the piped input
+    /// (see --stdin-mode). Returns `false` if the step has to wait for the input.
+    fn feed_input(&mut self) -> bool {
+        let Some(input) = &mut self.input else {
+            return true;
+        };
+
+        let Some(seed) = input.next_seed() else {
+            return false;
+        };
+
+        self.rng = StdRng::seed_from_u64(seed);
+
+        true
+    }
+
+    /// Erase the most recently completed pipe, restoring what it has overdrawn.
+    fn erase_last_pipe(&mut self) {
+        let current_id = self.sim.pipe.id;
+        let current_done = self.sim.pipe.pieces_remaining == 0;
+
+        let Some(i) = self
+            .pipe_records
+            .iter()
+            .rposition(|r| r.id != current_id || current_done)
+        else {
+            return;
+        };
+
+        let record = self.pipe_records.remove(i);
+
+        for piece in record.pieces.iter().rev() {
+            // Parts of the pipe could be overdrawn by other pipes
+            if self.sim.occupancy.get(piece.pos) != Some(&Some(record.id)) {
+                continue;
+            }
+
+            self.canv.put_cell(piece.pos, &piece.prev_cell);
+
+            if let Some(o) = self.sim.occupancy.get_mut(piece.pos) {
+                *o = piece.prev_occupancy;
+            }
+
+            if let Some(d) = self.sim.depth_buf.get_mut(piece.pos) {
+                *d = None;
+            }
+        }
+
+        let len = record.pieces.len() as u64;
+
+        self.state.pieces_total = self.state.pieces_total.saturating_sub(len);
+        self.state.layer_pieces_total = self.state.layer_pieces_total.saturating_sub(len);
+        self.state.pipes_total = self.state.pipes_total.saturating_sub(1);
+
+        if record.id == current_id {
+            self.sim.pipe.pieces_drawn = 0;
+        }
+
+        self.history.clear();
+
+        if self.cfg.show_stats {
+            self.draw_stats();
+        }
+    }
+
+    /// Switch to the theme scheduled for the current time if it has changed (see --schedule). The
+    /// screen is cleared, so the pipes are drawn in the new colors from scratch.
+    fn follow_schedule(&mut self) {
+        let Some(name) = self
+            .cfg
+            .schedule
+            .as_ref()
+            .zip(local_minutes())
+            .map(|(s, now)| s.at(now))
+        else {
+            return;
+        };
+
+        if self.cfg.theme.as_deref() == Some(name) {
+            return;
+        }
+
+        let name = name.to_string();
+
+        // The themes have been checked at the start, but the theme file may be gone since then
+        if let Ok(theme) = Theme::find(&name) {
+            self.switch_theme(&theme);
+            self.clear();
+        }
+
+        self.cfg.theme = Some(name);
+    }
+
+    /// Switch to the next built-in theme (see --cycle-on-clear). The screen isn't redrawn.
+    fn cycle_theme(&mut self) {
+        let themes = Theme::builtin();
+        let next = self
+            .cfg
+            .theme
+            .as_ref()
+            .and_then(|name| themes.iter().position(|t| t.name == *name))
+            .map_or(0, |i| (i + 1) % themes.len());
+
+        self.switch_theme(&themes[next]);
+        self.cfg.theme = Some(themes[next].name.clone());
+    }
+
+    /// Apply the theme to the configuration and adapt the simulation to it. The screen isn't
+    /// redrawn.
+    fn switch_theme(&mut self, theme: &Theme) {
+        theme.apply(&mut self.cfg);
+
+        if self.cfg.console {
+            self.cfg.apply_console();
+        }
+
+        if let Some(level) = self.cfg.term_color_level {
+            self.cfg.adapt_to_colors(level);
+        }
+
+        self.sim.color_pool.palette = self.cfg.palette;
+
+        if matches!(
+            self.cfg.mode,
+            Mode::Pipes | Mode::LifeDecay | Mode::Weather | Mode::GitActivity
+        ) {
+            self.sim.spacing = meta(&self.cfg);
+        }
+
+        if self.bg_cycle.is_empty() {
+            let bg = self.cfg.bg_color.as_deref().and_then(|c| {
+                let hc = HexColor::parse_rgb(c).ok()?;
+
+                Some(SrgbaTuple(
+                    hc.r as f32 / 255.0,
+                    hc.g as f32 / 255.0,
+                    hc.b as f32 / 255.0,
+                    1.0,
+                ))
+            });
+
+            self.set_bg_color(bg);
+        }
+    }
+
+    /// Check whether the animation should stay still because the user is active (`--idle-start`).
+    fn is_user_active(&self) -> bool {
+        self.user_active
+    }
+
+    /// Advance the screensaver by one frame.
+    fn update(&mut self) {
+        // Let all instances start together from the beginning. The new seed is derived from the
+        // current one, so the run stays reproducible
+        if let Some(server) = self.sync_server.as_ref().filter(|s| s.has_new_clients()) {
+            let seed = side_rng(self.seed, SideRng::Sync).gen();
+
+            server.broadcast(SyncMessage::Reset(seed));
+            self.reseed(seed);
+        }
+
+        if let Some(server) = &self.sync_server {
+            server.broadcast(SyncMessage::Step);
+        }
+
+        if self.cfg.redshift {
+            self.sim.color_pool.warmth =
+                local_minutes().map_or(0.0, |now| night_depth(now, self.cfg.night_hours));
+        }
+
+        self.follow_schedule();
+
+        if self.cfg.mode == Mode::LifeDecay
+            && self.state.decay_steps_left == 0
+            && self
+                .cfg
+                .decay_interval
+                .is_some_and(|i| self.state.cleared_at.elapsed() >= i)
+        {
+            self.state.decay_steps_left = self.cfg.decay_steps;
+        }
+
+        if let Some(m) = &mut self.load_meter {
+            m.update();
+        }
+
+        if self.state.flash_frames_left > 0 {
+            self.flash_step();
+        } else if self.state.fade_frames_left > 0 {
+            self.fade_step();
+        } else if self.state.retract_per_frame > 0 {
+            self.retract_step();
+        } else if self.state.decay_steps_left > 0 {
+            self.decay_step();
+        } else if self.is_spawn_held() {
+            // The system is calm, so wait a bit before the next pipe
+        } else if !self.feed_input() {
+            // Wait for more input
+        } else {
+            let (prev_state, prev_pipe) = (self.state.clone(), self.sim.pipe.clone());
+
+            let updates = match self.cfg.mode {
+                Mode::Pipes | Mode::LifeDecay | Mode::Weather | Mode::GitActivity => {
+                    if self.sim.pipe.pieces_remaining == 0 {
+                        self.finish_pipe();
+                        self.sim.spawn_key = self.spawn_queue.as_mut().and_then(|q| q.pop_front());
+                    }
+
+                    let updates = self.sim.step(&self.cfg, &mut self.rng);
+
+                    self.sim.spawn_key = None;
+
+                    if let Some(p) = self.sim.collision {
+                        self.emit(SimEvent::Collided(p));
+                    }
+
+                    // Pipes blocked right away are replaced in the same step
+                    for _ in prev_pipe.id..self.sim.pipe.id {
+                        self.emit(SimEvent::PipeSpawned);
+                    }
+
+                    updates
+                }
+                Mode::Maze => {
+                    self.gen_next_maze_piece();
+                    self.sim.draw(&self.cfg)
+                }
+                Mode::Curve => {
+                    if self.gen_next_curve_piece() {
+                        self.sim.draw(&self.cfg)
+                    } else {
+                        vec![]
+                    }
+                }
+            };
+
+            let pipe = &self.sim.pipe;
+            let prev_turns = if pipe.id == prev_pipe.id {
+                prev_pipe.turns
+            } else {
+                0
+            };
+
+            if pipe.turns > prev_turns {
+                self.emit(SimEvent::Turned);
+            }
+
+            self.record_step(prev_state, prev_pipe, &updates);
+            self.draw_updates(updates);
+        }
+
+        if let Some(b) = &mut self.bouncer {
+            b.update(self.canv.size());
+        }
+
+        if let Some(w) = &mut self.weather {
+            w.update(self.canv.size());
+        }
+
+        #[cfg(feature = "remote")]
+        if let Some(r) = &mut self.remote {
+            r.update(self.canv.size());
+        }
+
+        #[cfg(feature = "notifications")]
+        if let Some((m, started_at)) = self.milestones.as_mut().zip(self.started_at) {
+            m.check(
+                self.pipe_stats.pieces + self.sim.pipe.pieces_drawn,
+                started_at.elapsed(),
+            );
+        }
+
+        if let Some(c) = &mut self.countdown {
+            c.update(self.canv.size());
+
+            if c.is_elapsed() {
+                self.countdown = None;
+
+                if self.cfg.reduced_motion {
+                    self.clear_gently();
+                } else {
+                    self.state.flash_frames_left = FLASH_FRAMES;
+                }
+            }
+        }
+
+        let stats_due = self
+            .cfg
+            .stats_interval
+            .is_none_or(|i| self.stats_drawn_at.elapsed() >= i);
+
+        if self.cfg.show_stats && stats_due {
+            self.draw_stats();
+        }
+    }
+
+    /// Delay between frames. With --adaptive-fps, it's stretched while rendering takes too large a
+    /// share of the frame.
+    fn frame_delay(&self) -> Duration {
+        if self.cfg.adaptive_fps {
+            self.delay
+                .max(self.render_time.div_f64(ADAPTIVE_FPS_RENDER_SHARE))
+        } else {
+            self.delay
+        }
+    }
+
+    fn calculate_delay(fps: i64) -> Duration {
+        Duration::from_millis(1000 / fps as u64)
+    }
+
+    /// Change the FPS by the amount, keeping it under the limit of reduced motion.
+    fn change_fps(&mut self, amount: i64) {
+        let max = if self.cfg.reduced_motion {
+            REDUCED_MOTION_MAX_FPS
+        } else {
+            i64::MAX
+        };
+
+        self.cfg.fps = self.cfg.fps.saturating_add(amount).clamp(1, max);
+        self.delay = Self::calculate_delay(self.cfg.fps);
+    }
+
+    /// Handle input and incoming events. Waits for them at most `timeout`.
+    fn handle_events(&mut self, timeout: Duration) -> Result<()> {
+        let Some(term_scr) = &mut self.term_scr else {
+            thread::sleep(timeout);
+
+            return Ok(());
+        };
+
+        if let Some(event) = term_scr
+            .terminal()
+            .terminal()
+            .poll_input(Some(timeout))
+            .wrap_err("cannot read incoming events")?
+        {
+            match event {
+                // Any key dismisses the screensaver if it guards a command
+                InputEvent::Key(_) if self.cfg.until_keypress_cmd.is_some() => {
+                    self.state.quit = true
+                }
+                InputEvent::Key(KeyEvent {
+                    key,
+                    modifiers: Modifiers::NONE,
+                }) => match key {
+                    KeyCode::Escape | KeyCode::Char('q') | KeyCode::Char('Q') => {
+                        self.state.quit = true
+                    }
+                    KeyCode::Char(' ') => {
+                        self.state.pause = !self.state.pause;
+                        self.render()?;
+                    }
+                    KeyCode::Char('c') => self.clear(),
+                    KeyCode::Char('l') => self.redraw()?,
+                    KeyCode::Char('f') => self.turbo = !self.turbo,
+                    KeyCode::Char('z') => {
+                        self.frozen = !self.frozen;
+                        self.render()?;
+                    }
+                    KeyCode::Char('H') => {
+                        self.toggle_heatmap();
+                        self.render()?;
+                    }
+                    KeyCode::Char('u') => {
+                        self.erase_last_pipe();
+                        self.render()?;
+                    }
+                    KeyCode::Char('s') => self.toggle_stats(),
+                    KeyCode::Char('r') => {
+                        if let Some(r) = &mut self.replay {
+                            r.rewind();
+                        }
+                    }
+                    KeyCode::Char('b') => {
+                        let r = Bookmark {
+                            seed: self.seed,
+                            theme: self.cfg.theme.clone(),
+                        }
+                        .save();
+
+                        self.warn_on_failure(r);
+                    }
+                    KeyCode::Char('y') => {
+                        let s = self.stats_line();
+                        let r = self.copy_to_clipboard(&s);
+
+                        self.warn_on_failure(r);
+                    }
+                    KeyCode::Char('Y') => {
+                        let s = self.canv.ansi_text();
+                        let r = self.copy_to_clipboard(&s);
+
+                        self.warn_on_failure(r);
+                    }
+                    KeyCode::Char('.') if self.state.pause => {
+                        self.update();
+                        self.render()?;
+                    }
+                    KeyCode::Char(',') if self.state.pause => {
+                        self.step_back();
+                        self.render()?;
+                    }
+                    KeyCode::Char(',') => self.change_fps(-1),
+                    KeyCode::Char('.') => self.change_fps(1),
+                    KeyCode::Char('<') => self.change_fps(-10),
+                    KeyCode::Char('>') => self.change_fps(10),
+                    _ => {}
+                },
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('c'),
+                    modifiers: Modifiers::CTRL,
+                }) => self.state.quit = true,
+                InputEvent::Resized { cols, rows } => {
+                    // The drawing area may be smaller than the screen (see --geometry)
+                    let (cols, rows) = match &mut self.term_scr {
+                        Some(t) => {
+                            t.resize((cols, rows));
+                            t.size()
+                        }
+                        None => (cols, rows),
+                    };
+
+                    self.canv.resize((cols, rows));
+                    self.maze = None;
+                    self.curve = None;
+                    self.sim.resize((cols, rows));
+                    self.draw_bg();
+                    self.emit(SimEvent::Resized((cols, rows)));
+                    self.draw_wrap_box();
+
+                    if let Some(h) = &mut self.heatmap {
+                        h.resize((cols, rows));
+                    }
+
+                    self.redraw()?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the text to the system clipboard of the terminal.
+    fn copy_to_clipboard(&mut self, text: &str) -> Result<()> {
+        if let Some(t) = &mut self.term_scr {
+            t.copy_to_clipboard(text)
+                .wrap_err("cannot copy to the clipboard")?;
+        }
+
+        Ok(())
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        if let Some(t) = &mut self.term_scr {
+            t.clear();
+        }
+
+        self.canv.invalidate();
+        self.render()?;
+
+        Ok(())
+    }
+
+    /// Draw a stats widget which shows pipe/piece/layers counters and the current pipe color. The
+    /// counters are wrapped into as many rows as needed at the bottom of the screen, optionally in
+    /// a box (see --stats-box).
+    fn draw_stats(&mut self) {
+        let (cols, rows) = self.canv.size();
+        let border = if self.cfg.stats_box { 2 } else { 0 };
+        let max_width = cols.saturating_sub(border).max(1);
+
+        let sep = self.stats_separator();
+        let mut lines: Vec<String> = vec![];
+
+        for item in self.stats_items() {
+            match lines.last_mut() {
+                Some(l)
+                    if l.chars().count() + sep.chars().count() + item.chars().count()
+                        <= max_width =>
+                {
+                    l.push_str(sep);
+                    l.push_str(&item);
+                }
+                _ => lines.push(item),
+            }
+        }
+
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let size = ((width + border).min(cols), lines.len() + border);
+
+        let resized = self.stats_canv.size() != size;
+
+        if resized {
+            self.stats_canv.resize(size);
+            // The pipes under the part of the widget which is gone must be drawn again
+            self.canv.invalidate();
+        }
+
+        self.stats_canv.pos = Point {
+            x: 0,
+            y: rows.saturating_sub(size.1) as isize,
+        };
+        self.reserve_stats_rows();
+        self.stats_drawn_at = Instant::now();
+
+        let colors = self.stats_colors();
+
+        // Redrawing the same values would only make the widget flicker
+        if !resized && lines == self.stats_lines && colors == self.stats_colors {
+            return;
+        }
+
+        self.stats_canv.fill(colors.1);
+        self.stats_canv.set_fg_color(colors.0);
+
+        if self.cfg.stats_box {
+            let (w, h) = size;
+            let inner = "─".repeat(w.saturating_sub(2));
+
+            self.stats_canv.put_str(format!("╭{inner}╮"));
+
+            for y in 1..h as isize - 1 {
+                self.stats_canv.move_to(Point { x: 0, y });
+                self.stats_canv.put_str("│");
+                self.stats_canv.move_to(Point {
+                    x: w as isize - 1,
+                    y,
+                });
+                self.stats_canv.put_str("│");
+            }
+
+            self.stats_canv.move_to(Point {
+                x: 0,
+                y: h as isize - 1,
+            });
+            self.stats_canv.put_str(format!("╰{inner}╯"));
+        }
+
+        let offset = border as isize / 2;
+
+        for (y, l) in lines.iter().enumerate() {
+            self.stats_canv.move_to(Point {
+                x: offset,
+                y: offset + y as isize,
+            });
+            self.stats_canv.put_str(l);
+        }
+
+        self.stats_lines = lines;
+        self.stats_colors = colors;
+    }
+
+    /// Retrieve the colors of the stats widget (foreground and background). With true colors and a
+    /// known background (of the theme, --bg-color or the terminal), they're derived from it: a
+    /// slightly lighter bar (darker on light backgrounds) with text of enough contrast. Otherwise
+    /// the default colors of the terminal are used, which are legible in any color scheme.
+    fn stats_colors(&self) -> (ColorAttribute, ColorAttribute) {
+        let true_color = self
+            .cfg
+            .term_color_level
+            .is_none_or(|l| l == ColorLevel::TrueColor);
+        let Some(bg) = self
+            .bg_color
+            .or(self.cfg.term_bg_color)
+            .filter(|_| true_color)
+        else {
+            return (ColorAttribute::Default, ColorAttribute::Default);
+        };
+
+        let v = if luminance(bg) > 0.5 { 0.0 } else { 1.0 };
+        let bar = blend(SrgbaTuple(v, v, v, STATS_BAR_TINT), bg);
+        let text = ensure_contrast(
+            blend(SrgbaTuple(v, v, v, STATS_TEXT_TINT), bg),
+            bar,
+            STATS_CONTRAST,
+        );
+
+        (
+            ColorAttribute::TrueColorWithDefaultFallback(text),
+            ColorAttribute::TrueColorWithDefaultFallback(bar),
+        )
+    }
+
+    /// Format the pipe/piece/layers counters and the current pipe color shown by the stats widget
+    /// as a single line.
+    fn stats_line(&self) -> String {
+        self.stats_items().join(self.stats_separator())
+    }
+
+    /// Separator between the stats items (see --stats-style).
+    fn stats_separator(&self) -> &'static str {
+        match self.cfg.stats_style {
+            StatsStyle::Compact => ", ",
+            StatsStyle::Verbose => " • ",
+        }
+    }
+
+    /// Format the pipe/piece/layers counters and the current pipe color shown by the stats widget.
+    fn stats_items(&self) -> Vec<String> {
+        let pipe = &self.sim.pipe;
+        let pipe_len = pipe.pieces_drawn + pipe.pieces_remaining;
+
+        let color = pipe.piece.color.map_or("DEFAULT".to_string(), |c| match c {
+            ColorAttribute::Default => "DEFAULT".to_string(),
+            ColorAttribute::PaletteIndex(i) => match i {
+                0 => "BLACK",
+                1 => "RED",
+                2 => "GREEN",
+                3 => "YELLOW",
+                4 => "BLUE",
+                5 => "MAGENTA",
+                6 => "CYAN",
+                7 => "WHITE",
+                8 => "BRIGHT BLACK",
+                9 => "BRIGHT RED",
+                10 => "BRIGHT GREEN",
+                11 => "BRIGHT YELLOW",
+                12 => "BRIGHT BLUE",
+                13 => "BRIGHT MAGENTA",
+                14 => "BRIGHT CYAN",
+                15 => "BRIGHT GRAY",
+                _ => unreachable!(),
+            }
+            .to_string(),
+            ColorAttribute::TrueColorWithPaletteFallback(c, _)
+            | ColorAttribute::TrueColorWithDefaultFallback(c) => c.to_rgb_string(),
+        });
+
+        // The FPS lowered by --adaptive-fps is shown next to the requested one
+        let actual_fps = (1.0 / self.frame_delay().as_secs_f64()).round() as i64;
+        let fps = if actual_fps < self.cfg.fps {
+            format!("{actual_fps}/{}", self.cfg.fps)
+        } else {
+            self.cfg.fps.to_string()
+        };
+
+        // Each value with its compact and verbose label
+        let items = [
+            (
+                "pcs. drawn",
+                "pieces drawn",
+                self.state.pieces_total.to_string(),
+            ),
+            (
+                "lpcs. drawn",
+                "layer pieces drawn",
+                self.state.layer_pieces_total.to_string(),
+            ),
+            (
+                "c. pcs. drawn",
+                "current pipe pieces",
+                pipe.pieces_drawn.to_string(),
+            ),
+            ("pps. drawn", "pipes", self.state.pipes_total.to_string()),
+            (
+                "pcs. rem",
+                "pieces remaining",
+                pipe.pieces_remaining.to_string(),
+            ),
+            ("l. drawn", "layers", self.state.layers_drawn.to_string()),
+            ("pps. len", "pipe length", pipe_len.to_string()),
+            ("c. turns", "turns", pipe.turns.to_string()),
+            (
+                "longest pp.",
+                "longest pipe",
+                self.pipe_stats.longest.max(pipe.pieces_drawn).to_string(),
+            ),
+            (
+                "avg. turns",
+                "average turns",
+                format!("{:.1}", self.pipe_stats.avg_turns()),
+            ),
+            (
+                "avg. lifetime",
+                "average lifetime",
+                format!("{:.1}s", self.pipe_stats.avg_lifetime().as_secs_f64()),
+            ),
+            ("pipe color", "pipe color", color),
+            ("fps", "fps", fps),
+        ];
+
+        items
+            .into_iter()
+            .map(|(compact, verbose, value)| match self.cfg.stats_style {
+                StatsStyle::Compact => format!("{compact}: {value}"),
+                StatsStyle::Verbose => format!("{verbose}: {value}"),
+            })
+            .collect()
+    }
+}
+
+/// Purposes of the random number generators derived from the seed apart from the one driving the
+/// simulation.
+#[derive(Copy, Clone, Debug)]
+enum SideRng {
+    Spawn,
+    Weather,
+    Bouncer,
+    Sync,
+}
+
+/// Create a random number generator for the purpose, derived from the seed. Runs with the same seed
+/// are reproducible, but drawing from it doesn't change the pipes.
+fn side_rng(seed: u64, purpose: SideRng) -> StdRng {
+    // The golden ratio spreads the seeds of the purposes far apart
+    StdRng::seed_from_u64(seed ^ (purpose as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// Create a headless `Screensaver` of specified size with the command line arguments (without
+    /// the program name).
+    fn headless(size: (usize, usize), args: &[&str]) -> Screensaver {
+        let cfg = Config::try_parse_from(["rxpipes", "--seed", "1"].iter().chain(args)).unwrap();
+
+        Screensaver::headless(size, cfg).unwrap()
+    }
+
+    #[test]
+    fn no_layers_without_depth_mode() {
+        let mut s = headless((40, 12), &["--palette", "rgb"]);
+
+        s.simulate(3000);
+
+        assert_eq!(s.state.layers_drawn, 0);
+    }
+
+    #[test]
+    fn layers_in_depth_mode() {
+        let mut s = headless(
+            (40, 12),
+            &[
+                "--palette",
+                "rgb",
+                "--depth-mode",
+                "--layer-max-drawn-pieces",
+                "50",
+            ],
+        );
+
+        s.simulate(3000);
+
+        assert!(s.state.layers_drawn > 0);
+    }
+
+    #[test]
+    fn same_seed_draws_same_pipes() {
+        for args in [&[][..], &["--mode", "maze"], &["--mode", "life-decay"]] {
+            let mut a = headless((30, 10), args);
+            let mut b = headless((30, 10), args);
+
+            a.simulate(500);
+            b.simulate(500);
+
+            assert_eq!(a.frame(), b.frame(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_screens() {
+        for size in [(1, 1), (1, 9), (9, 1)] {
+            for args in [
+                &[][..],
+                &["--mode", "maze"],
+                &["--mode", "life-decay"],
+                &["--mode", "curve", "--curve", "hilbert"],
+                &["--mode", "curve", "--curve", "lissajous"],
+                &["--mode", "curve", "--curve", "spiral"],
+            ] {
+                headless(size, args).simulate(200);
+            }
+        }
+    }
+
+    #[test]
+    fn mirror_pair_counts_both_pieces() {
+        let mut s = headless((40, 12), &["--mirror-pair"]);
+
+        s.simulate(10);
+
+        assert_eq!(s.state.pieces_total, 20);
+    }
+
+    #[test]
+    fn stop_on_full_screen_again_after_resume() {
+        let mut s = headless((40, 12), &["--on-full", "stop", "--max-drawn-pieces", "50"]);
+
+        for stop in [50, 100] {
+            s.state.pause = false;
+
+            while !s.state.pause {
+                s.simulate(1);
+            }
+
+            assert_eq!(s.state.pieces_total, stop);
+        }
+    }
+
+    #[test]
+    fn same_seed_bounces_same_path() {
+        let path = || {
+            let mut s = headless((30, 10), &["--bouncer", "DVD"]);
+
+            (0..100)
+                .map(|_| {
+                    s.simulate(1);
+                    s.bouncer.as_ref().unwrap().canvas().pos
+                })
+                .collect::<Vec<_>>()
+        };
 
-        self.stats_canv.put_str(s);
+        assert_eq!(path(), path());
     }
 }
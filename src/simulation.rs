@@ -0,0 +1,628 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::TextStyle,
+    color::{complementary, scale_brightness, ColorPalette, ColorPool},
+    config::{Config, Geometry, MirrorPair},
+    grid::Grid,
+    piece_set::{glyph, piece_idx, PieceSetMeta},
+    pipe::{gen_style, PipePiece},
+    plane_2d::{Direction, Point},
+    stencil::Stencil,
+};
+use rand::{seq::SliceRandom, Rng};
+use std::collections::VecDeque;
+use termwiz::color::ColorAttribute;
+
+/// How many times to try spawning a pipe that doesn't collide right away.
+const MAX_SPAWN_ATTEMPTS: u32 = 100;
+
+/// Probability that a pipe is steered towards the traced text or image.
+const TRACE_BIAS: f64 = 0.9;
+
+/// Occupancy of the wall cells (see --walled).
+pub const WALL_ID: u64 = u64::MAX;
+
+/// Pipe being drawn.
+#[derive(Clone, Debug, Default)]
+pub struct CurrentPipe {
+    /// Piece to be drawn next.
+    pub piece: PipePiece,
+    /// Number of pieces not drawn yet.
+    pub pieces_remaining: u64,
+    /// Number of drawn pieces.
+    pub pieces_drawn: u64,
+    /// Number of turns made so far.
+    pub turns: u64,
+    /// Number of pieces drawn since the last turn.
+    pub straight: u64,
+    /// Directions of the recently drawn pieces, the latest last (see --no-immediate-uturn).
+    pub recent_dirs: VecDeque<Direction>,
+    /// ID of the pipe, unique since the last screen clear.
+    pub id: u64,
+}
+
+/// Change of a cell made by a simulation step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellUpdate {
+    /// Position of the cell.
+    pub pos: Point,
+    /// Glyph put into the cell.
+    pub glyph: String,
+    /// Index of the piece the glyph belongs to (none for joints).
+    pub piece: Option<usize>,
+    /// Color of the glyph.
+    pub color: ColorAttribute,
+    /// Text attributes of the glyph.
+    pub style: TextStyle,
+    /// ID of the pipe which occupied the cell before.
+    pub prev_occupancy: Option<u64>,
+    /// Depth of the piece which was drawn in the cell before.
+    pub prev_depth: Option<f32>,
+}
+
+/// Core of the simulation: moves the pipes and tells which cells they cover, without drawing
+/// anything. All randomness comes from the RNG given to each step, so the same seed always yields
+/// the same pipes.
+pub struct Simulation {
+    /// Pipe being drawn.
+    pub pipe: CurrentPipe,
+    /// IDs of pipes occupying each cell.
+    pub occupancy: Grid<Option<u64>>,
+    /// Depth of the piece drawn in each cell (z-depth only).
+    pub depth_buf: Grid<Option<f32>>,
+    /// How many times each cell has been drawn since the start (or the last resize).
+    pub visits: Grid<u32>,
+    /// Colors of the pipes.
+    pub color_pool: ColorPool,
+    /// Spacing of the pieces (pipes and life-decay modes only).
+    pub spacing: PieceSetMeta,
+    /// Picture the pipes are attracted to (see --trace-text and --trace-image).
+    pub stencil: Option<Stencil>,
+    /// Cell where the pipe has run into another pipe in the last step (see --end-on-collision).
+    pub collision: Option<Point>,
+    /// Key picking the color of the pipes spawned from now on (see [`ColorPool::keyed`]).
+    pub spawn_key: Option<u64>,
+    /// Size of the screen.
+    size: (usize, usize),
+    /// Number of rows at the bottom of the screen kept free of pipes (e.g. for the stats widget).
+    reserved_rows: usize,
+    /// Region of the screen the pipes pass under without being drawn (e.g. for the output of
+    /// --wrap-cmd).
+    reserved_rect: Option<Geometry>,
+}
+
+impl Simulation {
+    /// Create a `Simulation` on a screen of specified size.
+    pub fn new(
+        size: (usize, usize),
+        color_pool: ColorPool,
+        spacing: PieceSetMeta,
+        mut stencil: Option<Stencil>,
+    ) -> Self {
+        if let Some(s) = &mut stencil {
+            s.fit(size);
+        }
+
+        Self {
+            pipe: CurrentPipe::default(),
+            occupancy: Grid::new(size),
+            depth_buf: Grid::new(size),
+            visits: Grid::new(size),
+            color_pool,
+            spacing,
+            stencil,
+            collision: None,
+            spawn_key: None,
+            size,
+            reserved_rows: 0,
+            reserved_rect: None,
+        }
+    }
+
+    /// Keep the number of rows at the bottom of the screen free: pipes neither spawn nor draw
+    /// there. The pipes already drawn there are kept.
+    pub fn reserve_rows(&mut self, rows: usize) {
+        self.reserved_rows = rows;
+    }
+
+    /// Keep the region of the screen free: pipes pass under it without being drawn.
+    pub fn reserve_rect(&mut self, rect: Option<Geometry>) {
+        self.reserved_rect = rect;
+    }
+
+    /// Check whether the cell is in the reserved region (see [`Self::reserve_rect`]).
+    fn is_reserved(&self, p: Point) -> bool {
+        self.reserved_rect.is_some_and(|r| {
+            (r.pos.x..r.pos.x + r.size.0 as isize).contains(&p.x)
+                && (r.pos.y..r.pos.y + r.size.1 as isize).contains(&p.y)
+        })
+    }
+
+    /// Retrieve the size of the area the pipes are drawn in: the screen without the reserved rows.
+    pub fn area(&self) -> (usize, usize) {
+        (
+            self.size.0,
+            self.size.1.saturating_sub(self.reserved_rows).max(1),
+        )
+    }
+
+    /// Retrieve the fraction of the area (0.0 - 1.0) covered by the pipes since the last clear or
+    /// new layer.
+    pub fn coverage(&self) -> f64 {
+        let (w, h) = self.area();
+        let covered = (0..h)
+            .flat_map(|y| {
+                (0..w).map(move |x| Point {
+                    x: x as isize,
+                    y: y as isize,
+                })
+            })
+            .filter(|p| matches!(self.occupancy.get(*p), Some(Some(id)) if *id != WALL_ID))
+            .count();
+
+        covered as f64 / (w * h).max(1) as f64
+    }
+
+    /// Resize the screen. All pipes are forgotten.
+    pub fn resize(&mut self, size: (usize, usize)) {
+        self.size = size;
+        self.occupancy.resize(size);
+        self.depth_buf.resize(size);
+        self.visits.resize(size);
+
+        if let Some(s) = &mut self.stencil {
+            s.fit(size);
+        }
+    }
+
+    /// Forget all pipes and start a new one with the next step.
+    pub fn clear(&mut self) {
+        self.new_layer();
+        self.pipe.id = 0;
+    }
+
+    /// Start a new layer on top of the drawn pipes: they no longer count as obstacles.
+    pub fn new_layer(&mut self) {
+        self.pipe.pieces_drawn = 0;
+        self.pipe.turns = 0;
+        self.pipe.pieces_remaining = 0;
+        self.occupancy.clear();
+        self.depth_buf.clear();
+    }
+
+    /// Advance the simulation by one piece and retrieve the cells it covers.
+    pub fn step(&mut self, cfg: &Config, rng: &mut impl Rng) -> Vec<CellUpdate> {
+        self.advance(cfg, rng);
+
+        self.draw(cfg)
+    }
+
+    /// Start a new pipe with random color and text attributes.
+    pub fn start_pipe(&mut self, cfg: &Config, rng: &mut impl Rng) {
+        self.pipe.id += 1;
+        self.pipe.pieces_drawn = 0;
+        self.pipe.turns = 0;
+        self.pipe.straight = 0;
+        self.pipe.recent_dirs.clear();
+        self.pipe.piece = PipePiece::gen(&mut self.color_pool, rng);
+        self.pipe.piece.style = gen_style(&cfg.attr, rng);
+
+        if let Some(key) = self.spawn_key {
+            self.pipe.piece.color = self.color_pool.keyed(key);
+        }
+    }
+
+    /// Move the pipe to its next piece, spawning a new pipe when the current one ends.
+    pub fn advance(&mut self, cfg: &Config, rng: &mut impl Rng) {
+        let area = self.area();
+        let size = (area.0 as isize, area.1 as isize);
+        let mut attempts = 0;
+
+        self.collision = None;
+
+        loop {
+            let spawned = self.pipe.pieces_remaining == 0;
+
+            if spawned {
+                // The previous pipe is remembered until the screen is cleared (see --continuous)
+                let prev = (self.pipe.id > 0).then_some(self.pipe.piece);
+
+                self.pipe.pieces_remaining =
+                    rng.gen_range(cfg.min_pipe_length..=cfg.max_pipe_length);
+                self.start_pipe(cfg, rng);
+
+                let piece = &mut self.pipe.piece;
+
+                piece.pos = Point {
+                    x: rng.gen_range(0..area.0) as isize,
+                    y: rng.gen_range(0..area.1) as isize,
+                };
+
+                // Start most pipes right on the traced stencil
+                if let Some(p) = self
+                    .stencil
+                    .as_ref()
+                    .filter(|_| rng.gen_bool(TRACE_BIAS))
+                    .and_then(|s| s.random_cell(rng))
+                {
+                    piece.pos = p;
+                }
+
+                if let Some(quirks) = &cfg.pipes_sh {
+                    if !quirks.random_start {
+                        piece.pos = Point {
+                            x: size.0 / 2,
+                            y: size.1 / 2,
+                        };
+                    }
+
+                    if let Some(c) = quirks.fixed_color(rng) {
+                        piece.color = Some(c);
+                    }
+                }
+
+                if cfg.walled {
+                    piece.pos.x = piece.pos.x.clamp(1, (size.0 - 2).max(1));
+                    piece.pos.y = piece.pos.y.clamp(1, (size.1 - 2).max(1));
+
+                    let (pos, dir) = (piece.pos, piece.dir);
+
+                    if self.hits_wall(pos, dir) {
+                        let free: Vec<Direction> = Direction::ALL
+                            .into_iter()
+                            .filter(|d| !self.hits_wall(pos, *d))
+                            .collect();
+
+                        if let Some(dir) = free.choose(rng) {
+                            self.pipe.piece.dir = *dir;
+                            self.pipe.piece.prev_dir = *dir;
+                        }
+                    }
+                }
+
+                // Continue from the end of the previous pipe, unless it has run into an obstacle
+                if let Some(prev) = prev.filter(|_| cfg.continuous && attempts == 0) {
+                    let piece = &mut self.pipe.piece;
+
+                    piece.pos = prev.pos;
+                    piece.dir = prev.dir;
+                    piece.prev_dir = prev.dir;
+                }
+            }
+
+            let piece = &mut self.pipe.piece;
+
+            for _ in 0..self.spacing.steps(piece.dir) {
+                piece.pos.advance(piece.dir);
+            }
+
+            let unwrapped = piece.pos;
+            piece.pos.wrap(size.0, size.1);
+
+            // Like pipes.sh, change the color of the pipe when it crosses an edge
+            if let Some(quirks) = cfg.pipes_sh.as_ref().filter(|q| !q.keep_color) {
+                if piece.pos != unwrapped {
+                    piece.color = quirks.fixed_color(rng).or_else(|| self.color_pool.gen(rng));
+                }
+            }
+
+            attempts += 1;
+
+            let blocked =
+                cfg.end_on_collision && matches!(self.occupancy.get(piece.pos), Some(Some(_)));
+
+            // If the screen is too crowded, give up and let the pipe overdraw others
+            if !blocked || attempts >= MAX_SPAWN_ATTEMPTS {
+                break;
+            }
+
+            // Pipes spawned on an occupied cell and walls don't count
+            if !spawned && self.occupancy.get(piece.pos) != Some(&Some(WALL_ID)) {
+                self.collision.get_or_insert(piece.pos);
+            }
+
+            // The pipe has run into an obstacle, so end it and spawn a new one
+            self.pipe.pieces_remaining = 0;
+        }
+
+        let occupancy = &self.occupancy;
+        let piece = &mut self.pipe.piece;
+
+        piece.prev_dir = piece.dir;
+
+        let turning_prob = match cfg.turn_momentum {
+            Some(m) => (cfg.turning_prob * self.pipe.straight as f64 / m as f64).min(1.0),
+            None => cfg.turning_prob,
+        };
+
+        // Try to turn the pipe in other direction
+        if rng.gen_bool(turning_prob) {
+            let [a, b] = piece.dir.perpendicular();
+
+            piece.dir = if rng.gen_bool(0.5) { a } else { b };
+        }
+
+        // Don't fold the pipe back onto itself
+        if piece.dir != piece.prev_dir && self.pipe.recent_dirs.contains(&piece.dir.opposite()) {
+            piece.dir = piece.prev_dir;
+        }
+
+        // Follow the traced stencil, preferring the parts which haven't been filled yet
+        if let Some(stencil) = self.stencil.as_ref().filter(|_| rng.gen_bool(TRACE_BIAS)) {
+            let score = |dir: Direction| {
+                let mut p = piece.pos;
+                p.advance(dir);
+                p.wrap(size.0, size.1);
+
+                let free = occupancy.get(p) == Some(&None);
+
+                stencil.weight(p) * if free { 2.0 } else { 1.0 }
+            };
+
+            let [a, b] = piece.prev_dir.perpendicular();
+            let candidates = [piece.prev_dir, a, b];
+            let best = candidates.iter().map(|d| score(*d)).fold(0.0, f32::max);
+
+            if best > 0.0 && score(piece.dir) < best {
+                let best_dirs: Vec<Direction> = candidates
+                    .into_iter()
+                    .filter(|d| score(*d) >= best)
+                    .collect();
+
+                if let Some(dir) = best_dirs.choose(rng) {
+                    piece.dir = *dir;
+                }
+            }
+        }
+
+        // Steer away from cells visited by the pipe itself
+        if cfg.self_avoid {
+            let pipe_id = self.pipe.id;
+            let is_free = |dir: Direction| {
+                let mut p = piece.pos;
+                p.advance(dir);
+                p.wrap(size.0, size.1);
+
+                occupancy.get(p) != Some(&Some(pipe_id))
+            };
+
+            if !is_free(piece.dir) {
+                let free: Vec<Direction> = piece
+                    .dir
+                    .perpendicular()
+                    .into_iter()
+                    .filter(|d| is_free(*d))
+                    .collect();
+
+                if let Some(dir) = free.choose(rng) {
+                    piece.dir = *dir;
+                } else {
+                    // The pipe is boxed in, so end it after this piece
+                    self.pipe.pieces_remaining = 1;
+                }
+            }
+        }
+
+        let piece = self.pipe.piece;
+
+        // Crawl along the walls instead of running into them
+        if cfg.walled && self.hits_wall(piece.pos, piece.dir) {
+            let [a, b] = piece.prev_dir.perpendicular();
+            let free: Vec<Direction> = [piece.prev_dir, a, b]
+                .into_iter()
+                .filter(|d| !self.hits_wall(piece.pos, *d))
+                .collect();
+
+            if let Some(dir) = free.choose(rng) {
+                self.pipe.piece.dir = *dir;
+            } else {
+                // The pipe is stuck in a corner, so end it after this piece
+                self.pipe.pieces_remaining = 1;
+            }
+        }
+    }
+
+    /// Check whether moving from the position in the direction runs into a wall.
+    fn hits_wall(&self, pos: Point, dir: Direction) -> bool {
+        let mut p = pos;
+
+        (0..self.spacing.steps(dir)).any(|_| {
+            p.advance(dir);
+
+            matches!(self.occupancy.get(p), None | Some(Some(WALL_ID)))
+        })
+    }
+
+    /// Retrieve the cells covered by the current piece: the columns skipped by a wide horizontal
+    /// step filled with the joint glyph (see [`PieceSetMeta`]), followed by the piece itself.
+    pub fn piece_cells(&self) -> Vec<Point> {
+        self.cells_of(&self.pipe.piece)
+    }
+
+    /// Retrieve the cells covered by the piece (see [`Self::piece_cells`]).
+    fn cells_of(&self, piece: &PipePiece) -> Vec<Point> {
+        let area = self.area();
+        let mut cells = vec![];
+
+        if self.spacing.joint.is_some() && piece.prev_dir.is_horizontal() {
+            for k in (1..self.spacing.advance as isize).rev() {
+                let mut p = piece.pos;
+                p.x += if piece.prev_dir == Direction::Right {
+                    -k
+                } else {
+                    k
+                };
+                p.wrap(area.0 as isize, area.1 as isize);
+
+                cells.push(p);
+            }
+        }
+
+        cells.push(piece.pos);
+
+        cells
+    }
+
+    /// Put the current piece on the screen: retrieve the cells it covers and mark them as
+    /// occupied.
+    pub fn draw(&mut self, cfg: &Config) -> Vec<CellUpdate> {
+        let piece_cells = self.piece_cells();
+        let piece = &mut self.pipe.piece;
+
+        if cfg.gradient {
+            piece.advance_gradient(cfg.gradient_step);
+        }
+
+        let color = match piece.color {
+            Some(color) => {
+                // Reveal the colors of the traced image
+                let color = match self.stencil.as_ref().and_then(|s| s.color(piece.pos)) {
+                    Some(c) if cfg.palette == ColorPalette::Rgb => {
+                        ColorAttribute::TrueColorWithDefaultFallback(c)
+                    }
+                    _ => color,
+                };
+
+                if cfg.z_depth {
+                    let brightness = 1.0 - piece.depth * (1.0 - cfg.z_depth_min_brightness);
+
+                    scale_brightness(color, brightness)
+                } else {
+                    color
+                }
+            }
+            None => ColorAttribute::Default,
+        };
+
+        let piece = *piece;
+        let mut updates = vec![];
+
+        self.put_piece(cfg, &piece, piece_cells, color, &mut updates);
+
+        if let Some(m) = cfg.mirror_pair {
+            let twin = self.twin(&piece, m);
+            let cells = self.cells_of(&twin);
+
+            self.put_piece(cfg, &twin, cells, complementary(color), &mut updates);
+        }
+
+        if piece.dir != piece.prev_dir {
+            self.pipe.turns += 1;
+            self.pipe.straight = 0;
+        } else {
+            self.pipe.straight += 1;
+        }
+
+        if let Some(n) = cfg.no_immediate_uturn {
+            self.pipe.recent_dirs.push_back(piece.dir);
+
+            if self.pipe.recent_dirs.len() > n as usize {
+                self.pipe.recent_dirs.pop_front();
+            }
+        }
+
+        self.pipe.pieces_drawn += 1;
+        self.pipe.pieces_remaining = self.pipe.pieces_remaining.saturating_sub(1);
+
+        updates
+    }
+
+    /// Retrieve the twin of the piece (see --mirror-pair).
+    fn twin(&self, piece: &PipePiece, m: MirrorPair) -> PipePiece {
+        let (w, h) = (self.area().0 as isize, self.area().1 as isize);
+        let mut twin = *piece;
+
+        match m {
+            MirrorPair::Point => {
+                twin.pos = Point {
+                    x: w - 1 - piece.pos.x,
+                    y: h - 1 - piece.pos.y,
+                };
+                twin.prev_dir = piece.prev_dir.opposite();
+                twin.dir = piece.dir.opposite();
+            }
+            MirrorPair::Offset => {
+                twin.pos = Point {
+                    x: piece.pos.x + w / 2,
+                    y: piece.pos.y + h / 2,
+                };
+                twin.pos.wrap(w, h);
+            }
+        }
+
+        twin
+    }
+
+    /// Put the piece covering the cells on the screen and mark them as occupied by the current
+    /// pipe.
+    fn put_piece(
+        &mut self,
+        cfg: &Config,
+        piece: &PipePiece,
+        cells: Vec<Point>,
+        color: ColorAttribute,
+        updates: &mut Vec<CellUpdate>,
+    ) {
+        // Deeper pipes must not overdraw the shallower ones
+        let hidden = cfg.z_depth
+            && matches!(self.depth_buf.get(piece.pos), Some(Some(d)) if *d < piece.depth);
+
+        if hidden {
+            return;
+        }
+
+        let piece_idx = piece_idx(piece.prev_dir, piece.dir);
+        let last = cells.len() - 1;
+
+        for (i, pos) in cells.into_iter().enumerate() {
+            // The twin of a pipe may run into the walls
+            if self.occupancy.get(pos) == Some(&Some(WALL_ID)) || self.is_reserved(pos) {
+                continue;
+            }
+
+            let (glyph, glyph_piece, color) = match &self.spacing.joint {
+                Some(joint) if i < last => (
+                    joint.clone(),
+                    None,
+                    cfg.joint_color
+                        .map_or(color, ColorAttribute::TrueColorWithDefaultFallback),
+                ),
+                _ => (
+                    glyph(cfg, piece_idx),
+                    Some(piece_idx),
+                    match cfg.corner_tint {
+                        Some(t) if piece.dir != piece.prev_dir => scale_brightness(color, t),
+                        _ => color,
+                    },
+                ),
+            };
+
+            updates.push(CellUpdate {
+                pos,
+                glyph,
+                piece: glyph_piece,
+                color,
+                style: piece.style,
+                prev_occupancy: self.occupancy.get(pos).copied().flatten(),
+                prev_depth: self.depth_buf.get(pos).copied().flatten(),
+            });
+
+            if let Some(o) = self.occupancy.get_mut(pos) {
+                *o = Some(self.pipe.id);
+            }
+
+            if let Some(v) = self.visits.get_mut(pos) {
+                *v = v.saturating_add(1);
+            }
+
+            if cfg.z_depth {
+                if let Some(d) = self.depth_buf.get_mut(pos) {
+                    *d = Some(piece.depth);
+                }
+            }
+        }
+    }
+}
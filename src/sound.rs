@@ -0,0 +1,85 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    config::SoundEvent,
+    events::{SimEvent, TimedEvent},
+};
+use clap::ValueEnum;
+use std::{
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+/// Shortest time between two runs of the sound command. Bursts of events (e.g. many collisions on
+/// a crowded screen) would otherwise start a cacophony of sounds.
+const SOUND_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs a command on the chosen simulation events, e.g. to play a chime (see --sound-cmd).
+#[derive(Debug)]
+pub struct SoundHooks {
+    /// Command to run via `sh -c`.
+    cmd: String,
+    /// Events running the command.
+    events: Vec<SoundEvent>,
+    /// Time of the event which has run the command last.
+    played_at: Option<Instant>,
+    /// Runs of the command which haven't exited yet.
+    running: Vec<Child>,
+}
+
+impl SoundHooks {
+    /// Create `SoundHooks` running the command on the events.
+    pub fn new(cmd: String, events: Vec<SoundEvent>) -> Self {
+        Self {
+            cmd,
+            events,
+            played_at: None,
+            running: vec![],
+        }
+    }
+
+    /// Run the command in the background if the simulation event is one of the chosen ones and
+    /// the command hasn't been run too recently.
+    pub fn handle(&mut self, e: &TimedEvent) {
+        // Reap the finished runs, so they don't linger as zombies
+        self.running
+            .retain_mut(|c| matches!(c.try_wait(), Ok(None)));
+
+        let event = match e.event {
+            SimEvent::Cleared => SoundEvent::Clear,
+            SimEvent::LayerDarkened => SoundEvent::Layer,
+            SimEvent::Collided(_) => SoundEvent::Collision,
+            _ => return,
+        };
+
+        if !self.events.contains(&event)
+            || self
+                .played_at
+                .is_some_and(|t| e.at.duration_since(t) < SOUND_MIN_INTERVAL)
+        {
+            return;
+        }
+
+        self.played_at = Some(e.at);
+
+        let name = event
+            .to_possible_value()
+            .map_or_else(String::new, |v| v.get_name().to_string());
+
+        // The output would garble the screen. A command which can't be started has no way to
+        // report it without disturbing the animation, so it's just skipped
+        if let Ok(c) = Command::new("sh")
+            .arg("-c")
+            .arg(&self.cmd)
+            .env("RXPIPES_EVENT", name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            self.running.push(c);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{canvas::Canvas, color::scale_brightness, plane_2d::Point};
+use std::time::{Duration, Instant};
+use termwiz::{
+    cell::{Cell, CellAttributes},
+    color::ColorAttribute,
+};
+
+/// How long each frame of a spark is shown.
+const SPARK_FRAME_INTERVAL: Duration = Duration::from_millis(60);
+
+/// Brightness of the spark in each frame, relative to the color of the hit pipe. The first frame
+/// is the flash at the impact cell, the next ones are the rings flying apart.
+const SPARK_BRIGHTNESS: [f32; 4] = [2.0, 1.6, 1.0, 0.6];
+
+/// Directions the sparks fly in, with the glyphs drawn along them.
+const SPARK_RAYS: [(isize, isize, &str); 8] = [
+    (1, 0, "-"),
+    (-1, 0, "-"),
+    (0, 1, "|"),
+    (0, -1, "|"),
+    (1, 1, "\\"),
+    (-1, -1, "\\"),
+    (1, -1, "/"),
+    (-1, 1, "/"),
+];
+
+/// Spark burst at the cell where a pipe has run into another one.
+#[derive(Debug)]
+struct Spark {
+    pos: Point,
+    /// Color of the hit pipe.
+    color: ColorAttribute,
+    /// Time of the collision.
+    at: Instant,
+}
+
+/// Brief radial spark animations at the collisions of the pipes (see --end-on-collision). Like
+/// the flow pulses, the sparks are put on the canvas only while it's copied to the terminal.
+#[derive(Debug, Default)]
+pub struct Sparks {
+    sparks: Vec<Spark>,
+    /// Cells covered by the sparks, with their original contents.
+    covered: Vec<(Point, Cell)>,
+}
+
+impl Sparks {
+    /// Start a spark burst at the cell, in the color of the pipe drawn in it.
+    pub fn ignite(&mut self, canv: &mut Canvas, pos: Point) {
+        let color = canv
+            .cell(pos)
+            .map_or(ColorAttribute::Default, |c| c.attrs().foreground());
+
+        self.sparks.push(Spark {
+            pos,
+            color,
+            at: Instant::now(),
+        });
+    }
+
+    /// Draw the current frame of each spark burst, dropping the finished ones.
+    pub fn apply(&mut self, canv: &mut Canvas) {
+        self.sparks
+            .retain(|s| s.at.elapsed() < SPARK_FRAME_INTERVAL * SPARK_BRIGHTNESS.len() as u32);
+
+        for s in &self.sparks {
+            let frame = (s.at.elapsed().as_millis() / SPARK_FRAME_INTERVAL.as_millis()) as usize;
+            let Some(&brightness) = SPARK_BRIGHTNESS.get(frame) else {
+                continue;
+            };
+
+            let mut attrs = CellAttributes::default();
+            attrs.set_foreground(scale_brightness(s.color, brightness));
+
+            let glyphs: Vec<(Point, &str)> = if frame == 0 {
+                vec![(s.pos, "*")]
+            } else {
+                // Terminal cells are about twice as tall as wide
+                SPARK_RAYS
+                    .iter()
+                    .map(|&(dx, dy, glyph)| {
+                        let r = frame as isize;
+
+                        (
+                            Point {
+                                x: s.pos.x + dx * r * 2,
+                                y: s.pos.y + dy * r,
+                            },
+                            glyph,
+                        )
+                    })
+                    .collect()
+            };
+
+            for (p, glyph) in glyphs {
+                let Some(cell) = canv.cell(p) else {
+                    continue;
+                };
+
+                canv.put_cell(p, &Cell::new_grapheme(glyph, attrs.clone(), None));
+                self.covered.push((p, cell));
+            }
+        }
+    }
+
+    /// Put back the original contents of the cells covered by the sparks.
+    pub fn restore(&mut self, canv: &mut Canvas) {
+        // Sparks may overlap, so the cells are restored in reverse
+        for (p, cell) in self.covered.drain(..).rev() {
+            canv.put_cell(p, &cell);
+        }
+    }
+
+    /// Drop all spark bursts, e.g. when the screen is cleared.
+    pub fn clear(&mut self) {
+        self.sparks.clear();
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{color::luminance, grid::Grid, plane_2d::Point};
+use eyre::{bail, eyre, Result, WrapErr};
+use rand::{seq::SliceRandom, Rng};
+use std::{fs, path::Path};
+use termwiz::color::SrgbaTuple;
+
+/// Width of a glyph of the large font.
+const GLYPH_WIDTH: usize = 5;
+
+/// Height of a glyph of the large font.
+const GLYPH_HEIGHT: usize = 5;
+
+/// Portion of the screen the stencil occupies in the constraining dimension.
+const FILL: f64 = 0.8;
+
+/// Large font used for tracing text. Lowercase letters are drawn as uppercase, unknown characters as
+/// a question mark.
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 42] = [
+    ('A', [" ### ", "#   #", "#####", "#   #", "#   #"]),
+    ('B', ["#### ", "#   #", "#### ", "#   #", "#### "]),
+    ('C', [" ####", "#    ", "#    ", "#    ", " ####"]),
+    ('D', ["#### ", "#   #", "#   #", "#   #", "#### "]),
+    ('E', ["#####", "#    ", "#### ", "#    ", "#####"]),
+    ('F', ["#####", "#    ", "#### ", "#    ", "#    "]),
+    ('G', [" ####", "#    ", "#  ##", "#   #", " ####"]),
+    ('H', ["#   #", "#   #", "#####", "#   #", "#   #"]),
+    ('I', ["#####", "  #  ", "  #  ", "  #  ", "#####"]),
+    ('J', ["#####", "   # ", "   # ", "#  # ", " ##  "]),
+    ('K', ["#   #", "#  # ", "###  ", "#  # ", "#   #"]),
+    ('L', ["#    ", "#    ", "#    ", "#    ", "#####"]),
+    ('M', ["#   #", "## ##", "# # #", "#   #", "#   #"]),
+    ('N', ["#   #", "##  #", "# # #", "#  ##", "#   #"]),
+    ('O', [" ### ", "#   #", "#   #", "#   #", " ### "]),
+    ('P', ["#### ", "#   #", "#### ", "#    ", "#    "]),
+    ('Q', [" ### ", "#   #", "# # #", "#  # ", " ## #"]),
+    ('R', ["#### ", "#   #", "#### ", "#  # ", "#   #"]),
+    ('S', [" ####", "#    ", " ### ", "    #", "#### "]),
+    ('T', ["#####", "  #  ", "  #  ", "  #  ", "  #  "]),
+    ('U', ["#   #", "#   #", "#   #", "#   #", " ### "]),
+    ('V', ["#   #", "#   #", "#   #", " # # ", "  #  "]),
+    ('W', ["#   #", "#   #", "# # #", "## ##", "#   #"]),
+    ('X', ["#   #", " # # ", "  #  ", " # # ", "#   #"]),
+    ('Y', ["#   #", " # # ", "  #  ", "  #  ", "  #  "]),
+    ('Z', ["#####", "   # ", "  #  ", " #   ", "#####"]),
+    ('0', [" ### ", "#  ##", "# # #", "##  #", " ### "]),
+    ('1', ["  #  ", " ##  ", "  #  ", "  #  ", " ### "]),
+    ('2', [" ### ", "#   #", "  ## ", " #   ", "#####"]),
+    ('3', ["#### ", "    #", " ### ", "    #", "#### "]),
+    ('4', ["#   #", "#   #", "#####", "    #", "    #"]),
+    ('5', ["#####", "#    ", "#### ", "    #", "#### "]),
+    ('6', [" ### ", "#    ", "#### ", "#   #", " ### "]),
+    ('7', ["#####", "    #", "   # ", "  #  ", "  #  "]),
+    ('8', [" ### ", "#   #", " ### ", "#   #", " ### "]),
+    ('9', [" ### ", "#   #", " ####", "    #", " ### "]),
+    ('!', ["  #  ", "  #  ", "  #  ", "     ", "  #  "]),
+    ('?', [" ### ", "#   #", "  ## ", "     ", "  #  "]),
+    ('.', ["     ", "     ", "     ", "     ", "  #  "]),
+    ('-', ["     ", "     ", "#####", "     ", "     "]),
+    ('+', ["     ", "  #  ", " ### ", "  #  ", "     "]),
+    (' ', ["     ", "     ", "     ", "     ", "     "]),
+];
+
+/// Picture which pipes are attracted to, scaled to fit the screen.
+#[derive(Clone, Debug)]
+pub struct Stencil {
+    /// Source picture: intensities (0.0 - 1.0) stored row by row.
+    pixels: Vec<f32>,
+    /// Size of the source picture.
+    pixels_size: (usize, usize),
+    /// Colors of the source picture, if it has any.
+    colors: Option<Vec<SrgbaTuple>>,
+    /// Intensities of the screen cells.
+    weights: Grid<f32>,
+    /// Colors of the screen cells.
+    cell_colors: Grid<Option<SrgbaTuple>>,
+    /// Screen cells covered by the picture.
+    cells: Vec<Point>,
+}
+
+impl Stencil {
+    /// Create a `Stencil` with the text rendered in a large font.
+    pub fn from_text(text: &str) -> Self {
+        let glyphs: Vec<&[&str; GLYPH_HEIGHT]> = text
+            .chars()
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+
+                FONT.iter()
+                    .find(|(f, _)| *f == c)
+                    .or_else(|| FONT.iter().find(|(f, _)| *f == '?'))
+                    .map(|(_, g)| g)
+                    .unwrap_or(&FONT[0].1)
+            })
+            .collect();
+
+        // Glyphs are separated by a blank column
+        let width = (glyphs.len() * (GLYPH_WIDTH + 1)).saturating_sub(1).max(1);
+        let mut pixels = vec![0.0; width * GLYPH_HEIGHT];
+
+        for (i, glyph) in glyphs.iter().enumerate() {
+            for (y, row) in glyph.iter().enumerate() {
+                for (x, _) in row.bytes().enumerate().filter(|(_, b)| *b == b'#') {
+                    pixels[y * width + i * (GLYPH_WIDTH + 1) + x] = 1.0;
+                }
+            }
+        }
+
+        Self {
+            pixels,
+            pixels_size: (width, GLYPH_HEIGHT),
+            colors: None,
+            weights: Grid::new((0, 0)),
+            cell_colors: Grid::new((0, 0)),
+            cells: vec![],
+        }
+    }
+
+    /// Create a `Stencil` from the luminance of an image in the Netpbm format (PGM or PPM, both
+    /// plain and binary). Bright parts of the image attract the pipes.
+    pub fn from_image(path: &Path) -> Result<Self> {
+        let data = fs::read(path).wrap_err_with(|| format!("cannot read {}", path.display()))?;
+        let (size, colors) = parse_netpbm(&data)
+            .wrap_err_with(|| format!("cannot load the image {}", path.display()))?;
+
+        Ok(Self {
+            pixels: colors.iter().map(|c| luminance(*c)).collect(),
+            pixels_size: size,
+            colors: Some(colors),
+            weights: Grid::new((0, 0)),
+            cell_colors: Grid::new((0, 0)),
+            cells: vec![],
+        })
+    }
+
+    /// Scale the picture to fit the center of the screen of specified size.
+    pub fn fit(&mut self, scr_size: (usize, usize)) {
+        let (w, h) = (scr_size.0 as f64, scr_size.1 as f64);
+        let (pw, ph) = (self.pixels_size.0 as f64, self.pixels_size.1 as f64);
+
+        // Terminal cells are about twice as tall as they are wide
+        let rows_per_pixel = (w * FILL / (pw * 2.)).min(h * FILL / ph);
+        let cols_per_pixel = rows_per_pixel * 2.;
+
+        let origin = (
+            (w - pw * cols_per_pixel) / 2.,
+            (h - ph * rows_per_pixel) / 2.,
+        );
+
+        self.weights.resize(scr_size);
+        self.cell_colors.resize(scr_size);
+        self.cells.clear();
+
+        for y in 0..scr_size.1 {
+            for x in 0..scr_size.0 {
+                let px = ((x as f64 + 0.5 - origin.0) / cols_per_pixel).floor();
+                let py = ((y as f64 + 0.5 - origin.1) / rows_per_pixel).floor();
+
+                if px < 0. || py < 0. || px >= pw || py >= ph {
+                    continue;
+                }
+
+                let i = py as usize * self.pixels_size.0 + px as usize;
+                let weight = self.pixels[i];
+                let p = Point {
+                    x: x as isize,
+                    y: y as isize,
+                };
+
+                if let Some(c) = self.weights.get_mut(p) {
+                    *c = weight;
+                }
+
+                if let (Some(c), Some(colors)) = (self.cell_colors.get_mut(p), &self.colors) {
+                    *c = Some(colors[i]);
+                }
+
+                if weight >= 0.5 {
+                    self.cells.push(p);
+                }
+            }
+        }
+    }
+
+    /// Retrieve the intensity of the picture at the point (0.0 outside of it).
+    pub fn weight(&self, p: Point) -> f32 {
+        self.weights.get(p).copied().unwrap_or(0.0)
+    }
+
+    /// Retrieve the color of the picture at the point, if the picture has colors.
+    pub fn color(&self, p: Point) -> Option<SrgbaTuple> {
+        self.cell_colors.get(p).copied().flatten()
+    }
+
+    /// Pick a random cell covered by the picture.
+    pub fn random_cell(&self, rng: &mut impl Rng) -> Option<Point> {
+        self.cells.choose(rng).copied()
+    }
+}
+
+/// Parse a Netpbm image (P2, P3, P5 or P6). Returns the size and colors of the pixels row by row.
+fn parse_netpbm(data: &[u8]) -> Result<((usize, usize), Vec<SrgbaTuple>)> {
+    let mut pos = 0;
+
+    // Read the next whitespace separated token of the header, skipping comments
+    let mut token = || -> Result<&[u8]> {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => {
+                    while data.get(pos).is_some_and(|b| *b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => pos += 1,
+                Some(_) => break,
+                None => bail!("unexpected end of file"),
+            }
+        }
+
+        let start = pos;
+
+        while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+
+        Ok(&data[start..pos])
+    };
+
+    let magic = token()?.to_vec();
+    let mut number = || -> Result<usize> {
+        std::str::from_utf8(token()?)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| eyre!("invalid number in the header"))
+    };
+
+    let (width, height, maxval) = (number()?, number()?, number()?);
+
+    if width == 0 || height == 0 || maxval == 0 || maxval > u16::MAX as usize {
+        bail!("invalid image dimensions or maximum value");
+    }
+
+    let (channels, binary) = match magic.as_slice() {
+        b"P2" => (1, false),
+        b"P3" => (3, false),
+        b"P5" => (1, true),
+        b"P6" => (3, true),
+        _ => bail!("unsupported format (only PGM and PPM images are supported)"),
+    };
+
+    let count = width * height * channels;
+    let samples: Vec<usize> = if binary {
+        // A single whitespace separates the header from the raster
+        let raster = data.get(pos + 1..).unwrap_or_default();
+        let bytes = if maxval < 256 { 1 } else { 2 };
+
+        if raster.len() < count * bytes {
+            bail!("the image data is truncated");
+        }
+
+        raster
+            .chunks_exact(bytes)
+            .take(count)
+            .map(|b| b.iter().fold(0, |acc, b| acc << 8 | *b as usize))
+            .collect()
+    } else {
+        (0..count).map(|_| number()).collect::<Result<_>>()?
+    };
+
+    let colors = samples
+        .chunks_exact(channels)
+        .map(|s| {
+            let c = |v: usize| v.min(maxval) as f32 / maxval as f32;
+
+            if let [r, g, b] = s {
+                SrgbaTuple(c(*r), c(*g), c(*b), 1.0)
+            } else {
+                SrgbaTuple(c(s[0]), c(s[0]), c(s[0]), 1.0)
+            }
+        })
+        .collect();
+
+    Ok(((width, height), colors))
+}
@@ -0,0 +1,99 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::config::env_var;
+use eyre::{Result, WrapErr};
+use std::{
+    env,
+    io::{self, Write},
+    process::{self, Command, ExitStatus},
+};
+
+/// Option enabling the supervisor, which mustn't be passed to the renderer.
+const SUPERVISED_OPTION: &str = "--supervised";
+
+/// Escape sequences undoing what the renderer may have set up: reset the text attributes, show
+/// the cursor, disable mouse reporting and bracketed paste and leave the alternate screen.
+const RESET_SEQUENCES: &str =
+    "\x1b[0m\x1b[?25h\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?2004l\x1b[?1049l";
+
+/// Run the renderer in a child process with the same options and restore the terminal if it dies
+/// abruptly (e.g. killed by the OOM killer or SIGKILL), which the panic hook can't cover. Exits
+/// with the exit code of the renderer.
+pub fn run() -> Result<()> {
+    let saved = TermMode::save();
+
+    let status = Command::new(env::current_exe().wrap_err("cannot find the rxpipes executable")?)
+        .args(env::args_os().skip(1).filter(|a| a != SUPERVISED_OPTION))
+        .env_remove(env_var(SUPERVISED_OPTION))
+        .status()
+        .wrap_err("failed to launch the renderer")?;
+
+    if let Some(sig) = killed_by(status) {
+        if let Some(m) = saved {
+            m.restore();
+        }
+
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(RESET_SEQUENCES.as_bytes());
+        let _ = stdout.flush();
+
+        eprintln!(
+            "rxpipes: the renderer was killed by signal {sig}, the terminal has been restored"
+        );
+
+        process::exit(128 + sig);
+    }
+
+    process::exit(status.code().unwrap_or(1));
+}
+
+/// Retrieve the signal which has killed the process.
+#[cfg(unix)]
+fn killed_by(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal()
+}
+
+/// Retrieve the signal which has killed the process (there are no signals on this platform).
+#[cfg(not(unix))]
+fn killed_by(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Mode of the terminal (raw or cooked, echo etc.) before the renderer has changed it.
+#[cfg(unix)]
+struct TermMode(libc::termios);
+
+#[cfg(unix)]
+impl TermMode {
+    /// Save the mode of the terminal on the standard input, if it's a terminal.
+    fn save() -> Option<Self> {
+        // SAFETY: `libc::termios` is a plain C struct, for which all-zero bytes is a valid value.
+        let mut t: libc::termios = unsafe { std::mem::zeroed() };
+
+        // SAFETY: `t` is a valid pointer to a `libc::termios`.
+        (unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut t) } == 0).then_some(Self(t))
+    }
+
+    /// Put the terminal back into the saved mode.
+    fn restore(&self) {
+        // SAFETY: the pointer is valid for the duration of the call.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+/// Mode of the terminal (can't be saved on this platform).
+#[cfg(not(unix))]
+struct TermMode;
+
+#[cfg(not(unix))]
+impl TermMode {
+    fn save() -> Option<Self> {
+        None
+    }
+
+    fn restore(&self) {}
+}
@@ -0,0 +1,63 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    color::ColorPalette,
+    config::{Config, MirrorPair, Surprise},
+    theme::Theme,
+};
+use clap::ValueEnum;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// Randomize the aspects of the style (all of them if none are listed). Options set explicitly are
+/// kept. With --seed, the same style is picked each time.
+pub fn apply(cfg: &mut Config, aspects: &[Surprise]) {
+    let mut rng = cfg
+        .seed
+        .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let enabled = |a| aspects.is_empty() || aspects.contains(&a);
+
+    // The theme goes first, so the other aspects are randomized on top of it
+    if enabled(Surprise::Theme) && cfg.theme.is_none() && cfg.schedule.is_none() {
+        let themes = Theme::builtin();
+        let theme = themes.choose(&mut rng).unwrap();
+
+        theme.apply(cfg);
+        cfg.theme = Some(theme.name.clone());
+    }
+
+    if enabled(Surprise::Palette) && !cfg.is_explicit("palette") {
+        cfg.palette = *ColorPalette::value_variants()[1..]
+            .choose(&mut rng)
+            .unwrap();
+    }
+
+    let custom_pieces = cfg.is_explicit("custom-piece-set") || cfg.piece_set_file.is_some();
+
+    if enabled(Surprise::PieceSet) && !cfg.is_explicit("piece_set") && !custom_pieces {
+        cfg.piece_set = rng.gen_range(0..=6);
+    }
+
+    if enabled(Surprise::Symmetry) && !cfg.is_explicit("mirror_pair") {
+        cfg.mirror_pair = [None, Some(MirrorPair::Point), Some(MirrorPair::Offset)]
+            .choose(&mut rng)
+            .copied()
+            .unwrap();
+    }
+
+    if enabled(Surprise::Effects) {
+        if !cfg.is_explicit("gradient") {
+            cfg.gradient = rng.gen_bool(0.5);
+        }
+
+        if !cfg.is_explicit("z_depth") {
+            cfg.z_depth = rng.gen_bool(0.5);
+        }
+    }
+
+    // The gradient needs colors, which may have been disabled explicitly
+    if cfg.palette == ColorPalette::None && !cfg.is_explicit("gradient") {
+        cfg.gradient = false;
+    }
+}
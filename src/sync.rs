@@ -0,0 +1,195 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::events::{Event, EventSender};
+use eyre::{Result, WrapErr};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How long to wait for a slow instance before disconnecting it.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of messages waiting to be sent to an instance. An instance which falls behind by more
+/// is disconnected, so it doesn't hold up the others.
+const CLIENT_QUEUE: usize = 1024;
+
+/// Message sent by the sync server to the connected instances.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SyncMessage {
+    /// Start the simulation again on a clean screen with specified seed.
+    Reset(u64),
+    /// Advance the simulation by one step.
+    Step,
+}
+
+impl SyncMessage {
+    /// Encode the message as a line of text.
+    fn encode(self) -> String {
+        match self {
+            Self::Reset(seed) => format!("reset {seed}\n"),
+            Self::Step => "step\n".to_string(),
+        }
+    }
+
+    /// Decode the message from a line of text.
+    fn decode(line: &str) -> Option<Self> {
+        match line.trim().split_once(' ') {
+            Some(("reset", seed)) => seed.parse().ok().map(Self::Reset),
+            None if line.trim() == "step" => Some(Self::Step),
+            _ => None,
+        }
+    }
+}
+
+/// Broadcasts the simulation steps to other instances.
+pub struct SyncServer {
+    /// Queues of the messages for the connected instances. Each instance is written to by its own
+    /// thread, so a slow one doesn't hold up the simulation.
+    clients: Arc<Mutex<Vec<SyncSender<SyncMessage>>>>,
+    /// Notifications about newly connected instances.
+    joined: Receiver<()>,
+}
+
+impl SyncServer {
+    /// Start accepting instances on the address.
+    pub fn bind(addr: &str) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).wrap_err_with(|| format!("cannot listen on {addr}"))?;
+        let clients = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel();
+
+        let accepted = Arc::clone(&clients);
+
+        thread::spawn(move || {
+            for stream in listener.incoming().map_while(|s| s.ok()) {
+                let _ = stream.set_nodelay(true);
+                let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+
+                if let Ok(mut clients) = accepted.lock() {
+                    clients.push(write_in_background(stream));
+                }
+
+                if tx.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            clients,
+            joined: rx,
+        })
+    }
+
+    /// Check whether new instances have connected since the last call.
+    pub fn has_new_clients(&self) -> bool {
+        let mut joined = false;
+
+        while self.joined.try_recv().is_ok() {
+            joined = true;
+        }
+
+        joined
+    }
+
+    /// Send the message to all instances. Instances which can't receive it or are too far behind
+    /// are disconnected.
+    pub fn broadcast(&self, msg: SyncMessage) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|c| c.try_send(msg).is_ok());
+        }
+    }
+}
+
+/// Start writing the messages from the returned queue to the instance. When the instance can't
+/// receive them, the queue is closed. When the queue is dropped, the connection is closed.
+fn write_in_background(mut stream: TcpStream) -> SyncSender<SyncMessage> {
+    let (tx, rx) = mpsc::sync_channel::<SyncMessage>(CLIENT_QUEUE);
+
+    thread::spawn(move || {
+        for msg in rx {
+            if stream.write_all(msg.encode().as_bytes()).is_err() {
+                return;
+            }
+        }
+    });
+
+    tx
+}
+
+/// Connect to the sync server at the address and deliver the received simulation steps to the
+/// main loop. When the connection is lost, [`Event::SyncLost`] is delivered.
+pub fn follow(addr: &str, events: EventSender) -> Result<()> {
+    let stream = TcpStream::connect(addr).wrap_err_with(|| format!("cannot connect to {addr}"))?;
+
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(|l| l.ok()) {
+            if let Some(msg) = SyncMessage::decode(&line) {
+                if !events.send(Event::Sync(msg)) {
+                    return;
+                }
+            }
+        }
+
+        events.send(Event::SyncLost);
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_round_trip() {
+        for msg in [
+            SyncMessage::Step,
+            SyncMessage::Reset(0),
+            SyncMessage::Reset(u64::MAX),
+        ] {
+            assert_eq!(SyncMessage::decode(&msg.encode()), Some(msg));
+        }
+    }
+
+    #[test]
+    fn malformed_messages() {
+        for line in [
+            "",
+            "step 1",
+            "reset",
+            "reset ",
+            "reset -1",
+            "reset x",
+            "reset 18446744073709551616",
+            "jump 3",
+        ] {
+            assert_eq!(SyncMessage::decode(line), None, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn slow_client_is_dropped() {
+        let server = SyncServer::bind("127.0.0.1:0").unwrap();
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+
+        server.clients.lock().unwrap().push(tx);
+
+        // Nobody takes the messages from the queue
+        for _ in 0..=CLIENT_QUEUE {
+            server.broadcast(SyncMessage::Step);
+        }
+
+        assert!(server.clients.lock().unwrap().is_empty());
+        drop(rx);
+    }
+}
@@ -2,25 +2,57 @@
 //
 // This file is licensed under the MIT License (see LICENSE.md).
 
-use crate::canvas::Canvas;
+use crate::{
+    canvas::Canvas, config::Geometry, latency::LatencyTerminal, multiplexer::Multiplexer,
+    plane_2d::Point,
+};
 use eyre::{Result, WrapErr};
+use std::{
+    fmt::Display,
+    io::{stdout, Write},
+    time::Duration,
+};
 use termwiz::{
-    color::ColorAttribute,
-    surface::{Change, CursorVisibility},
-    terminal::{buffered::BufferedTerminal, SystemTerminal, Terminal},
+    cell::{Cell, CellAttributes},
+    color::{ColorAttribute, SrgbaTuple},
+    escape::{
+        csi::{Window, CSI},
+        osc::Selection,
+        OperatingSystemCommand,
+    },
+    surface::{Change, CursorVisibility, Position},
+    terminal::{buffered::BufferedTerminal, Terminal, TerminalWaker},
 };
 
+/// Estimated number of bytes of escape sequences needed to draw a cell besides its text: moving the
+/// cursor and setting the attributes.
+const CELL_ESCAPE_BYTES: usize = 32;
+
+/// How long to wait for the terminal to respond to a query.
+#[cfg(unix)]
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
 /// Represents a terminal screen.
-pub struct TerminalScreen {
+pub struct TerminalScreen<T: Terminal = LatencyTerminal> {
     /// Associated terminal.
-    term: BufferedTerminal<SystemTerminal>,
+    term: BufferedTerminal<T>,
     /// Size.
     size: (usize, usize),
+    /// Region of the screen to draw in. If not set, the whole screen is used.
+    viewport: Option<Geometry>,
+    /// Maximal number of bytes written per render when copying changed cells. If not set, all
+    /// changes are written at once.
+    byte_budget: Option<usize>,
+    /// Whether only ASCII characters without colors or text attributes are drawn, and the
+    /// alternate screen isn't used (see --safe-mode).
+    plain: bool,
+    /// Multiplexer the terminal runs inside, if any.
+    multiplexer: Option<Multiplexer>,
 }
 
-impl TerminalScreen {
+impl<T: Terminal> TerminalScreen<T> {
     /// Create a new `TerminalScreen` instance.
-    pub fn new(mut term: SystemTerminal) -> Result<Self> {
+    pub fn new(mut term: T) -> Result<Self> {
         let size = term
             .get_screen_size()
             .wrap_err("failed to query the size of the terminal")
@@ -29,6 +61,10 @@ impl TerminalScreen {
         Ok(Self {
             term: BufferedTerminal::new(term)?,
             size,
+            viewport: None,
+            byte_budget: None,
+            plain: false,
+            multiplexer: Multiplexer::detect(),
         })
     }
 
@@ -55,6 +91,11 @@ impl TerminalScreen {
             .set_cooked_mode()
             .wrap_err("failed to unset raw mode")?;
         self.leave_alternate_screen()?;
+        // Make sure the terminal is restored before anything else is printed
+        self.term
+            .terminal()
+            .flush()
+            .wrap_err("failed to flush the terminal")?;
 
         Ok(())
     }
@@ -65,16 +106,179 @@ impl TerminalScreen {
             .add_change(Change::ClearScreen(ColorAttribute::Default));
     }
 
+    /// Set the title of the terminal window.
+    pub fn set_title(&mut self, title: &str) {
+        self.term.add_change(Change::Title(title.to_string()));
+    }
+
+    /// Save the title of the terminal window, so it can be restored by [`Self::pop_title`].
+    pub fn push_title(&mut self) -> Result<()> {
+        self.write_escape(CSI::Window(Box::new(Window::PushIconAndWindowTitle)))
+    }
+
+    /// Restore the title of the terminal window saved by [`Self::push_title`].
+    pub fn pop_title(&mut self) -> Result<()> {
+        self.write_escape(CSI::Window(Box::new(Window::PopIconAndWindowTitle)))
+    }
+
+    /// Copy the text to the system clipboard (OSC 52). Works over SSH and inside tmux and screen
+    /// too, if the terminal allows it.
+    pub fn copy_to_clipboard(&mut self, text: &str) -> Result<()> {
+        let seq = OperatingSystemCommand::SetSelection(Selection::CLIPBOARD, text.to_string())
+            .to_string();
+
+        match self.multiplexer {
+            Some(m) => self.write_escape(m.passthrough(&seq)),
+            None => self.write_escape(seq),
+        }
+    }
+
+    /// Write the escape sequence to the terminal after everything rendered so far.
+    fn write_escape(&mut self, seq: impl Display) -> Result<()> {
+        self.term
+            .terminal()
+            .flush()
+            .wrap_err("failed to flush the terminal")?;
+
+        let mut out = stdout();
+        write!(out, "{seq}").wrap_err("failed to write to the terminal")?;
+        out.flush().wrap_err("failed to flush the terminal")
+    }
+
     /// Resize terminal screen buffer to specified size.
     pub fn resize(&mut self, size: (usize, usize)) {
         self.size = size;
         self.term.resize(size.0, size.1);
     }
 
+    /// Restrict drawing to the region of the screen. Positions of canvases become relative to it.
+    pub fn set_viewport(&mut self, viewport: Geometry) {
+        self.viewport = Some(viewport);
+    }
+
+    /// Limit the number of bytes of changed cells written per render (see
+    /// [`Self::copy_canvas_changes`]).
+    pub fn set_byte_budget(&mut self, bytes: usize) {
+        self.byte_budget = Some(bytes);
+    }
+
+    /// Draw only ASCII characters without colors or text attributes and don't switch to the
+    /// alternate screen, for terminals which can't do more (see --safe-mode).
+    pub fn set_plain(&mut self) {
+        self.plain = true;
+    }
+
     /// Copy canvas buffer to the terminal screen buffer.
     pub fn copy_canvas(&mut self, canv: &Canvas) {
-        self.term
-            .draw_from_screen(canv.surface(), canv.pos.x as usize, canv.pos.y as usize);
+        // The cells have to be stripped one by one
+        if self.plain {
+            let cells = self.differing_cells(canv);
+            self.put_cells(canv, cells);
+
+            return;
+        }
+
+        let origin = self.viewport.map_or(Point { x: 0, y: 0 }, |v| v.pos);
+
+        self.term.draw_from_screen(
+            canv.surface(),
+            (origin.x + canv.pos.x) as usize,
+            (origin.y + canv.pos.y) as usize,
+        );
+    }
+
+    /// Copy only the cells of the canvas changed since the last copy to the terminal screen buffer,
+    /// which is much cheaper than comparing the whole canvas with it (see [`Self::copy_canvas`]).
+    /// With `full`, the whole canvas is copied. Cells over the byte budget are left for the next
+    /// copy.
+    pub fn copy_canvas_changes(&mut self, canv: &mut Canvas, full: bool) {
+        let cells = canv.take_changed_cells().filter(|_| !full);
+
+        let mut cells = match cells {
+            Some(c) => c,
+            // Copying the whole canvas would write all of it at once
+            None if self.byte_budget.is_some() => self.differing_cells(canv),
+            None => {
+                self.copy_canvas(canv);
+
+                return;
+            }
+        };
+
+        if let Some(budget) = self.byte_budget {
+            let mut bytes = 0;
+            let n = cells
+                .iter()
+                .take_while(|(_, cell)| {
+                    bytes += CELL_ESCAPE_BYTES + cell.str().len();
+
+                    bytes <= budget
+                })
+                .count();
+
+            // Always make progress, even if a single cell is over the budget
+            canv.defer_changes(cells.drain(n.max(1).min(cells.len())..).map(|(p, _)| p));
+        }
+
+        self.put_cells(canv, cells);
+    }
+
+    /// Put the cells of the canvas (with their positions in it) into the terminal screen buffer.
+    fn put_cells(&mut self, canv: &Canvas, cells: Vec<(Point, Cell)>) {
+        let origin = self.viewport.map_or(Point { x: 0, y: 0 }, |v| v.pos);
+        let mut changes = vec![];
+
+        for (p, mut cell) in cells {
+            if self.plain {
+                cell = plain_cell(&cell);
+            }
+
+            changes.push(Change::CursorPosition {
+                x: Position::Absolute((origin.x + canv.pos.x + p.x) as usize),
+                y: Position::Absolute((origin.y + canv.pos.y + p.y) as usize),
+            });
+            changes.push(Change::AllAttributes(cell.attrs().clone()));
+            changes.push(Change::Text(cell.str().to_string()));
+        }
+
+        self.term.add_changes(changes);
+    }
+
+    /// Find the cells of the canvas which differ from the terminal screen buffer (with their
+    /// positions in the canvas).
+    fn differing_cells(&self, canv: &Canvas) -> Vec<(Point, Cell)> {
+        let origin = self.viewport.map_or(Point { x: 0, y: 0 }, |v| v.pos);
+        let term_lines = self.term.screen_lines();
+        let mut cells = vec![];
+
+        for (y, line) in canv.surface().screen_lines().iter().enumerate() {
+            let term_y = origin.y + canv.pos.y + y as isize;
+            let Some(term_line) = usize::try_from(term_y).ok().and_then(|y| term_lines.get(y))
+            else {
+                continue;
+            };
+
+            for cell in line.visible_cells() {
+                let x = cell.cell_index() as isize;
+                let term_x = (origin.x + canv.pos.x + x) as usize;
+                let mut cell = cell.as_cell();
+
+                if self.plain {
+                    cell = plain_cell(&cell);
+                }
+
+                if term_line
+                    .get_cell(term_x)
+                    .is_some_and(|c| c.str() == cell.str() && c.attrs() == cell.attrs())
+                {
+                    continue;
+                }
+
+                cells.push((Point { x, y: y as isize }, cell));
+            }
+        }
+
+        cells
     }
 
     /// Renders all changes since the last render.
@@ -88,6 +292,12 @@ impl TerminalScreen {
     /// just clear the terminal screen.
     #[cfg(feature = "alternate-screen")]
     pub fn enter_alternate_screen(&mut self) -> Result<()> {
+        if self.plain {
+            self.clear();
+
+            return Ok(());
+        }
+
         self.term
             .terminal()
             .enter_alternate_screen()
@@ -100,6 +310,12 @@ impl TerminalScreen {
     /// just clear the terminal screen.
     #[cfg(feature = "alternate-screen")]
     pub fn leave_alternate_screen(&mut self) -> Result<()> {
+        if self.plain {
+            self.clear();
+
+            return Ok(());
+        }
+
         self.term
             .terminal()
             .exit_alternate_screen()
@@ -122,13 +338,144 @@ impl TerminalScreen {
         Ok(())
     }
 
+    /// Ask the terminal for its background color (OSC 11). The terminal must be in raw mode.
+    #[cfg(unix)]
+    pub fn query_bg_color(&mut self) -> Option<SrgbaTuple> {
+        use std::{
+            fs::OpenOptions,
+            io::{Read, Write},
+        };
+
+        let mut tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .ok()?;
+
+        // Every terminal answers the primary device attributes request (ESC [ c), so its response
+        // marks the end even if OSC 11 isn't supported.
+        tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+        tty.flush().ok()?;
+
+        let mut resp = vec![];
+        let mut byte = [0u8];
+
+        while wait_readable(&tty, QUERY_TIMEOUT) && tty.read(&mut byte).ok()? == 1 {
+            resp.push(byte[0]);
+
+            if byte[0] == b'c' && resp.windows(3).any(|w| w == b"\x1b[?") {
+                break;
+            }
+        }
+
+        parse_osc_color(&String::from_utf8_lossy(&resp))
+    }
+
+    #[cfg(not(unix))]
+    pub fn query_bg_color(&mut self) -> Option<SrgbaTuple> {
+        None
+    }
+
+    /// Retrieve a handle which wakes up the terminal when it's waiting for input.
+    pub fn waker(&mut self) -> TerminalWaker {
+        self.term.terminal().waker()
+    }
+
     /// Retrieve reference the associated terminal.
-    pub fn terminal(&mut self) -> &mut BufferedTerminal<SystemTerminal> {
+    pub fn terminal(&mut self) -> &mut BufferedTerminal<T> {
         &mut self.term
     }
 
-    /// Retrieve the size of the screen.
+    /// Retrieve the size of the drawing area (the viewport or the whole screen).
     pub fn size(&self) -> (usize, usize) {
-        self.size
+        self.viewport.map_or(self.size, |v| v.size)
+    }
+}
+
+/// Wait until there is something to read from the file or the timeout expires.
+#[cfg(unix)]
+fn wait_readable(f: &std::fs::File, timeout: Duration) -> bool {
+    use std::os::fd::AsRawFd;
+
+    let mut fds = libc::pollfd {
+        fd: f.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    // SAFETY: `fds` points to exactly one valid `pollfd`.
+    unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+/// Parse a color from an OSC response like `ESC ] 11 ; rgb:RRRR/GGGG/BBBB ESC \`.
+#[cfg(unix)]
+fn parse_osc_color(resp: &str) -> Option<SrgbaTuple> {
+    let rgb = &resp[resp.find("rgb:")? + 4..];
+    let end = rgb
+        .find(|c: char| !c.is_ascii_hexdigit() && c != '/')
+        .unwrap_or(rgb.len());
+
+    let mut channels = rgb[..end].split('/').map(|c| {
+        if c.is_empty() || c.len() > 4 {
+            return None;
+        }
+
+        let max = (1u32 << (4 * c.len())) - 1;
+
+        u32::from_str_radix(c, 16)
+            .ok()
+            .map(|v| v as f32 / max as f32)
+    });
+
+    Some(SrgbaTuple(
+        channels.next()??,
+        channels.next()??,
+        channels.next()??,
+        1.0,
+    ))
+}
+
+/// Strip the colors and text attributes of the cell. Characters other than printable ASCII are
+/// replaced with `?`.
+fn plain_cell(cell: &Cell) -> Cell {
+    let c = match cell.str().chars().next() {
+        Some(c) if c == ' ' || c.is_ascii_graphic() => c,
+        _ => '?',
+    };
+
+    Cell::new(c, CellAttributes::default())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc_color() {
+        assert_eq!(
+            parse_osc_color("\x1b]11;rgb:ffff/0000/8080\x1b\\"),
+            Some(SrgbaTuple(1.0, 0.0, 0x8080 as f32 / 0xffff as f32, 1.0))
+        );
+        // Terminals may answer with fewer digits and end with BEL
+        assert_eq!(
+            parse_osc_color("\x1b]11;rgb:f/00/fff\x07"),
+            Some(SrgbaTuple(1.0, 0.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn malformed_osc_color() {
+        for resp in [
+            "",
+            "\x1b]11;\x1b\\",
+            "\x1b]11;rgb:\x1b\\",
+            "\x1b]11;rgb:ffff/0000\x1b\\",
+            "\x1b]11;rgb:ffff//0000\x1b\\",
+            "\x1b]11;rgb:fffff/0/0\x1b\\",
+            "\x1b]11;rgb:gg/00/00\x1b\\",
+            "\x1b]11;rgb:ff",
+        ] {
+            assert_eq!(parse_osc_color(resp), None, "{resp:?}");
+        }
     }
 }
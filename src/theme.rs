@@ -0,0 +1,344 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas,
+    color::{ColorPalette, ColorPool},
+    config::Config,
+    paths::config_dir,
+    piece_set::default_pieces,
+    plane_2d::Point,
+    preview::Preview,
+    terminal::TerminalScreen,
+};
+use clap::ValueEnum;
+use eyre::{bail, eyre, Result, WrapErr};
+use hex_color::HexColor;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use termwiz::{
+    color::{ColorAttribute, SrgbaTuple},
+    input::{InputEvent, KeyCode, KeyEvent, Modifiers},
+    terminal::Terminal,
+};
+
+/// Extension of theme files.
+const THEME_EXT: &str = "theme";
+
+/// Delay between frames of the preview.
+const FRAME_DELAY: Duration = Duration::from_millis(50);
+
+/// Width of the list of themes.
+const LIST_WIDTH: usize = 20;
+
+/// Gradient step used in the preview.
+const PREVIEW_GRADIENT_STEP: f32 = 0.005;
+
+/// Named combination of options defining the look of the pipes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Name of the theme.
+    pub name: String,
+    /// Set of colors used for coloring each pipe.
+    pub palette: ColorPalette,
+    /// ID of the default piece set.
+    pub piece_set: i64,
+    /// Enable gradient.
+    pub gradient: bool,
+    /// Color of the background.
+    pub bg_color: Option<String>,
+}
+
+impl Theme {
+    /// Create a `Theme`.
+    fn new(
+        name: &str,
+        palette: ColorPalette,
+        piece_set: i64,
+        gradient: bool,
+        bg_color: Option<&str>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            palette,
+            piece_set,
+            gradient,
+            bg_color: bg_color.map(|c| c.to_string()),
+        }
+    }
+
+    /// Retrieve all built-in themes.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new("classic", ColorPalette::BaseColors, 6, false, None),
+            Self::new("thin", ColorPalette::BaseColors, 3, false, None),
+            Self::new("rounded", ColorPalette::Rgb, 4, false, None),
+            Self::new("double-gradient", ColorPalette::Rgb, 5, true, None),
+            Self::new("midnight", ColorPalette::Rgb, 4, true, Some("#0b1026")),
+            Self::new("paper", ColorPalette::BaseColors, 3, false, Some("#f4f1e8")),
+            Self::new("dots", ColorPalette::Rgb, 2, false, None),
+            Self::new("ascii", ColorPalette::None, 0, false, None),
+        ]
+    }
+
+    /// Find a theme by its name: first among the built-in themes, then in the themes directory of
+    /// the config directory. A path to a theme file is accepted too.
+    pub fn find(name: &str) -> Result<Self> {
+        if let Some(t) = Self::builtin().into_iter().find(|t| t.name == name) {
+            return Ok(t);
+        }
+
+        let path = if name.contains(std::path::MAIN_SEPARATOR) || name.ends_with(THEME_EXT) {
+            PathBuf::from(name)
+        } else {
+            themes_dir()
+                .ok_or_else(|| eyre!("unknown theme `{name}`"))?
+                .join(format!("{name}.{THEME_EXT}"))
+        };
+
+        if !path.exists() {
+            bail!("unknown theme `{name}` (see `rxpipes themes --list`)");
+        }
+
+        Self::load(&path)
+    }
+
+    /// Load a theme from a file.
+    ///
+    /// The file consists of `key = value` lines, lines starting with `#` are comments. The keys are
+    /// `palette`, `piece-set`, `gradient` and `bg-color` with the same values as the options.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("cannot read the theme file {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let mut theme = Self::new(&name, ColorPalette::default(), 6, false, None);
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: &str| eyre!("{}:{}: {msg}", path.display(), i + 1);
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| err("expected `key = value`"))?;
+
+            match key {
+                "palette" => {
+                    theme.palette = ColorPalette::from_str(value, true)
+                        .map_err(|_| err("expected none, base-colors or rgb"))?;
+                }
+                "piece-set" => {
+                    theme.piece_set = value
+                        .parse()
+                        .ok()
+                        .filter(|id| (0..=6).contains(id))
+                        .ok_or_else(|| err("expected a piece set ID (0 - 6)"))?;
+                }
+                "gradient" => {
+                    theme.gradient = value.parse().map_err(|_| err("expected true or false"))?;
+                }
+                "bg-color" => {
+                    HexColor::parse_rgb(value).map_err(|_| err("expected a color like #rrggbb"))?;
+                    theme.bg_color = Some(value.to_string());
+                }
+                _ => return Err(err(&format!("unknown key `{key}`"))),
+            }
+        }
+
+        Ok(theme)
+    }
+
+    /// Write the theme to the themes directory of the config directory. Returns the path of the
+    /// written file.
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = themes_dir().ok_or_else(|| eyre!("cannot find the config directory"))?;
+
+        fs::create_dir_all(&dir).wrap_err_with(|| format!("cannot create {}", dir.display()))?;
+
+        let path = dir.join(format!("{}.{THEME_EXT}", self.name));
+        let palette = self
+            .palette
+            .to_possible_value()
+            .map_or_else(String::new, |v| v.get_name().to_string());
+        let mut text = format!(
+            "# rxpipes theme\npalette = {palette}\npiece-set = {}\ngradient = {}\n",
+            self.piece_set, self.gradient
+        );
+
+        if let Some(c) = &self.bg_color {
+            text.push_str(&format!("bg-color = {c}\n"));
+        }
+
+        fs::write(&path, text).wrap_err_with(|| format!("cannot write {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// Apply the theme to the configuration. Options set explicitly by the user take precedence.
+    pub fn apply(&self, cfg: &mut Config) {
+        if !cfg.is_explicit("palette") {
+            cfg.palette = self.palette;
+        }
+
+        if !cfg.is_explicit("piece_set") {
+            cfg.piece_set = self.piece_set;
+        }
+
+        if !cfg.is_explicit("gradient") {
+            cfg.gradient = self.gradient;
+        }
+
+        if !cfg.is_explicit("bg_color") {
+            cfg.bg_color.clone_from(&self.bg_color);
+        }
+    }
+
+    /// Set up the preview to draw in the style of the theme.
+    fn style_preview(&self, preview: &mut Preview) {
+        let bg = self
+            .bg_color
+            .as_ref()
+            .and_then(|c| HexColor::parse_rgb(c).ok())
+            .map_or(ColorAttribute::Default, |hc| {
+                ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple(
+                    hc.r as f32 / 255.0,
+                    hc.g as f32 / 255.0,
+                    hc.b as f32 / 255.0,
+                    1.0,
+                ))
+            });
+
+        preview.set_style(
+            default_pieces(self.piece_set as usize),
+            ColorPool {
+                palette: self.palette,
+                ..Default::default()
+            },
+            self.gradient.then_some(PREVIEW_GRADIENT_STEP),
+            bg,
+        );
+    }
+}
+
+/// Retrieve the directory with user themes.
+fn themes_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("themes"))
+}
+
+/// Print the names of the built-in and user themes.
+pub fn list() {
+    for t in Theme::builtin() {
+        println!("{}", t.name);
+    }
+
+    let Some(entries) = themes_dir().and_then(|d| fs::read_dir(d).ok()) else {
+        return;
+    };
+
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.extension().is_some_and(|e| e == THEME_EXT) {
+            if let Some(stem) = path.file_stem() {
+                println!("{} (user)", stem.to_string_lossy());
+            }
+        }
+    }
+}
+
+/// Run the interactive theme browser: a list of the built-in themes next to a live preview of the
+/// selected one.
+pub fn run(term_scr: &mut TerminalScreen) -> Result<()> {
+    let themes = Theme::builtin();
+    let mut selected = 0;
+    let mut status = String::new();
+
+    let scr_size = term_scr.size();
+    let mut ui = Canvas::new(Point { x: 0, y: 0 }, scr_size);
+    let mut preview = Preview::new(
+        Point {
+            x: LIST_WIDTH as isize + 1,
+            y: 1,
+        },
+        (
+            scr_size.0.saturating_sub(LIST_WIDTH + 2),
+            scr_size.1.saturating_sub(3),
+        ),
+        default_pieces(6),
+    );
+
+    themes[selected].style_preview(&mut preview);
+
+    loop {
+        ui.clear();
+
+        for (i, t) in themes.iter().enumerate() {
+            let marker = if i == selected { '>' } else { ' ' };
+
+            ui.move_to(Point {
+                x: 1,
+                y: i as isize + 1,
+            });
+            ui.put_str(format!("{marker} {}", t.name));
+        }
+
+        ui.move_to(Point {
+            x: 1,
+            y: scr_size.1 as isize - 1,
+        });
+
+        if status.is_empty() {
+            ui.put_str("Up/Down: select  w: write to the config directory  q: quit");
+        } else {
+            ui.put_str(&status);
+        }
+
+        preview.step();
+
+        term_scr.copy_canvas(&ui);
+        term_scr.copy_canvas(preview.canvas());
+        term_scr.render()?;
+
+        let event = term_scr
+            .terminal()
+            .terminal()
+            .poll_input(Some(FRAME_DELAY))
+            .wrap_err("cannot read incoming events")?;
+
+        let Some(InputEvent::Key(KeyEvent {
+            key,
+            modifiers: Modifiers::NONE,
+        })) = event
+        else {
+            continue;
+        };
+
+        match key {
+            KeyCode::Escape | KeyCode::Char('q') => return Ok(()),
+            KeyCode::UpArrow | KeyCode::DownArrow => {
+                selected = if key == KeyCode::DownArrow {
+                    (selected + 1) % themes.len()
+                } else {
+                    (selected + themes.len() - 1) % themes.len()
+                };
+                status.clear();
+                themes[selected].style_preview(&mut preview);
+            }
+            KeyCode::Char('w') => {
+                status = match themes[selected].save() {
+                    Ok(path) => format!("Written to {}", path.display()),
+                    Err(e) => format!("Error: {e}"),
+                };
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    config::Config,
+    paths::state_dir,
+    piece_set::{glyph, piece_idx},
+    plane_2d::Direction,
+};
+use eyre::{eyre, Result, WrapErr};
+use rand::{thread_rng, Rng};
+use std::{fs, path::PathBuf};
+
+/// A single line of pipes which advances by one step on each run of rxpipes.
+///
+/// The pipe runs horizontally; when it turns, it leaves the line and enters it again at a random
+/// position.
+struct Ticker {
+    /// Glyphs of the line.
+    cells: Vec<String>,
+    /// Position of the pipe end.
+    head: usize,
+    /// Direction of the pipe.
+    dir: Direction,
+}
+
+impl Ticker {
+    /// Create an empty `Ticker`.
+    fn new(width: usize) -> Self {
+        Self {
+            cells: vec![" ".to_string(); width],
+            head: 0,
+            dir: Direction::Right,
+        }
+    }
+
+    /// Load the ticker from the string. Returns `None` if the string is malformed or the ticker
+    /// has different width.
+    fn load(s: &str, width: usize) -> Option<Self> {
+        let mut lines = s.lines();
+        let head = lines.next()?.parse().ok()?;
+        let dir = match lines.next()? {
+            "L" => Direction::Left,
+            "R" => Direction::Right,
+            _ => return None,
+        };
+        let cells: Vec<String> = lines.map(|l| l.to_string()).collect();
+
+        (cells.len() == width && head < width).then_some(Self { cells, head, dir })
+    }
+
+    /// Save the ticker to a string.
+    fn save(&self) -> String {
+        let dir = if self.dir == Direction::Left {
+            "L"
+        } else {
+            "R"
+        };
+
+        format!("{}\n{}\n{}\n", self.head, dir, self.cells.join("\n"))
+    }
+
+    /// Advance the pipe by one step.
+    fn step(&mut self, cfg: &Config) {
+        let mut rng = thread_rng();
+        let width = self.cells.len();
+
+        self.head = match self.dir {
+            Direction::Left => (self.head + width - 1) % width,
+            _ => (self.head + 1) % width,
+        };
+
+        if rng.gen_bool(cfg.turning_prob) {
+            let vertical = if rng.gen() {
+                Direction::Up
+            } else {
+                Direction::Down
+            };
+
+            // Leave the line...
+            self.cells[self.head] = glyph(cfg, piece_idx(self.dir, vertical));
+
+            // ...and come back somewhere else
+            self.head = rng.gen_range(0..width);
+            self.dir = if rng.gen() {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+            self.cells[self.head] = glyph(cfg, piece_idx(vertical, self.dir));
+        } else {
+            self.cells[self.head] = glyph(cfg, piece_idx(self.dir, self.dir));
+        }
+    }
+}
+
+/// Advance the ticker line by one step, print it and save its state.
+pub fn run(cfg: &Config) -> Result<()> {
+    let width = cfg.ticker_width.max(1);
+    let path = state_file()?;
+
+    let mut ticker = fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| Ticker::load(&s, width))
+        .unwrap_or_else(|| Ticker::new(width));
+
+    ticker.step(cfg);
+    println!("{}", ticker.cells.concat());
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).wrap_err("cannot create the state directory")?;
+    }
+
+    fs::write(&path, ticker.save()).wrap_err("cannot save the ticker state")
+}
+
+/// Retrieve the path to the file with the ticker state.
+fn state_file() -> Result<PathBuf> {
+    state_dir()
+        .map(|d| d.join("ticker"))
+        .ok_or_else(|| eyre!("cannot find the state directory (is $HOME set?)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn save_and_load() {
+        let cfg = Config::try_parse_from(["rxpipes", "--turning-prob", "0.5"]).unwrap();
+        let mut ticker = Ticker::new(20);
+
+        for _ in 0..50 {
+            ticker.step(&cfg);
+
+            let loaded = Ticker::load(&ticker.save(), 20).unwrap();
+
+            assert_eq!(loaded.cells, ticker.cells);
+            assert_eq!(loaded.head, ticker.head);
+            assert_eq!(loaded.dir, ticker.dir);
+        }
+    }
+
+    #[test]
+    fn load_malformed() {
+        for s in [
+            "",
+            "0",
+            "0\nR",
+            "x\nR\na\nb",
+            "-1\nR\na\nb",
+            "2\nR\na\nb",
+            "0\nU\na\nb",
+            "0\nR\na",
+            "0\nR\na\nb\nc",
+        ] {
+            assert!(Ticker::load(s, 2).is_none(), "{s:?}");
+        }
+
+        assert!(Ticker::load("1\nL\na\nb\n", 2).is_some());
+    }
+}
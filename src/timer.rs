@@ -0,0 +1,110 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{canvas::Canvas, plane_2d::Point};
+use std::time::{Duration, Instant};
+use termwiz::color::ColorAttribute;
+
+/// Large 3x5 font for digits.
+const DIGITS: [[&str; 5]; 10] = [
+    ["###", "# #", "# #", "# #", "###"],
+    [" # ", "## ", " # ", " # ", "###"],
+    ["###", "  #", "###", "#  ", "###"],
+    ["###", "  #", "###", "  #", "###"],
+    ["# #", "# #", "###", "  #", "  #"],
+    ["###", "#  ", "###", "  #", "###"],
+    ["###", "#  ", "###", "# #", "###"],
+    ["###", "  #", "  #", "  #", "  #"],
+    ["###", "# #", "###", "# #", "###"],
+    ["###", "# #", "###", "  #", "###"],
+];
+
+/// Large font for the colon separator.
+const COLON: [&str; 5] = [" ", "#", " ", "#", " "];
+
+/// Height of the large font.
+const FONT_HEIGHT: usize = 5;
+
+/// Large countdown shown in the top right corner of the screen.
+pub struct Countdown {
+    /// Layer with the countdown.
+    canv: Canvas,
+    /// When the countdown elapses.
+    deadline: Instant,
+    /// Currently displayed text.
+    shown: String,
+    /// Color of the background.
+    bg: ColorAttribute,
+}
+
+impl Countdown {
+    /// Create a `Countdown` which elapses after specified time.
+    pub fn new(duration: Duration, bg: ColorAttribute) -> Self {
+        Self {
+            canv: Canvas::new(Point { x: 0, y: 0 }, (0, FONT_HEIGHT)),
+            deadline: Instant::now() + duration,
+            shown: String::new(),
+            bg,
+        }
+    }
+
+    /// Redraw the countdown if the displayed time has changed and keep it in the corner of the
+    /// screen of specified size.
+    pub fn update(&mut self, scr_size: (usize, usize)) {
+        let secs = self
+            .deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs();
+        let text = format!("{:02}:{:02}", secs / 60, secs % 60);
+
+        if text != self.shown {
+            let rows: Vec<String> = (0..FONT_HEIGHT)
+                .map(|row| {
+                    text.chars()
+                        .map(|c| match c.to_digit(10) {
+                            Some(d) => DIGITS[d as usize][row],
+                            None => COLON[row],
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .replace('#', "█")
+                })
+                .collect();
+
+            let width = rows[0].chars().count();
+
+            if width != self.canv.size().0 {
+                self.canv.resize((width, FONT_HEIGHT));
+            }
+
+            self.canv.fill(self.bg);
+            self.canv.set_fg_color(ColorAttribute::PaletteIndex(15));
+
+            for (i, row) in rows.iter().enumerate() {
+                self.canv.move_to(Point {
+                    x: 0,
+                    y: i as isize,
+                });
+                self.canv.put_str(row);
+            }
+
+            self.shown = text;
+        }
+
+        self.canv.pos = Point {
+            x: scr_size.0.saturating_sub(self.canv.size().0 + 1) as isize,
+            y: 1,
+        };
+    }
+
+    /// Check whether the countdown has elapsed.
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Retrieve a reference to the countdown layer.
+    pub fn canvas(&self) -> &Canvas {
+        &self.canv
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// How often the CPU usage is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// CPU usage (share of one core) from which the main loop may be busy.
+const BUSY_USAGE: f64 = 0.9;
+
+/// How many times faster than the frame rate the main loop has to run to be busy.
+const BUSY_RATE_FACTOR: f64 = 4.0;
+
+/// Number of busy samples in a row after which the main loop is throttled.
+const BUSY_SAMPLES: u32 = 3;
+
+/// Watches the main loop for busy looping: if a bug makes it spin without waiting for the frames,
+/// it would pin a CPU core for hours on an unattended machine. The process CPU usage is sampled
+/// and if the loop keeps running far faster than the frame rate with a core fully used, each
+/// iteration is made to last at least the frame delay from then on.
+#[derive(Debug)]
+pub struct Watchdog {
+    /// CPU time used by the process at the last sample.
+    cpu_time: Duration,
+    /// Time of the last sample.
+    sampled_at: Instant,
+    /// Number of iterations of the loop since the last sample.
+    iterations: u64,
+    /// Number of busy samples in a row.
+    busy_samples: u32,
+    /// Whether the loop is throttled.
+    throttled: bool,
+    /// Time when the last iteration started.
+    iteration_at: Instant,
+    /// Descriptions of the detected busy loops.
+    log: Vec<String>,
+}
+
+impl Watchdog {
+    /// Create a `Watchdog`. Returns `None` if the CPU usage of the process can't be read on this
+    /// system.
+    pub fn new() -> Option<Self> {
+        Some(Self {
+            cpu_time: cpu_time()?,
+            sampled_at: Instant::now(),
+            iterations: 0,
+            busy_samples: 0,
+            throttled: false,
+            iteration_at: Instant::now(),
+            log: vec![],
+        })
+    }
+
+    /// Account an iteration of the loop, which should take the frame delay. While throttled,
+    /// waits for the rest of the frame delay since the previous iteration. Exempt iterations
+    /// (e.g. skipping ahead on purpose) are neither delayed nor considered busy.
+    pub fn tick(&mut self, frame_delay: Duration, exempt: bool) {
+        if self.throttled && !exempt {
+            let elapsed = self.iteration_at.elapsed();
+
+            if elapsed < frame_delay {
+                sleep(frame_delay - elapsed);
+            }
+        }
+
+        self.iteration_at = Instant::now();
+        self.iterations += 1;
+
+        let elapsed = self.sampled_at.elapsed();
+
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let Some(cpu) = cpu_time() else {
+            return;
+        };
+
+        let secs = elapsed.as_secs_f64();
+        let usage = cpu.saturating_sub(self.cpu_time).as_secs_f64() / secs;
+        let rate = self.iterations as f64 / secs;
+        let fps = 1.0 / frame_delay.as_secs_f64().max(f64::EPSILON);
+
+        self.cpu_time = cpu;
+        self.sampled_at = Instant::now();
+        self.iterations = 0;
+
+        if exempt || usage < BUSY_USAGE || rate < fps * BUSY_RATE_FACTOR {
+            self.busy_samples = 0;
+
+            return;
+        }
+
+        self.busy_samples += 1;
+
+        if self.busy_samples >= BUSY_SAMPLES && !self.throttled {
+            self.throttled = true;
+            self.log.push(format!(
+                "busy loop detected ({rate:.0} iterations/s at {:.0}% CPU), the animation has \
+                 been throttled to {fps:.0} FPS",
+                usage * 100.0,
+            ));
+        }
+    }
+
+    /// Retrieve the descriptions of the detected busy loops.
+    pub fn log(&self) -> &[String] {
+        &self.log
+    }
+}
+
+/// Retrieve the CPU time used by the process so far.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    // SAFETY: `libc::rusage` is a plain C struct, for which all-zero bytes is a valid value.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+    // SAFETY: `usage` is a valid pointer to a `libc::rusage`.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+
+    let time = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+/// Retrieve the CPU time used by the process so far.
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,319 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    canvas::Canvas,
+    color::{blend, to_srgba},
+    events::{Event, EventSender},
+    plane_2d::Point,
+    process::output_with_timeout,
+};
+use rand::{rngs::StdRng, Rng};
+use std::{fs, path::PathBuf, process::Command, thread, time::Duration};
+use termwiz::{
+    cell::{Cell, CellAttributes},
+    color::{ColorAttribute, SrgbaTuple},
+};
+
+/// How long --weather-cmd may run. A command which hangs (e.g. on a stalled connection) is killed
+/// after this and the weather is left as it is.
+const WEATHER_CMD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How strongly the colors of new pipes are tinted by the weather.
+const TINT_OPACITY: f32 = 0.35;
+
+/// Columns per rain drop spawned in each frame.
+const RAIN_SPARSENESS: usize = 12;
+
+/// Columns per snowflake spawned in each frame.
+const SNOW_SPARSENESS: usize = 40;
+
+/// Color of the rain drops.
+const RAIN_COLOR: SrgbaTuple = SrgbaTuple(0.45, 0.6, 0.9, 1.0);
+
+/// Color of the snowflakes.
+const SNOW_COLOR: SrgbaTuple = SrgbaTuple(0.95, 0.95, 1.0, 1.0);
+
+/// Current weather conditions, simplified to what can be shown.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Weather {
+    Sunny,
+    Cloudy,
+    Rain,
+    Snow,
+}
+
+impl Weather {
+    /// Parse the weather: either a bare description of the conditions (e.g. `Light rain`) or a
+    /// JSON object with a `condition` field holding it (e.g. `{"condition": "snow", ...}`).
+    pub fn parse(s: &str) -> Option<Self> {
+        let condition = json_string_field(s, "condition").unwrap_or(s);
+        let condition = condition.trim().to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| condition.contains(w));
+
+        // Storms and showers are wet above all
+        if has(&["rain", "drizzle", "shower", "thunder", "storm"]) {
+            Some(Self::Rain)
+        } else if has(&["snow", "sleet", "hail", "blizzard"]) {
+            Some(Self::Snow)
+        } else if has(&["cloud", "overcast", "fog", "mist", "haze"]) {
+            Some(Self::Cloudy)
+        } else if has(&["sun", "clear", "fair"]) {
+            Some(Self::Sunny)
+        } else {
+            None
+        }
+    }
+
+    /// Retrieve the color which the pipes are tinted with.
+    fn tint(&self) -> SrgbaTuple {
+        match self {
+            Self::Sunny => SrgbaTuple(1.0, 0.65, 0.2, TINT_OPACITY),
+            Self::Cloudy => SrgbaTuple(0.6, 0.6, 0.65, TINT_OPACITY),
+            Self::Rain => SrgbaTuple(0.2, 0.4, 1.0, TINT_OPACITY),
+            Self::Snow => SrgbaTuple(0.85, 0.9, 1.0, TINT_OPACITY),
+        }
+    }
+}
+
+/// Retrieve the value of a string field of a JSON object. Only flat objects are understood, which
+/// is all the weather needs, so there is no need for a full JSON parser.
+fn json_string_field<'a>(json: &'a str, name: &str) -> Option<&'a str> {
+    let rest = &json[json.find(&format!("\"{name}\""))? + name.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+
+    rest.find('"').map(|end| &rest[..end])
+}
+
+/// Where the weather is read from (see --weather-cmd and --weather-file).
+#[derive(Clone, Debug)]
+pub enum WeatherSource {
+    /// Command printing the weather, run via `sh -c`.
+    Command(String),
+    /// File holding the weather.
+    File(PathBuf),
+}
+
+impl WeatherSource {
+    /// Read the current weather, if it's available and understood.
+    fn read(&self) -> Option<Weather> {
+        let text = match self {
+            Self::Command(cmd) => {
+                let out =
+                    output_with_timeout(Command::new("sh").arg("-c").arg(cmd), WEATHER_CMD_TIMEOUT)
+                        .ok()
+                        .filter(|out| !out.timed_out)?;
+
+                String::from_utf8_lossy(&out.stdout).into_owned()
+            }
+            Self::File(path) => fs::read_to_string(path).ok()?,
+        };
+
+        Weather::parse(&text)
+    }
+}
+
+/// Read the weather from the source in the background every interval and deliver it to the main
+/// loop as events. Fetching the weather (e.g. over the network) may take a while, so the animation
+/// doesn't wait for it.
+pub fn watch(source: WeatherSource, interval: Duration, events: EventSender) {
+    thread::spawn(move || loop {
+        if let Some(w) = source.read() {
+            if !events.send(Event::Weather(w)) {
+                return;
+            }
+        }
+
+        thread::sleep(interval);
+    });
+}
+
+/// Falling rain drop or snowflake.
+#[derive(Debug)]
+struct Particle {
+    pos: Point,
+    glyph: &'static str,
+    /// Whether it's a snowflake, which falls slower and drifts sideways.
+    snow: bool,
+}
+
+/// Scene of the weather mode: the weather tints the colors of new pipes, and rain or snow falls
+/// over them. Like the flow pulses, the particles are put on the canvas only while it's copied to
+/// the terminal.
+#[derive(Debug)]
+pub struct WeatherScene {
+    /// Current weather (none until it's read for the first time).
+    weather: Option<Weather>,
+    /// Falling rain drops or snowflakes.
+    particles: Vec<Particle>,
+    /// Whether the particles are shown (not with --reduced-motion).
+    particles_enabled: bool,
+    /// Number of updates so far, snowflakes fall only on every other.
+    ticks: u64,
+    /// Cells covered by the particles, with their original contents.
+    covered: Vec<(Point, Cell)>,
+    /// Random number generator placing the particles.
+    rng: StdRng,
+}
+
+impl WeatherScene {
+    /// Create a `WeatherScene`, optionally without the falling particles, placing them with the
+    /// random number generator.
+    pub fn new(particles_enabled: bool, rng: StdRng) -> Self {
+        Self {
+            weather: None,
+            particles: vec![],
+            particles_enabled,
+            ticks: 0,
+            covered: vec![],
+            rng,
+        }
+    }
+
+    /// Change the weather. The particles of the previous weather keep falling until they are off
+    /// the screen.
+    pub fn set(&mut self, weather: Weather) {
+        self.weather = Some(weather);
+    }
+
+    /// Tint the color of a new pipe with the weather. Base colors are kept as they are.
+    pub fn tint(&self, c: ColorAttribute) -> ColorAttribute {
+        match (self.weather, c) {
+            (Some(w), ColorAttribute::TrueColorWithDefaultFallback(_)) => to_srgba(c)
+                .map_or(c, |rgb| {
+                    ColorAttribute::TrueColorWithDefaultFallback(blend(w.tint(), rgb))
+                }),
+            _ => c,
+        }
+    }
+
+    /// Let the particles fall by a frame and spawn new ones at the top of the screen.
+    pub fn update(&mut self, scr_size: (usize, usize)) {
+        if !self.particles_enabled {
+            return;
+        }
+
+        self.ticks += 1;
+
+        let snow_falls = self.ticks & 1 == 0;
+
+        for p in &mut self.particles {
+            if !p.snow {
+                p.pos.y += 1;
+            } else if snow_falls {
+                p.pos.y += 1;
+                p.pos.x += self.rng.gen_range(-1..=1);
+            }
+        }
+
+        self.particles.retain(|p| {
+            p.pos.y < scr_size.1 as isize && (0..scr_size.0 as isize).contains(&p.pos.x)
+        });
+
+        let (sparseness, glyphs): (usize, &[&'static str]) = match self.weather {
+            Some(Weather::Rain) => (RAIN_SPARSENESS, &["╎"]),
+            Some(Weather::Snow) => (SNOW_SPARSENESS, &["*", "·"]),
+            _ => return,
+        };
+
+        if scr_size.0 == 0 {
+            return;
+        }
+
+        for _ in 0..(scr_size.0 / sparseness).max(1) {
+            self.particles.push(Particle {
+                pos: Point {
+                    x: self.rng.gen_range(0..scr_size.0) as isize,
+                    y: 0,
+                },
+                glyph: glyphs[self.rng.gen_range(0..glyphs.len())],
+                snow: self.weather == Some(Weather::Snow),
+            });
+        }
+    }
+
+    /// Draw the particles over the pipes.
+    pub fn apply(&mut self, canv: &mut Canvas) {
+        for p in &self.particles {
+            let Some(cell) = canv.cell(p.pos) else {
+                continue;
+            };
+
+            let color = if p.snow { SNOW_COLOR } else { RAIN_COLOR };
+
+            let mut attrs = CellAttributes::default();
+            attrs.set_foreground(ColorAttribute::TrueColorWithDefaultFallback(color));
+
+            canv.put_cell(p.pos, &Cell::new_grapheme(p.glyph, attrs, None));
+            self.covered.push((p.pos, cell));
+        }
+    }
+
+    /// Put back the original contents of the cells covered by the particles.
+    pub fn restore(&mut self, canv: &mut Canvas) {
+        // Particles may overlap, so the cells are restored in reverse
+        for (p, cell) in self.covered.drain(..).rev() {
+            canv.put_cell(p, &cell);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_description() {
+        assert_eq!(Weather::parse("Light rain"), Some(Weather::Rain));
+        assert_eq!(Weather::parse("  SNOW\n"), Some(Weather::Snow));
+        assert_eq!(Weather::parse("Overcast"), Some(Weather::Cloudy));
+        assert_eq!(Weather::parse("clear sky"), Some(Weather::Sunny));
+        // Storms are wet above all
+        assert_eq!(
+            Weather::parse("Thunderstorm with hail"),
+            Some(Weather::Rain)
+        );
+    }
+
+    #[test]
+    fn parse_json() {
+        assert_eq!(
+            Weather::parse(r#"{"temp": 3, "condition": "Snow showers"}"#),
+            Some(Weather::Rain)
+        );
+        assert_eq!(
+            Weather::parse(r#"{"condition" : "partly cloudy"}"#),
+            Some(Weather::Cloudy)
+        );
+    }
+
+    #[test]
+    fn parse_unknown() {
+        for s in ["", "   ", "tornado", "{}", r#"{"condition": 42}"#] {
+            assert_eq!(Weather::parse(s), None, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn json_field() {
+        assert_eq!(json_string_field(r#"{"a": "b"}"#, "a"), Some("b"));
+        assert_eq!(json_string_field(r#"{"a":"","c":"d"}"#, "a"), Some(""));
+        assert_eq!(json_string_field(r#"{"a":"b","c":"d"}"#, "c"), Some("d"));
+    }
+
+    #[test]
+    fn malformed_json_field() {
+        for json in [
+            "",
+            "\"a\"",
+            r#"{"a" "b"}"#,
+            r#"{"a": b}"#,
+            r#"{"a": "b"#,
+            r#"{"ab": "c"}"#,
+        ] {
+            assert_eq!(json_string_field(json, "a"), None, "{json:?}");
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2024 inunix3
+//
+// This file is licensed under the MIT License (see LICENSE.md).
+
+use crate::{
+    events::{Event, EventSender},
+    process::output_with_timeout,
+};
+use std::{process::Command, thread, time::Duration};
+
+/// How long --wrap-cmd may run. Commands which don't exit on their own (e.g. `htop`) are killed
+/// after this and the output printed so far is shown.
+const WRAP_CMD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run --wrap-cmd in the background, and again every interval if there is one, and deliver its
+/// output to the main loop as events. The command may take a while, so the animation doesn't
+/// wait for it.
+pub fn watch(cmd: String, interval: Option<Duration>, events: EventSender) {
+    thread::spawn(move || loop {
+        if !events.send(Event::WrapOutput(run(&cmd))) {
+            return;
+        }
+
+        let Some(i) = interval else {
+            return;
+        };
+
+        thread::sleep(i);
+    });
+}
+
+/// Run the command via `sh -c` and retrieve the lines of its output, with the escape sequences
+/// stripped. The command is killed if it runs for too long.
+pub fn run(cmd: &str) -> Vec<String> {
+    match output_with_timeout(Command::new("sh").arg("-c").arg(cmd), WRAP_CMD_TIMEOUT) {
+        Ok(out) => String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(strip_escapes)
+            .collect(),
+        Err(e) => vec![format!("failed to run `{cmd}`: {e}")],
+    }
+}
+
+/// Remove the escape sequences (e.g. colors) and other control characters from the line. Tabs are
+/// expanded to spaces.
+fn strip_escapes(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI ends with a letter or one of a few symbols
+                Some('[') => {
+                    chars.by_ref().find(|c| ('@'..='~').contains(c));
+                }
+                // OSC ends with BEL or ST
+                Some(']') => {
+                    chars.by_ref().find(|c| *c == '\x07' || *c == '\\');
+                }
+                _ => {}
+            },
+            '\t' => out.push_str(&" ".repeat(8 - out.chars().count() % 8)),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(strip_escapes("\x1b[1;31merror\x1b[0m: x"), "error: x");
+        assert_eq!(strip_escapes("\x1b[38;2;1;2;3mrgb"), "rgb");
+    }
+
+    #[test]
+    fn strips_titles() {
+        assert_eq!(strip_escapes("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_escapes("\x1b]0;title\x1b\\text"), "text");
+    }
+
+    #[test]
+    fn expands_tabs() {
+        assert_eq!(strip_escapes("a\tb"), "a       b");
+        assert_eq!(strip_escapes("\t"), " ".repeat(8));
+        assert_eq!(
+            strip_escapes("12345678\tx"),
+            format!("12345678{}x", " ".repeat(8))
+        );
+    }
+
+    #[test]
+    fn drops_control_characters() {
+        assert_eq!(strip_escapes("a\rb\x08c\x00"), "abc");
+    }
+
+    #[test]
+    fn malformed_escapes() {
+        // Unterminated sequences swallow the rest of the line
+        assert_eq!(strip_escapes("a\x1b[31"), "a");
+        assert_eq!(strip_escapes("a\x1b]0;title"), "a");
+        assert_eq!(strip_escapes("a\x1b"), "a");
+        // Other escapes lose only the character after ESC
+        assert_eq!(strip_escapes("a\x1bMb"), "ab");
+        assert_eq!(strip_escapes("ž\x1b[0mé"), "žé");
+    }
+
+    #[test]
+    fn runs_via_shell() {
+        assert_eq!(run("echo a; echo b"), ["a", "b"]);
+    }
+}